@@ -0,0 +1,77 @@
+//! Deserializer benchmarks, run with `cargo bench` on a nightly toolchain.
+
+#![feature(test)]
+
+extern crate serde;
+extern crate serde_hjson;
+extern crate test;
+
+use std::borrow::Cow;
+
+use serde::Deserialize;
+use serde::de::impls::IgnoredAny;
+use serde_hjson::{Deserializer, Value};
+use test::Bencher;
+
+// A document of `count` objects with nested arrays, multiline strings and comments.
+fn document(count: usize) -> String {
+    let mut doc = String::from("[\n");
+    for i in 0..count {
+        doc.push_str(&format!("  {{\n    # server {}\n    name: server-{}\n    port: {}\n    \
+                               tags: [\"a\", \"b\", \"c\"]\n    motd:\n      '''\n      hello\n      \
+                               world\n      '''\n    limits: {{ cpu: 1.5, mem: {} }}\n  }}\n", i, i, i, i * 1024));
+    }
+    doc.push_str("]\n");
+    doc
+}
+
+#[bench]
+fn bench_ignore_document(b: &mut Bencher) {
+    let doc = document(1000);
+    b.bytes = doc.len() as u64;
+    b.iter(|| {
+        let mut de = Deserializer::new(doc.bytes());
+        let _: IgnoredAny = Deserialize::deserialize(&mut de).unwrap();
+    });
+}
+
+#[bench]
+fn bench_value_document(b: &mut Bencher) {
+    let doc = document(1000);
+    b.bytes = doc.len() as u64;
+    b.iter(|| {
+        let value: Value = serde_hjson::from_str(&doc).unwrap();
+        value
+    });
+}
+
+// a quoted string of `len` bytes, long enough for the parser to hand its buffer over
+fn long_string(len: usize) -> String {
+    let mut doc = String::with_capacity(len + 2);
+    doc.push('"');
+    while doc.len() <= len {
+        doc.push_str("lorem ipsum ");
+    }
+    doc.push('"');
+    doc
+}
+
+#[bench]
+fn bench_long_quoted_string(b: &mut Bencher) {
+    let doc = long_string(1 << 20);
+    b.bytes = doc.len() as u64;
+    b.iter(|| {
+        let mut de = Deserializer::new(doc.bytes());
+        <String as Deserialize>::deserialize(&mut de).unwrap()
+    });
+}
+
+#[bench]
+fn bench_long_quoted_cow(b: &mut Bencher) {
+    let doc = long_string(1 << 20);
+    b.bytes = doc.len() as u64;
+    b.iter(|| {
+        let mut de = Deserializer::new(doc.bytes());
+        <Cow<str> as Deserialize>::deserialize(&mut de).unwrap()
+    });
+}
@@ -0,0 +1,107 @@
+//! Serializer benchmarks, run with `cargo bench` on a nightly toolchain.
+
+#![feature(test)]
+
+extern crate serde;
+extern crate serde_hjson;
+extern crate test;
+
+use serde_hjson::ser::RawStr;
+use serde_hjson::Value;
+use test::Bencher;
+
+struct Server {
+    name: String,
+    location: String,
+    port: u16,
+    tags: Vec<String>,
+}
+
+impl serde::Serialize for Server {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer {
+        let mut state = try!(serializer.serialize_struct("Server", 4));
+        try!(serializer.serialize_struct_elt(&mut state, "name", &self.name));
+        try!(serializer.serialize_struct_elt(&mut state, "location", &self.location));
+        try!(serializer.serialize_struct_elt(&mut state, "port", &self.port));
+        try!(serializer.serialize_struct_elt(&mut state, "tags", &self.tags));
+        serializer.serialize_struct_end(state)
+    }
+}
+
+// `count` servers with non-ASCII strings
+fn servers(count: usize) -> Vec<Server> {
+    (0..count).map(|i| Server {
+        name: format!("сервер-{}", i),
+        location: format!("Zürich, Straße {}", i),
+        port: (i % 65536) as u16,
+        tags: vec!["größe".to_owned(), "東京".to_owned()],
+    }).collect()
+}
+
+fn identifiers(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("identifier_{}", i)).collect()
+}
+
+#[bench]
+fn bench_plain_str(b: &mut Bencher) {
+    let names = identifiers(10000);
+    let values: Vec<&str> = names.iter().map(|s| &**s).collect();
+    b.iter(|| serde_hjson::to_vec(&values).unwrap());
+}
+
+#[bench]
+fn bench_raw_str(b: &mut Bencher) {
+    let names = identifiers(10000);
+    let values: Vec<RawStr> = names.iter().map(|s| RawStr::new(s)).collect();
+    b.iter(|| serde_hjson::to_vec(&values).unwrap());
+}
+
+#[bench]
+fn bench_to_vec_repeated(b: &mut Bencher) {
+    let names = identifiers(100);
+    b.iter(|| {
+        for _ in 0..100 {
+            test::black_box(serde_hjson::to_vec(&names).unwrap());
+        }
+    });
+}
+
+#[bench]
+fn bench_to_buf_repeated(b: &mut Bencher) {
+    let names = identifiers(100);
+    let mut buf = Vec::new();
+    b.iter(|| {
+        for _ in 0..100 {
+            serde_hjson::to_buf(&names, &mut buf).unwrap();
+            test::black_box(&buf);
+        }
+    });
+}
+
+#[bench]
+fn bench_to_vec(b: &mut Bencher) {
+    let servers = servers(10000);
+    b.iter(|| serde_hjson::to_vec(&servers).unwrap());
+}
+
+#[bench]
+fn bench_to_string(b: &mut Bencher) {
+    let servers = servers(10000);
+    b.iter(|| serde_hjson::to_string(&servers).unwrap());
+}
+
+// the UTF-8 check that `to_string` adds to `to_vec`
+#[bench]
+fn bench_utf8_check(b: &mut Bencher) {
+    let output = serde_hjson::to_vec(&servers(10000)).unwrap();
+    b.bytes = output.len() as u64;
+    b.iter(|| ::std::str::from_utf8(&output).is_ok());
+}
+
+// the same document as `bench_to_vec`, built as a `Value` first
+#[bench]
+fn bench_value_document(b: &mut Bencher) {
+    let value: Value = serde_hjson::to_value(&servers(10000));
+    b.iter(|| serde_hjson::to_vec(&value).unwrap());
+}
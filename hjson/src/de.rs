@@ -1,17 +1,39 @@
 //! Hjson Deserialization
 //!
 //! This module provides for Hjson deserialization with the type `Deserializer`.
+//!
+//! Hjson is a superset of JSON: any valid JSON document, including one that is just a scalar
+//! such as `42`, deserializes to the same value as with a JSON parser, except that numbers are
+//! read as `f64` (the JSON documents in `hjson_tests/assets/json` are checked against
+//! `serde_json`).
+//!
+//! The `Deserializer` reads its input from a byte iterator, so keys and strings are always
+//! copied out of the input: deserializing into borrowed types such as `HashMap<&str, Value>`
+//! is not supported (this also needs the borrowed deserialization of later serde versions).
+//!
+//! Keys and strings are however handed to visitors with `visit_str`, borrowing a buffer that
+//! the `Deserializer` reuses for each of them, quoted or not. A visitor that only inspects keys,
+//! e.g. the field visitor of a derived struct or one that looks keys up in a table, does not
+//! allocate for them.
 
 use std::char;
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+use std::f64;
 use std::io;
+use std::iter;
+use std::mem;
 use std::str;
 use std::marker::PhantomData;
 
 use serde::de;
 
 use super::error::{Error, ErrorCode, Result};
+use super::ser::StringStyle;
 use super::util::StringReader;
-use super::util::ParseNumber;
+use super::util::{ParseNumber, decode_base64, next_tab_stop, writes_back};
+use super::number::RAW_NUMBER_TOKEN;
+use super::value::{Map, Value, RAW_VALUE_TOKEN, STYLED_STRING_TOKEN, VALUE_TOKEN};
 
 enum State {
     Normal,
@@ -19,13 +41,66 @@ enum State {
     Keyname,
 }
 
+// Quoted string values at least this long are handed to the visitor as a `String` instead of
+// being copied out of `str_buf`, which is then allocated again. Keys are always copied, as the
+// `MapVisitor` reads them from `str_buf` after they were deserialized.
+const HAND_OVER_LEN: usize = 4096;
+
 /// A structure that deserializes Hjson into Rust values.
 pub struct Deserializer<Iter: Iterator<Item=u8>> {
     rdr: StringReader<Iter>,
     str_buf: Vec<u8>,
+    str_style: StringStyle,
+    extended_numbers: bool,
+    strict_bare_words: bool,
+    allow_single_quotes: bool,
+    allow_non_finite: bool,
+    allow_bool_words: bool,
+    allow_equals_separator: bool,
+    duplicate_keys: DuplicateKeys,
+    integral_floats: bool,
+    preserve_crlf: bool,
+    integer_target: Option<IntegerRange>,
+    number_literal: bool,
+    reading_key: bool,
+    record_spans: bool,
+    spans: BTreeMap<String, Span>,
+    path: Vec<String>,
+    trailing_space: usize,
     state: State,
 }
 
+/// Selects what happens when a key appears more than once in an object, see
+/// `Deserializer::duplicate_keys`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DuplicateKeys {
+    /// Keep the first member with the key, the values of the others are skipped.
+    FirstWins,
+    /// Pass all members on, so that a map keeps the last value. This is the default.
+    LastWins,
+    /// Fail with `ErrorCode::DuplicateKey`.
+    Error,
+}
+
+/// The byte range of a value in the input, from the offset of its first byte up to the offset
+/// after its last byte, see `Deserializer::record_spans`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    /// The offset of the first byte.
+    pub start: usize,
+    /// The offset after the last byte.
+    pub end: usize,
+}
+
+// The integer type a number is deserialized into: its name, the magnitude of its minimum and
+// its maximum.
+#[derive(Clone, Copy)]
+struct IntegerRange {
+    name: &'static str,
+    min_abs: u64,
+    max: u64,
+}
+
 macro_rules! try_or_invalid {
     ($self_:expr, $e:expr) => {
         match $e {
@@ -44,6 +119,23 @@ impl<Iter> Deserializer<Iter>
         Deserializer {
             rdr: StringReader::new(rdr),
             str_buf: Vec::with_capacity(128),
+            str_style: StringStyle::Quoted,
+            extended_numbers: false,
+            strict_bare_words: false,
+            allow_single_quotes: false,
+            allow_non_finite: false,
+            allow_bool_words: false,
+            allow_equals_separator: false,
+            duplicate_keys: DuplicateKeys::LastWins,
+            integral_floats: false,
+            preserve_crlf: false,
+            integer_target: None,
+            number_literal: false,
+            reading_key: false,
+            record_spans: false,
+            spans: BTreeMap::new(),
+            path: Vec::new(),
+            trailing_space: 0,
             state: State::Normal,
         }
     }
@@ -56,6 +148,127 @@ impl<Iter> Deserializer<Iter>
         res
     }
 
+    /// Accept hexadecimal integers (`0xFF`) and underscores between digits (`1_000`) in
+    /// numbers. These are not part of Hjson and are disabled by default.
+    #[inline]
+    pub fn extended_numbers(mut self, enabled: bool) -> Self {
+        self.extended_numbers = enabled;
+        self
+    }
+
+    /// Reject quoteless strings that contain `{`, `}`, `[`, `]`, `,`, `:` or the start of a
+    /// comment with `ErrorCode::ReservedCharInQlString`. Such a string runs on to the end of
+    /// the line, so e.g. `[a, b]` is a single string `a, b]`, which is more likely a typo than
+    /// intended. Quoted and multiline strings are not affected.
+    #[inline]
+    pub fn strict_bare_words(mut self, enabled: bool) -> Self {
+        self.strict_bare_words = enabled;
+        self
+    }
+
+    /// Accept strings and keys in single quotes, e.g. `'say "hi"'`, as in JSON5. They have
+    /// the escapes of double-quoted strings, and `\'` for a single quote. `'''` still starts a
+    /// multiline string (so `''` is an empty string only if no third quote follows). The
+    /// `Serializer` quotes strings and keys that start with a single quote, so its output
+    /// reads the same with or without this option.
+    #[inline]
+    pub fn allow_single_quotes(mut self, enabled: bool) -> Self {
+        self.allow_single_quotes = enabled;
+        self
+    }
+
+    /// Read the quoteless values `NaN`, `Infinity` and `-Infinity` as floats, as written with
+    /// `NonFiniteMode::Token`. They are strings in Hjson, so this is disabled by default.
+    #[inline]
+    pub fn allow_non_finite(mut self, enabled: bool) -> Self {
+        self.allow_non_finite = enabled;
+        self
+    }
+
+    /// Read the quoteless values `yes`, `no`, `on` and `off` as booleans, as written with
+    /// `BoolStyle::YesNo` and `BoolStyle::OnOff`. They are strings in Hjson, so this is
+    /// disabled by default.
+    #[inline]
+    pub fn allow_bool_words(mut self, enabled: bool) -> Self {
+        self.allow_bool_words = enabled;
+        self
+    }
+
+    /// Accept `=` as well as `:` between a key and its value, e.g. `port = 80`, as written by
+    /// `HjsonFormatter::equals_separator`. A key without quotes then ends at the first `=`.
+    #[inline]
+    pub fn allow_equals_separator(mut self, enabled: bool) -> Self {
+        self.allow_equals_separator = enabled;
+        self
+    }
+
+    /// Select what happens when an object has duplicate keys, e.g. `{a: 1, a: 2}`, see
+    /// `DuplicateKeys`. The policy applies to all maps and structs, not to objects read as a
+    /// sequence of pairs, which keep every member.
+    #[inline]
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    /// Read numbers written as floats into integer types if their value is an integer that
+    /// fits, e.g. `1e3` or `5.0` into a `u32`. By default these fail with
+    /// `ErrorCode::InvalidType`, like `1.5` always does.
+    #[inline]
+    pub fn integral_floats(mut self, enabled: bool) -> Self {
+        self.integral_floats = enabled;
+        self
+    }
+
+    /// Record the `Span` of every array element and object member value while parsing, see
+    /// `spans`. This helps tools that point at a value in the source, e.g. an editor that
+    /// jumps to a setting.
+    #[inline]
+    pub fn record_spans(mut self, enabled: bool) -> Self {
+        self.record_spans = enabled;
+        self
+    }
+
+    /// Returns the spans recorded with `record_spans`, keyed by the JSON pointer that finds
+    /// the value with `Value::pointer`, e.g. `/servers/0/port`. A quoteless value's span ends
+    /// before any whitespace that follows it. The root value, which spans the whole document,
+    /// and the values of skipped duplicate keys have no spans.
+    pub fn spans(&self) -> &BTreeMap<String, Span> {
+        &self.spans
+    }
+
+    /// Keep the carriage returns in multiline `'''` strings, e.g. of `\r\n` line breaks. By
+    /// default they are removed, so that the string has `\n` line breaks whether the file has
+    /// CRLF or LF line endings. The line break before the closing `'''` is never part of the
+    /// string.
+    #[inline]
+    pub fn preserve_crlf(mut self, enabled: bool) -> Self {
+        self.preserve_crlf = enabled;
+        self
+    }
+
+    /// Reject tab characters between tokens, e.g. in indentation or between a key and its
+    /// value, with `ErrorCode::TabOutsideString`, for files that must only be indented with
+    /// spaces. Tabs in strings and comments are still allowed.
+    #[inline]
+    pub fn reject_tabs(mut self, reject: bool) -> Self {
+        self.rdr.set_reject_tabs(reject);
+        self
+    }
+
+    /// Sets how many columns a tab stands for when the indentation is removed from the lines
+    /// of a multiline `'''` string: a tab advances to the next multiple of `width`. The lines
+    /// lose the whitespace up to the column of the opening `'''`. The default of 1 counts a
+    /// tab like a space, which suits text that only indents with tabs.
+    ///
+    /// Panics if `width` is 0.
+    #[inline]
+    pub fn tab_width(mut self, width: usize) -> Self {
+        assert!(width > 0, "the tab width must be at least 1");
+        self.rdr.set_tab_width(width);
+        self
+    }
+
     /// The `Deserializer::end` method should be called after a value has been fully deserialized.
     /// This allows the `Deserializer` to validate that the input stream is at the end or that it
     /// only has trailing whitespace.
@@ -88,8 +301,8 @@ impl<Iter> Deserializer<Iter>
         loop {
             let ch = try!(self.rdr.next_char_or_null());
 
-            if ch == b':' {
-              if self.str_buf.len()==0 { return Err(self.rdr.error(ErrorCode::Custom("Found ':' but no key name (for an empty key name use quotes)".to_string()))); }
+            if ch == b':' || ch == b'=' && self.allow_equals_separator {
+              if self.str_buf.len()==0 { return Err(self.rdr.error(ErrorCode::Custom(format!("Found '{}' but no key name (for an empty key name use quotes)", ch as char)))); }
               else if space.is_some() && space.unwrap() != self.str_buf.len() { return Err(self.rdr.error(ErrorCode::Custom("Found whitespace in your key name (use quotes to include)".to_string()))); }
               self.rdr.uneat_char(ch);
               let s=str::from_utf8(&self.str_buf).unwrap();
@@ -97,6 +310,7 @@ impl<Iter> Deserializer<Iter>
             }
             else if ch <= b' ' {
               if ch==0 { return Err(self.rdr.error(ErrorCode::EOFWhileParsingObject)); }
+              else if ch == b'\t' && self.rdr.reject_tabs() { return Err(self.rdr.error(ErrorCode::TabOutsideString)); }
               else if space.is_none() { space = Some(self.str_buf.len()); }
             }
             else if self.is_punctuator_char(ch) {
@@ -106,11 +320,90 @@ impl<Iter> Deserializer<Iter>
         }
     }
 
+    // checks if the value here is `null`, rather than a quoteless string that starts with it
+    // like `nullable` or `null and void`
+    fn at_null(&mut self) -> Result<bool> {
+        for (i, &ch) in b"null".iter().enumerate() {
+            if try!(self.rdr.peek_next(i)) != Some(ch) {
+                return Ok(false);
+            }
+        }
+        let mut i = 4;
+        loop {
+            match try!(self.rdr.peek_next(i)) {
+                Some(b' ') | Some(b'\t') => i += 1,
+                None | Some(b'\r') | Some(b'\n') | Some(b',') | Some(b'}') | Some(b']') | Some(b'#') => {
+                    return Ok(true);
+                }
+                Some(b'/') => {
+                    let next = try!(self.rdr.peek_next(i + 1));
+                    return Ok(next == Some(b'/') || next == Some(b'*'));
+                }
+                Some(_) => return Ok(false),
+            }
+        }
+    }
+
+    // checks if the first token here is a key followed by its separator, i.e. if the document
+    // is an object without braces; an empty document counts as one, as it is read as null
+    fn at_key(&mut self) -> Result<bool> {
+        let mut i = 0;
+        match try!(self.rdr.peek()) {
+            None => return Ok(true),
+            Some(b'"') => {
+                i = 1;
+                loop {
+                    match try!(self.rdr.peek_next(i)) {
+                        None | Some(b'\n') => return Ok(false),
+                        Some(b'"') => break,
+                        Some(b'\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+            }
+            Some(_) => {
+                while let Some(ch) = try!(self.rdr.peek_next(i)) {
+                    if ch <= b' ' || self.is_punctuator_char(ch) {
+                        break;
+                    }
+                    i += 1;
+                }
+                if i == 0 {
+                    return Ok(false);
+                }
+            }
+        }
+        loop {
+            match try!(self.rdr.peek_next(i)) {
+                Some(b' ') | Some(b'\t') => i += 1,
+                Some(b':') => return Ok(true),
+                Some(b'=') => return Ok(self.allow_equals_separator),
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    // checks if a string in single quotes starts here, rather than a multiline string
+    fn at_single_quote(&mut self) -> Result<bool> {
+        if !self.allow_single_quotes || try!(self.rdr.peek()) != Some(b'\'') {
+            return Ok(false);
+        }
+        Ok(!(try!(self.rdr.peek_next(1)) == Some(b'\'') && try!(self.rdr.peek_next(2)) == Some(b'\'')))
+    }
+
     fn parse_value<V>(&mut self, mut visitor: V) -> Result<V::Value>
         where V: de::Visitor {
+        // only the value itself may keep the literal of a number, not what it contains
+        let number_literal = mem::replace(&mut self.number_literal, false);
         try!(self.rdr.parse_whitespace());
 
         if try!(self.rdr.eof()) {
+            if let State::Root = self.state {
+                // an empty document (or one with only comments) is null
+                self.state = State::Normal;
+                return visitor.visit_unit();
+            }
             return Err(self.rdr.error(ErrorCode::EOFWhileParsingValue));
         }
 
@@ -126,6 +419,7 @@ impl<Iter> Deserializer<Iter>
             _ => {},
         }
 
+        let single_quoted = try!(self.at_single_quote());
         let value = match try!(self.rdr.peek_or_null()) {
             /*
             b'-' => {
@@ -136,9 +430,15 @@ impl<Iter> Deserializer<Iter>
                 self.parse_integer(true, visitor)
             }
             */
-            b'"' => {
+            quote @ b'"' | quote @ b'\'' if quote == b'"' || single_quoted => {
                 self.rdr.eat_char();
-                try!(self.parse_string());
+                try!(self.parse_string(quote));
+                self.str_style = StringStyle::Quoted;
+                if self.str_buf.len() >= HAND_OVER_LEN && !self.reading_key {
+                    // saves copying a long string for targets that take a `String`
+                    let buf = mem::replace(&mut self.str_buf, Vec::with_capacity(128));
+                    return visitor.visit_string(String::from_utf8(buf).unwrap());
+                }
                 let s = str::from_utf8(&self.str_buf).unwrap();
                 visitor.visit_str(s)
             }
@@ -154,28 +454,20 @@ impl<Iter> Deserializer<Iter>
                 Err(self.rdr.error(ErrorCode::ExpectedSomeValue))
             }
             _ => {
-                self.parse_tfnns(visitor)
+                self.parse_tfnns(visitor, number_literal)
             }
         };
 
         match value {
             Ok(value) => Ok(value),
-            Err(Error::Syntax(code, _, _)) => Err(self.rdr.error(code)),
+            Err(Error::Syntax(code, _, _, _)) => Err(self.rdr.error(code)),
             Err(err) => Err(err),
         }
     }
 
-    fn parse_ident(&mut self, ident: &[u8]) -> Result<()> {
-        for c in ident {
-            if Some(*c) != try!(self.rdr.next_char()) {
-                return Err(self.rdr.error(ErrorCode::ExpectedSomeIdent));
-            }
-        }
-
-        Ok(())
-    }
-
-    fn parse_tfnns<V>(&mut self, mut visitor: V) -> Result<V::Value>
+    // A number is visited as a map with a `RAW_NUMBER_TOKEN` key for its literal if
+    // `number_literal` is set and the strict literal would not be written back the same way.
+    fn parse_tfnns<V>(&mut self, mut visitor: V, number_literal: bool) -> Result<V::Value>
         where V: de::Visitor {
 
         // Hjson strings can be quoteless
@@ -189,7 +481,9 @@ impl<Iter> Deserializer<Iter>
         }
 
         loop {
-            let ch = try!(self.rdr.next_char_or_null());
+            // at the end of the input there is no character to put back
+            let next = try!(self.rdr.next_char());
+            let ch = next.unwrap_or(b'\x00');
 
             let is_eol = ch == b'\r' || ch == b'\n' || ch == b'\x00';
             let is_comment = ch == b'#' || if ch == b'/' {
@@ -199,26 +493,79 @@ impl<Iter> Deserializer<Iter>
             if is_eol || is_comment ||
                ch == b',' || ch == b'}' || ch == b']' {
 
+                if self.record_spans {
+                    // the whitespace before the end is not part of the value
+                    let text = str::from_utf8(&self.str_buf).unwrap();
+                    self.trailing_space = text.len() - text.trim_right().len();
+                }
+
+                if self.allow_non_finite {
+                    let number = match str::from_utf8(&self.str_buf).unwrap().trim() {
+                        "NaN" => Some(f64::NAN),
+                        "Infinity" => Some(f64::INFINITY),
+                        "-Infinity" => Some(f64::NEG_INFINITY),
+                        _ => None,
+                    };
+                    if let Some(v) = number {
+                        if next.is_some() { self.rdr.uneat_char(ch); }
+                        return visitor.visit_f64(v);
+                    }
+                }
+
+                if self.allow_bool_words {
+                    let boolean = match str::from_utf8(&self.str_buf).unwrap().trim() {
+                        "yes" | "on" => Some(true),
+                        "no" | "off" => Some(false),
+                        _ => None,
+                    };
+                    if let Some(v) = boolean {
+                        if next.is_some() { self.rdr.uneat_char(ch); }
+                        return visitor.visit_bool(v);
+                    }
+                }
+
                 let chf = self.str_buf[0];
                 match chf {
-                    b'f' => if str::from_utf8(&self.str_buf).unwrap().trim() == "false" { self.rdr.uneat_char(ch); return visitor.visit_bool(false); },
-                    b'n' => if str::from_utf8(&self.str_buf).unwrap().trim() == "null" { self.rdr.uneat_char(ch); return visitor.visit_unit(); },
-                    b't' => if str::from_utf8(&self.str_buf).unwrap().trim() == "true" { self.rdr.uneat_char(ch); return visitor.visit_bool(true); },
+                    b'f' => if str::from_utf8(&self.str_buf).unwrap().trim() == "false" { if next.is_some() { self.rdr.uneat_char(ch); } return visitor.visit_bool(false); },
+                    b'n' => if str::from_utf8(&self.str_buf).unwrap().trim() == "null" { if next.is_some() { self.rdr.uneat_char(ch); } return visitor.visit_unit(); },
+                    b't' => if str::from_utf8(&self.str_buf).unwrap().trim() == "true" { if next.is_some() { self.rdr.uneat_char(ch); } return visitor.visit_bool(true); },
                     _ => {
                         if chf == b'-' || chf >= b'0' && chf <= b'9' {
-                            let mut pn = ParseNumber::new(self.str_buf.iter().map(|b| *b));
+                            let mut pn = ParseNumber::new(self.str_buf.iter().map(|b| *b))
+                                .extended(self.extended_numbers);
                             match pn.parse(false) {
-                                Ok(v) => { self.rdr.uneat_char(ch); return visitor.visit_f64(v); },
+                                Ok(v) => {
+                                    if next.is_some() { self.rdr.uneat_char(ch); }
+                                    if let Some(range) = self.integer_target {
+                                        return self.visit_integer(visitor, v, range);
+                                    }
+                                    let text = str::from_utf8(&self.str_buf).unwrap().trim();
+                                    // extended literals are not kept, strict Hjson could not read them back
+                                    let strict = !self.extended_numbers ||
+                                        ParseNumber::new(text.bytes()).parse(false).is_ok();
+                                    if number_literal && strict && !writes_back(text, v) {
+                                        let entry = iter::once((RAW_NUMBER_TOKEN, text.to_owned()));
+                                        return visitor.visit_map(de::value::MapDeserializer::new(entry, 1));
+                                    }
+                                    return visitor.visit_f64(v);
+                                },
                                 Err(_) => {} // not a number, continue
                             }
                         }
                     },
                 }
                 if is_eol {
+                    if self.record_spans && next.is_some() {
+                        self.trailing_space += 1;
+                    }
                     // remove any whitespace at the end (ignored in quoteless strings)
+                    self.str_style = StringStyle::Quoteless;
                     return visitor.visit_str(str::from_utf8(&self.str_buf).unwrap().trim())
                 }
             }
+            if self.strict_bare_words && (is_comment || self.is_punctuator_char(ch)) {
+                return Err(self.rdr.error(ErrorCode::ReservedCharInQlString));
+            }
             self.str_buf.push(ch);
 
             if self.str_buf == vec![b'\''; 3] {
@@ -227,6 +574,33 @@ impl<Iter> Deserializer<Iter>
         }
     }
 
+    // Visits the number in `str_buf`, which parsed as `v`, for the integer type `range`: exactly
+    // if it is written as an integer, otherwise only if it is integral and `integral_floats` is
+    // set. Numbers outside of the range of the type fail with `ErrorCode::NumberOutOfRange`.
+    fn visit_integer<V>(&mut self, mut visitor: V, v: f64, range: IntegerRange) -> Result<V::Value>
+        where V: de::Visitor {
+        let literal = str::from_utf8(&self.str_buf).unwrap().trim().to_owned();
+        let text = literal.replace('_', "");
+        let (negative, digits) = if text.starts_with('-') { (true, &text[1..]) } else { (false, &text[..]) };
+
+        // the literal is known to be a number, so parsing only fails if it exceeds a u64
+        let n = if digits.starts_with("0x") || digits.starts_with("0X") {
+            u64::from_str_radix(&digits[2..], 16).ok()
+        } else if digits.bytes().all(|ch| ch >= b'0' && ch <= b'9') {
+            digits.parse().ok()
+        } else if self.integral_floats && v.fract() == 0.0 {
+            if v.abs() < 18446744073709551616.0 { Some(v.abs() as u64) } else { None }
+        } else {
+            return Err(self.rdr.error(ErrorCode::InvalidType(de::Type::F64)));
+        };
+
+        match n {
+            Some(n) if !negative && n <= range.max => visitor.visit_u64(n),
+            Some(n) if negative && n <= range.min_abs => visitor.visit_i64((n as i64).wrapping_neg()),
+            _ => Err(self.rdr.error(ErrorCode::NumberOutOfRange(literal, range.name))),
+        }
+    }
+
     fn decode_hex_escape(&mut self) -> Result<u16> {
         let mut i = 0;
         let mut n = 0u16;
@@ -263,10 +637,17 @@ impl<Iter> Deserializer<Iter>
         }
     }
 
+    // skips whitespace up to the column `indent`, a tab that reaches past it is skipped too
     fn ml_skip_indent(&mut self, indent: usize) -> Result<()> {
-        let mut skip = indent;
-        while try!(self.ml_skip_white()) && skip > 0 {
-            skip -= 1;
+        let mut col = 0;
+        while col < indent {
+            match try!(self.rdr.peek_or_null()) {
+                b' ' => col += 1,
+                b'\r' if !self.preserve_crlf => col += 1,
+                b'\t' => col = next_tab_stop(col, self.rdr.tab_width()),
+                _ => break,
+            }
+            self.rdr.eat_char();
         }
         Ok(())
     }
@@ -279,8 +660,7 @@ impl<Iter> Deserializer<Iter>
         let mut triple = 0;
 
         // we are at ''' +1 - get indent
-        let (_, col) = self.rdr.pos();
-        let indent = col - 4;
+        let indent = self.rdr.vcol() - 3;
 
         // skip white/to (newline)
         while try!(self.ml_skip_white()) {}
@@ -297,7 +677,11 @@ impl<Iter> Deserializer<Iter>
             if ch == b'\'' {
                 triple += 1;
                 if triple == 3 {
-                    if self.str_buf.last() == Some(&b'\n') { self.str_buf.pop(); }
+                    if self.str_buf.last() == Some(&b'\n') {
+                        self.str_buf.pop();
+                        if self.str_buf.last() == Some(&b'\r') { self.str_buf.pop(); }
+                    }
+                    self.str_style = StringStyle::Multiline;
                     let res = str::from_utf8(&self.str_buf).unwrap();
                     //todo if (self.str_buf.slice(-1) === '\n') self.str_buf=self.str_buf.slice(0, -1); // remove last EOL
                     return visitor.visit_str(res)
@@ -310,13 +694,14 @@ impl<Iter> Deserializer<Iter>
                 triple -= 1;
             }
 
-            if ch != b'\r' { self.str_buf.push(ch); }
+            if ch != b'\r' || self.preserve_crlf { self.str_buf.push(ch); }
             if ch == b'\n' { try!(self.ml_skip_indent(indent)); }
         }
     }
 
 
-    fn parse_string(&mut self) -> Result<()> {
+    // parses the rest of a string that ends with `quote`
+    fn parse_string(&mut self, quote: u8) -> Result<()> {
         self.str_buf.clear();
 
         loop {
@@ -326,7 +711,7 @@ impl<Iter> Deserializer<Iter>
             };
 
             match ch {
-                b'"' => {
+                _ if ch == quote => {
                     return Ok(());
                 }
                 b'\\' => {
@@ -337,6 +722,7 @@ impl<Iter> Deserializer<Iter>
 
                     match ch {
                         b'"' => self.str_buf.push(b'"'),
+                        b'\'' if quote == b'\'' => self.str_buf.push(b'\''),
                         b'\\' => self.str_buf.push(b'\\'),
                         b'/' => self.str_buf.push(b'/'),
                         b'b' => self.str_buf.push(b'\x08'),
@@ -405,17 +791,226 @@ impl<Iter> Deserializer<Iter>
         }
     }
 
+    /// Skips over a value without handing it to a visitor.
+    fn ignore_value(&mut self) -> Result<()> {
+        try!(self.rdr.parse_whitespace());
+
+        if try!(self.rdr.eof()) {
+            return Err(self.rdr.error(ErrorCode::EOFWhileParsingValue));
+        }
+
+        let single_quoted = try!(self.at_single_quote());
+        match try!(self.rdr.peek_or_null()) {
+            quote @ b'"' | quote @ b'\'' if quote == b'"' || single_quoted => {
+                self.rdr.eat_char();
+                self.ignore_string(quote)
+            }
+            b'[' => {
+                self.rdr.eat_char();
+                self.ignore_seq()
+            }
+            b'{' => {
+                self.rdr.eat_char();
+                self.ignore_map(false)
+            }
+            b'\x00' => {
+                Err(self.rdr.error(ErrorCode::ExpectedSomeValue))
+            }
+            _ => {
+                // quoteless strings need the buffer to tell them apart from numbers and keywords
+                self.parse_tfnns(IgnoreVisitor, false)
+            }
+        }
+    }
+
+    /// Skips the rest of a string that ends with `quote`, checking its escapes without decoding
+    /// them.
+    fn ignore_string(&mut self, quote: u8) -> Result<()> {
+        loop {
+            match try!(self.rdr.next_char()) {
+                Some(ch) if ch == quote => return Ok(()),
+                Some(b'\\') => {
+                    match try!(self.rdr.next_char()) {
+                        Some(b'"') | Some(b'\\') | Some(b'/') | Some(b'b') |
+                        Some(b'f') | Some(b'n') | Some(b'r') | Some(b't') => {}
+                        Some(b'\'') if quote == b'\'' => {}
+                        Some(b'u') => { try!(self.decode_hex_escape()); }
+                        Some(_) => return Err(self.rdr.error(ErrorCode::InvalidEscape)),
+                        None => return Err(self.rdr.error(ErrorCode::EOFWhileParsingString)),
+                    }
+                }
+                Some(_) => {}
+                None => return Err(self.rdr.error(ErrorCode::EOFWhileParsingString)),
+            }
+        }
+    }
+
+    /// Skips the rest of an array.
+    fn ignore_seq(&mut self) -> Result<()> {
+        loop {
+            try!(self.rdr.parse_whitespace());
+
+            match try!(self.rdr.peek()) {
+                Some(b']') => {
+                    self.rdr.eat_char();
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => return Err(self.rdr.error(ErrorCode::EOFWhileParsingList)),
+            }
+
+            try!(self.ignore_value());
+
+            // in Hjson the comma is optional and trailing commas are allowed
+            try!(self.rdr.parse_whitespace());
+            if try!(self.rdr.peek()) == Some(b',') {
+                self.rdr.eat_char();
+            }
+        }
+    }
+
+    /// Skips the rest of an object, or the members of the root object without braces.
+    fn ignore_map(&mut self, root: bool) -> Result<()> {
+        loop {
+            try!(self.rdr.parse_whitespace());
+
+            match try!(self.rdr.peek()) {
+                Some(b'}') if !root => {
+                    self.rdr.eat_char();
+                    return Ok(());
+                }
+                None if root => return Ok(()),
+                Some(b'"') => {
+                    self.rdr.eat_char();
+                    try!(self.ignore_string(b'"').map_err(key_error));
+                }
+                Some(b'\'') if try!(self.at_single_quote()) => {
+                    self.rdr.eat_char();
+                    try!(self.ignore_string(b'\'').map_err(key_error));
+                }
+                Some(_) => {
+                    try!(self.parse_keyname(IgnoreVisitor));
+                }
+                None => return Err(self.rdr.error(ErrorCode::EOFWhileParsingObject)),
+            }
+
+            try!(self.parse_object_colon());
+            try!(self.ignore_value());
+
+            // in Hjson the comma is optional and trailing commas are allowed
+            try!(self.rdr.parse_whitespace());
+            if try!(self.rdr.peek()) == Some(b',') {
+                self.rdr.eat_char();
+            }
+        }
+    }
+
+    /// Skips over a value like `ignore_value`, returning its source text.
+    fn capture_value(&mut self) -> Result<String> {
+        try!(self.rdr.parse_whitespace());
+
+        self.rdr.start_capture();
+        let res = match self.state {
+            State::Root => {
+                self.state = State::Normal;
+                self.ignore_map(true)
+            }
+            _ => {
+                self.state = State::Normal;
+                self.ignore_value()
+            }
+        };
+        let raw = self.rdr.end_capture();
+        try!(res);
+
+        // a quoteless string runs to the end of its line
+        let raw = try!(String::from_utf8(raw));
+        let text = String::from(raw.trim_right());
+        if self.record_spans {
+            self.trailing_space = raw.len() - text.len();
+        }
+        Ok(text)
+    }
+
+    /// Deserializes an array element or an object member value, recording its span under the
+    /// pointer token `token` if spans are recorded.
+    fn deserialize_member<T>(&mut self, token: &str) -> Result<T>
+        where T: de::Deserialize {
+        if !self.record_spans {
+            return de::Deserialize::deserialize(self);
+        }
+
+        try!(self.rdr.parse_whitespace());
+        let start = self.rdr.offset();
+        self.path.push(token.replace('~', "~0").replace('/', "~1"));
+        let value = try!(de::Deserialize::deserialize(self));
+        let end = self.rdr.offset() - self.trailing_space;
+        self.trailing_space = 0;
+
+        let pointer = self.path.iter().fold(String::new(), |pointer, token| pointer + "/" + token);
+        self.path.pop();
+        self.spans.insert(pointer, Span { start: start, end: end });
+        Ok(value)
+    }
+
     fn parse_object_colon(&mut self) -> Result<()> {
         try!(self.rdr.parse_whitespace());
 
         match try!(self.rdr.next_char()) {
             Some(b':') => Ok(()),
+            Some(b'=') if self.allow_equals_separator => Ok(()),
             Some(_) => Err(self.rdr.error(ErrorCode::ExpectedColon)),
             None => Err(self.rdr.error(ErrorCode::EOFWhileParsingObject)),
         }
     }
 }
 
+// Removes the indentation that the lines after the first have in common, so that a value
+// captured from a nested position reads as if it started in column 0.
+fn dedent_lines(text: &str) -> String {
+    let is_indent: &[char] = &[' ', '\t'];
+    let width = text.lines().skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_left_matches(is_indent).len())
+        .min()
+        .unwrap_or(0);
+    if width == 0 {
+        return text.to_owned();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+            let indent = line.len() - line.trim_left_matches(is_indent).len();
+            out.push_str(&line[cmp::min(indent, width)..]);
+        } else {
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+// Deserializes an integer type, whose numbers are checked by `visit_integer`.
+macro_rules! deserialize_integer {
+    ($($name:ident => $ty:ident),*) => {
+        $(
+            #[inline]
+            fn $name<V>(&mut self, visitor: V) -> Result<V::Value>
+                where V: de::Visitor {
+                self.integer_target = Some(IntegerRange {
+                    name: stringify!($ty),
+                    min_abs: ($ty::min_value() as i64).wrapping_neg() as u64,
+                    max: $ty::max_value() as u64,
+                });
+                let res = self.parse_value(visitor);
+                self.integer_target = None;
+                res
+            }
+        )*
+    }
+}
+
 impl<Iter> de::Deserializer for Deserializer<Iter>
     where Iter: Iterator<Item=u8>,
 {
@@ -438,15 +1033,84 @@ impl<Iter> de::Deserializer for Deserializer<Iter>
         where V: de::Visitor {
         try!(self.rdr.parse_whitespace());
 
+        if let State::Root = self.state {
+            if try!(self.rdr.eof()) {
+                self.state = State::Normal;
+                return visitor.visit_none();
+            }
+        }
+
+        if try!(self.at_null()) {
+            for _ in 0..4 {
+                self.rdr.eat_char();
+            }
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    /// Parses a tuple, checking that the array has exactly `len` elements.
+    #[inline]
+    fn deserialize_tuple<V>(&mut self, len: usize, mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        match self.state {
+            State::Normal => {}
+            _ => return self.deserialize(visitor),
+        }
+
+        try!(self.rdr.parse_whitespace());
+
         match try!(self.rdr.peek_or_null()) {
-            b'n' => {
+            b'[' => {
                 self.rdr.eat_char();
-                try!(self.parse_ident(b"ull"));
-                visitor.visit_none()
+                visitor.visit_seq(SeqVisitor::with_len(self, len))
             }
-            _ => {
-                visitor.visit_some(self)
+            _ => self.deserialize(visitor),
+        }
+    }
+
+    /// Parses a tuple struct, checking that the array has exactly `len` elements.
+    #[inline]
+    fn deserialize_tuple_struct<V>(&mut self,
+                                   _name: &'static str,
+                                   len: usize,
+                                   visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    /// Parses a struct, or a string together with its `StringStyle` for a `StyledString`, or
+    /// captures the source text of a value for a `RawValue` or a `RawNumber`.
+    #[inline]
+    fn deserialize_struct<V>(&mut self,
+                             name: &'static str,
+                             _fields: &'static [&'static str],
+                             mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        if name == STYLED_STRING_TOKEN {
+            let value: String = try!(de::Deserialize::deserialize(self));
+            let style = String::from(self.str_style.token());
+            visitor.visit_seq(de::value::SeqDeserializer::new(vec![style, value].into_iter(), 2))
+        } else if name == VALUE_TOKEN {
+            self.number_literal = true;
+            self.deserialize(visitor)
+        } else if name == RAW_VALUE_TOKEN {
+            let raw = try!(self.capture_value());
+            visitor.visit_string(dedent_lines(&raw))
+        } else if name == RAW_NUMBER_TOKEN {
+            if let State::Keyname = self.state {
+                // a key is read as a string, which the visitor checks
+                return self.deserialize(visitor);
+            }
+            let raw = try!(self.capture_value());
+            let mut pn = ParseNumber::new(raw.bytes()).extended(self.extended_numbers);
+            match pn.parse(false) {
+                Ok(_) => visitor.visit_string(raw),
+                Err(_) => Err(self.rdr.error(ErrorCode::InvalidNumber)),
             }
+        } else {
+            self.deserialize(visitor)
         }
     }
 
@@ -459,83 +1123,197 @@ impl<Iter> de::Deserializer for Deserializer<Iter>
         visitor.visit_newtype_struct(self)
     }
 
+    /// Parses an array, or an object as a sequence of key-value pairs in document order
+    /// (keeping duplicate keys), e.g. for a `Vec<(String, Value)>`.
+    #[inline]
+    fn deserialize_seq<V>(&mut self, mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        try!(self.rdr.parse_whitespace());
+
+        match self.state {
+            State::Normal => {}
+            State::Root => {
+                if try!(self.rdr.peek_or_null()) == b'[' {
+                    return self.deserialize(visitor);
+                }
+                self.state = State::Normal;
+                return visitor.visit_seq(PairsVisitor { map: MapVisitor::new(self, true).keep_duplicates() });
+            }
+            _ => return self.deserialize(visitor),
+        }
+
+        match try!(self.rdr.peek_or_null()) {
+            b'{' => {
+                self.rdr.eat_char();
+                visitor.visit_seq(PairsVisitor { map: MapVisitor::new(self, false).keep_duplicates() })
+            }
+            _ => self.deserialize(visitor),
+        }
+    }
+
+    /// Parses bytes (e.g. a `serde::bytes::ByteBuf`) from a base64 string, or from an array
+    /// of numbers.
+    #[inline]
+    fn deserialize_bytes<V>(&mut self, mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        match self.state {
+            State::Normal => {}
+            _ => return self.deserialize(visitor),
+        }
+
+        try!(self.rdr.parse_whitespace());
+
+        match try!(self.rdr.peek_or_null()) {
+            b'[' => self.deserialize(visitor),
+            _ => {
+                let text: String = try!(de::Deserialize::deserialize(self));
+                match decode_base64(&text) {
+                    Some(bytes) => visitor.visit_byte_buf(bytes),
+                    None => Err(self.rdr.error(ErrorCode::InvalidValue("invalid base64".to_owned()))),
+                }
+            }
+        }
+    }
+
+    /// Skips a value by advancing past it, without building anything for the visitor.
+    #[inline]
+    fn deserialize_ignored_any<V>(&mut self, mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        match self.state {
+            State::Normal => {}
+            _ => return self.deserialize(visitor),
+        }
+
+        try!(self.ignore_value());
+        visitor.visit_unit()
+    }
+
+    deserialize_integer!{
+        deserialize_usize => usize,
+        deserialize_u8 => u8,
+        deserialize_u16 => u16,
+        deserialize_u32 => u32,
+        deserialize_u64 => u64,
+        deserialize_isize => isize,
+        deserialize_i8 => i8,
+        deserialize_i16 => i16,
+        deserialize_i32 => i32,
+        deserialize_i64 => i64
+    }
+
     forward_to_deserialize!{
         deserialize_bool();
-        deserialize_usize();
-        deserialize_u8();
-        deserialize_u16();
-        deserialize_u32();
-        deserialize_u64();
-        deserialize_isize();
-        deserialize_i8();
-        deserialize_i16();
-        deserialize_i32();
-        deserialize_i64();
         deserialize_f32();
         deserialize_f64();
         deserialize_char();
         deserialize_str();
         deserialize_string();
         deserialize_unit();
-        deserialize_seq();
         deserialize_seq_fixed_size(len: usize);
-        deserialize_bytes();
         deserialize_map();
         deserialize_unit_struct(name: &'static str);
-        deserialize_tuple_struct(name: &'static str, len: usize);
-        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
         deserialize_struct_field();
-        deserialize_tuple(len: usize);
         deserialize_enum(name: &'static str, variants: &'static [&'static str]);
-        deserialize_ignored_any();
     }
 }
 
+/// Accepts the scalar values produced while skipping a value.
+struct IgnoreVisitor;
+
+impl de::Visitor for IgnoreVisitor {
+    type Value = ();
+
+    fn visit_bool<E>(&mut self, _: bool) -> ::std::result::Result<(), E> { Ok(()) }
+
+    fn visit_f64<E>(&mut self, _: f64) -> ::std::result::Result<(), E> { Ok(()) }
+
+    fn visit_str<E>(&mut self, _: &str) -> ::std::result::Result<(), E> { Ok(()) }
+
+    fn visit_unit<E>(&mut self) -> ::std::result::Result<(), E> { Ok(()) }
+}
+
 struct SeqVisitor<'a, Iter: 'a + Iterator<Item=u8>> {
     de: &'a mut Deserializer<Iter>,
+    len: Option<usize>,
+    count: usize,
 }
 
 impl<'a, Iter: Iterator<Item=u8>> SeqVisitor<'a, Iter> {
     fn new(de: &'a mut Deserializer<Iter>) -> Self {
         SeqVisitor {
             de: de,
+            len: None,
+            count: 0,
         }
     }
-}
 
-impl<'a, Iter> de::SeqVisitor for SeqVisitor<'a, Iter>
-    where Iter: Iterator<Item=u8>,
-{
-    type Error = Error;
+    /// A visitor for a sequence that must have exactly `len` elements.
+    fn with_len(de: &'a mut Deserializer<Iter>, len: usize) -> Self {
+        SeqVisitor {
+            de: de,
+            len: Some(len),
+            count: 0,
+        }
+    }
 
-    fn visit<T>(&mut self) -> Result<Option<T>>
-        where T: de::Deserialize {
+    /// Skips the whitespace before the next element and checks whether there is one.
+    fn has_next(&mut self) -> Result<bool> {
         try!(self.de.rdr.parse_whitespace());
 
         match try!(self.de.rdr.peek()) {
             Some(b']') => {
-                return Ok(None);
-            }
-            Some(_) => {
-            }
-            None => {
-                return Err(self.de.rdr.error(ErrorCode::EOFWhileParsingList));
+                match self.len {
+                    Some(len) if self.count < len => {
+                        Err(self.de.rdr.error(ErrorCode::InvalidTupleLength(len, self.count)))
+                    }
+                    _ => Ok(false),
+                }
             }
+            Some(_) => Ok(true),
+            None => Err(self.de.rdr.error(ErrorCode::EOFWhileParsingList)),
         }
+    }
 
-        let value = try!(de::Deserialize::deserialize(self.de));
-
+    /// Skips the separator after an element.
+    fn skip_comma(&mut self) -> Result<()> {
         // in Hjson the comma is optional and trailing commas are allowed
         try!(self.de.rdr.parse_whitespace());
         if try!(self.de.rdr.peek()) == Some(b',') {
             self.de.rdr.eat_char();
             try!(self.de.rdr.parse_whitespace());
         }
+        Ok(())
+    }
+}
+
+impl<'a, Iter> de::SeqVisitor for SeqVisitor<'a, Iter>
+    where Iter: Iterator<Item=u8>,
+{
+    type Error = Error;
+
+    fn visit<T>(&mut self) -> Result<Option<T>>
+        where T: de::Deserialize {
+        if !try!(self.has_next()) {
+            return Ok(None);
+        }
+
+        let index = self.count.to_string();
+        let value = try!(self.de.deserialize_member(&index));
+        self.count += 1;
 
+        try!(self.skip_comma());
         Ok(Some(value))
     }
 
     fn end(&mut self) -> Result<()> {
+        if let Some(len) = self.len {
+            // count any remaining elements to report the actual length
+            while try!(self.visit::<de::impls::IgnoredAny>()).is_some() {}
+            if self.count != len {
+                return Err(self.de.rdr.error(ErrorCode::InvalidTupleLength(len, self.count)));
+            }
+        }
+
         try!(self.de.rdr.parse_whitespace());
 
         match try!(self.de.rdr.next_char()) {
@@ -554,25 +1332,31 @@ struct MapVisitor<'a, Iter: 'a + Iterator<Item=u8>> {
     de: &'a mut Deserializer<Iter>,
     first: bool,
     root: bool,
+    keys: Option<HashSet<Vec<u8>>>,
+    key: String,
 }
 
 impl<'a, Iter: Iterator<Item=u8>> MapVisitor<'a, Iter> {
     fn new(de: &'a mut Deserializer<Iter>, root: bool) -> Self {
+        // the keys are only tracked if duplicates are not passed on
+        let keys = if de.duplicate_keys == DuplicateKeys::LastWins { None } else { Some(HashSet::new()) };
         MapVisitor {
             de: de,
             first: true,
             root: root,
+            keys: keys,
+            key: String::new(),
         }
     }
-}
 
-impl<'a, Iter> de::MapVisitor for MapVisitor<'a, Iter>
-    where Iter: Iterator<Item=u8>
-{
-    type Error = Error;
+    /// Passes on all members, whatever the `DuplicateKeys` policy.
+    fn keep_duplicates(mut self) -> Self {
+        self.keys = None;
+        self
+    }
 
-    fn visit_key<K>(&mut self) -> Result<Option<K>>
-        where K: de::Deserialize {
+    /// Skips the separator before the next member and checks whether there is one.
+    fn has_next_key(&mut self) -> Result<bool> {
         try!(self.de.rdr.parse_whitespace());
 
         if self.first {
@@ -584,22 +1368,68 @@ impl<'a, Iter> de::MapVisitor for MapVisitor<'a, Iter>
         }
 
         match try!(self.de.rdr.peek()) {
-            Some(b'}') => return Ok(None), // handled later for root
-            Some(_) => {},
+            Some(b'}') => Ok(false), // handled later for root
+            Some(_) => Ok(true),
             None => {
-                if self.root { return Ok(None); }
-                else { return Err(self.de.rdr.error(ErrorCode::EOFWhileParsingObject)); }
+                if self.root { Ok(false) }
+                else { Err(self.de.rdr.error(ErrorCode::EOFWhileParsingObject)) }
             },
         }
+    }
+}
 
-        match try!(self.de.rdr.peek()) {
-            Some(ch) => {
-                self.de.state = if ch == b'"' { State::Normal } else { State::Keyname };
-                Ok(Some(try!(de::Deserialize::deserialize(self.de))))
+/// Tells a bad escape in a quoted key apart from one in a string value.
+fn key_error(err: Error) -> Error {
+    match err {
+        Error::Syntax(ErrorCode::InvalidEscape, line, col, offset) => {
+            Error::Syntax(ErrorCode::InvalidEscapeInKey, line, col, offset)
+        }
+        err => err,
+    }
+}
+
+impl<'a, Iter> de::MapVisitor for MapVisitor<'a, Iter>
+    where Iter: Iterator<Item=u8>
+{
+    type Error = Error;
+
+    fn visit_key<K>(&mut self) -> Result<Option<K>>
+        where K: de::Deserialize {
+        loop {
+            if !try!(self.has_next_key()) {
+                return Ok(None);
             }
-            None => {
-                Err(self.de.rdr.error(ErrorCode::EOFWhileParsingValue))
+
+            let key = match try!(self.de.rdr.peek()) {
+                Some(ch) => {
+                    let quoted = ch == b'"' || try!(self.de.at_single_quote());
+                    self.de.state = if quoted { State::Normal } else { State::Keyname };
+                    self.de.reading_key = true;
+                    let key = de::Deserialize::deserialize(self.de).map_err(key_error);
+                    self.de.reading_key = false;
+                    try!(key)
+                }
+                None => {
+                    return Err(self.de.rdr.error(ErrorCode::EOFWhileParsingValue));
+                }
+            };
+
+            // both quoted keys and key names are read into `str_buf`
+            if let Some(ref mut keys) = self.keys {
+                if !keys.insert(self.de.str_buf.clone()) {
+                    if self.de.duplicate_keys == DuplicateKeys::Error {
+                        let name = String::from_utf8_lossy(&self.de.str_buf).into_owned();
+                        return Err(self.de.rdr.error(ErrorCode::DuplicateKey(name)));
+                    }
+                    try!(self.de.parse_object_colon());
+                    try!(self.de.ignore_value());
+                    continue;
+                }
             }
+            if self.de.record_spans {
+                self.key = String::from_utf8_lossy(&self.de.str_buf).into_owned();
+            }
+            return Ok(Some(key));
         }
     }
 
@@ -607,7 +1437,8 @@ impl<'a, Iter> de::MapVisitor for MapVisitor<'a, Iter>
         where V: de::Deserialize {
         try!(self.de.parse_object_colon());
 
-        Ok(try!(de::Deserialize::deserialize(self.de)))
+        let key = mem::replace(&mut self.key, String::new());
+        self.de.deserialize_member(&key)
     }
 
     fn end(&mut self) -> Result<()> {
@@ -687,6 +1518,111 @@ impl<'a, Iter> de::MapVisitor for MapVisitor<'a, Iter>
     }
 }
 
+/// Visits the members of an object as a sequence of `(key, value)` pairs.
+struct PairsVisitor<'a, Iter: 'a + Iterator<Item=u8>> {
+    map: MapVisitor<'a, Iter>,
+}
+
+impl<'a, Iter> de::SeqVisitor for PairsVisitor<'a, Iter>
+    where Iter: Iterator<Item=u8>,
+{
+    type Error = Error;
+
+    fn visit<T>(&mut self) -> Result<Option<T>>
+        where T: de::Deserialize {
+        if !try!(self.map.has_next_key()) {
+            return Ok(None);
+        }
+
+        let mut pair = PairDeserializer { map: &mut self.map, count: 0 };
+        Ok(Some(try!(de::Deserialize::deserialize(&mut pair))))
+    }
+
+    fn end(&mut self) -> Result<()> {
+        de::MapVisitor::end(&mut self.map)
+    }
+}
+
+/// Deserializes a single object member as a two element sequence of key and value.
+struct PairDeserializer<'a, 'b: 'a, Iter: 'b + Iterator<Item=u8>> {
+    map: &'a mut MapVisitor<'b, Iter>,
+    count: usize,
+}
+
+impl<'a, 'b, Iter> de::Deserializer for PairDeserializer<'a, 'b, Iter>
+    where Iter: Iterator<Item=u8>,
+{
+    type Error = Error;
+
+    #[inline]
+    fn deserialize<V>(&mut self, mut visitor: V) -> Result<V::Value>
+        where V: de::Visitor {
+        visitor.visit_seq(self)
+    }
+
+    forward_to_deserialize!{
+        deserialize_bool();
+        deserialize_usize();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_isize();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_unit();
+        deserialize_option();
+        deserialize_seq();
+        deserialize_seq_fixed_size(len: usize);
+        deserialize_bytes();
+        deserialize_map();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_struct_field();
+        deserialize_tuple(len: usize);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_ignored_any();
+    }
+}
+
+impl<'a, 'b, Iter> de::SeqVisitor for PairDeserializer<'a, 'b, Iter>
+    where Iter: Iterator<Item=u8>,
+{
+    type Error = Error;
+
+    fn visit<T>(&mut self) -> Result<Option<T>>
+        where T: de::Deserialize {
+        self.count += 1;
+        match self.count {
+            1 => de::MapVisitor::visit_key(self.map),
+            2 => de::MapVisitor::visit_value(self.map).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.count < 2 {
+            Err(de::Error::invalid_length(self.count))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = 2 - ::std::cmp::min(self.count, 2);
+        (len, Some(len))
+    }
+}
+
 impl<Iter> de::VariantVisitor for Deserializer<Iter>
     where Iter: Iterator<Item=u8>,
 {
@@ -726,6 +1662,9 @@ impl<Iter> de::VariantVisitor for Deserializer<Iter>
 //////////////////////////////////////////////////////////////////////////////
 
 /// Iterator that deserializes a stream into multiple Hjson values.
+///
+/// Unlike `from_str` and the other single value functions, content after a value is not an
+/// error but read as the next value.
 pub struct StreamDeserializer<T, Iter>
     where Iter: Iterator<Item=u8>,
           T: de::Deserialize
@@ -777,6 +1716,10 @@ impl <T, Iter> Iterator for StreamDeserializer<T, Iter>
 
 /// Decodes a Hjson value from an iterator over an iterator
 /// `Iterator<Item=u8>`.
+///
+/// An empty document, or one that only contains whitespace and comments, decodes as `null`
+/// (e.g. `Value::Null` or `None`). Anything but whitespace and comments after the value is
+/// an `ErrorCode::TrailingCharacters` error; use a `StreamDeserializer` to read several values.
 pub fn from_iter<I, T>(iter: I) -> Result<T>
     where I: Iterator<Item=io::Result<u8>>,
           T: de::Deserialize,
@@ -829,6 +1772,71 @@ pub fn from_reader<R, T>(rdr: R) -> Result<T>
     from_iter(rdr.bytes())
 }
 
+/// Decodes one Hjson value from the start of a `std::io::Read` and returns it together with a
+/// reader for the rest of the input, for protocols where the value is followed by other data.
+///
+/// The input is only read as far as the value needs, plus the few bytes the parser looks
+/// ahead; the returned reader yields those bytes first and then continues with `rdr`. Unlike
+/// `from_reader`, the value must not be an object without braces (it would run on to the end
+/// of the input), and the input after the value is not checked.
+///
+/// ```rust
+/// use std::io::{Cursor, Read};
+/// use serde_hjson::Value;
+///
+/// let input = Cursor::new(b"{\n  a: 1\n}\nbinary data".to_vec());
+/// let (value, mut rest): (Value, _) = serde_hjson::from_reader_with_trailing(input).unwrap();
+/// assert_eq!(value.find("a").and_then(|a| a.as_f64()), Some(1.0));
+///
+/// let mut trailing = String::new();
+/// rest.read_to_string(&mut trailing).unwrap();
+/// assert_eq!(trailing, "\nbinary data");
+/// ```
+pub fn from_reader_with_trailing<R, T>(mut rdr: R) -> Result<(T, io::Chain<io::Cursor<Vec<u8>>, R>)>
+    where R: io::Read,
+          T: de::Deserialize,
+{
+    let (value, peeked) = {
+        let mut bytes = ReadBytes { rdr: &mut rdr, err: None };
+        let (value, peeked) = {
+            let mut de = Deserializer::new(bytes.by_ref());
+            let value = de::Deserialize::deserialize(&mut de);
+            (value, de.rdr.take_peeked())
+        };
+        // a read error shows up as the end of the input to the parser
+        if let Some(err) = bytes.err {
+            return Err(Error::Io(err));
+        }
+        (try!(value), peeked)
+    };
+    Ok((value, io::Read::chain(io::Cursor::new(peeked), rdr)))
+}
+
+/// Reads a `std::io::Read` one byte at a time, so that nothing is read past what is needed.
+struct ReadBytes<'a, R: 'a> {
+    rdr: &'a mut R,
+    err: Option<io::Error>,
+}
+
+impl<'a, R: io::Read> Iterator for ReadBytes<'a, R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let mut buf = [0u8];
+        loop {
+            match self.rdr.read(&mut buf) {
+                Ok(0) => return None,
+                Ok(_) => return Some(buf[0]),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.err = Some(err);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
 /// Decodes a Hjson value from a byte slice `&[u8]`.
 pub fn from_slice<T>(v: &[u8]) -> Result<T>
     where T: de::Deserialize
@@ -837,8 +1845,204 @@ pub fn from_slice<T>(v: &[u8]) -> Result<T>
 }
 
 /// Decodes a Hjson value from a `&str`.
+///
+/// An empty document, or one that only contains whitespace and comments, decodes as `null`
+/// (e.g. `Value::Null` or `None`). Anything but whitespace and comments after the value is
+/// an `ErrorCode::TrailingCharacters` error.
 pub fn from_str<T>(s: &str) -> Result<T>
     where T: de::Deserialize
 {
     from_slice(s.as_bytes())
 }
+
+/// Decodes a Hjson value from a `&str` by handing it to `visitor`, like `from_str` does with
+/// the visitor of a `Deserialize` type.
+///
+/// serde 0.8 has no `DeserializeSeed`, but a visitor can carry state (e.g. an interner) into
+/// deserialization this way. Only the visitor for the document itself sees the state, values
+/// inside it are decoded by their `Deserialize` impls.
+///
+/// The document is an object without braces if its first token is a key followed by `:`. Unlike
+/// `from_str`, a document that fails to decode as one is not read again as a single value.
+pub fn from_str_visitor<V>(s: &str, visitor: V) -> Result<V::Value>
+    where V: de::Visitor
+{
+    // the visitor can only be used once, so decide from the first token whether the document
+    // is an object without braces instead of trying both like `from_str`
+    let mut de = Deserializer::new(s.bytes());
+    try!(de.rdr.parse_whitespace());
+    if try!(de.at_key()) {
+        de.state = State::Root;
+    }
+    let value = try!(de::Deserializer::deserialize(&mut de, visitor));
+    try!(de.end());
+    Ok(value)
+}
+
+/// Checks that `s` is a valid Hjson document without decoding it. The document is only
+/// skipped over, so no `Value` or strings are built. It fails where `from_str::<Value>` would,
+/// with the same error and position, except that `\u` escapes of UTF-16 surrogates are not
+/// checked to come in pairs.
+///
+/// ```rust
+/// assert!(serde_hjson::validate("{\n  a: 1\n  b: [2, \"x\"]\n}").is_ok());
+/// assert!(serde_hjson::validate("a: 1\nb: 2").is_ok());
+///
+/// match serde_hjson::validate("{\n  a: \"\\q\"\n}") {
+///     Err(serde_hjson::Error::Syntax(serde_hjson::ErrorCode::InvalidEscape, 2, 8, _)) => {}
+///     other => panic!("unexpected result: {:?}", other),
+/// }
+/// ```
+pub fn validate(s: &str) -> Result<()> {
+    // like `from_iter`, try an object without braces first
+    skip_document(Deserializer::new_for_root(s.bytes()))
+        .or_else(|_| skip_document(Deserializer::new(s.bytes())))
+}
+
+fn skip_document<Iter>(mut de: Deserializer<Iter>) -> Result<()>
+    where Iter: Iterator<Item=u8>,
+{
+    try!(de.rdr.parse_whitespace());
+    match de.state {
+        State::Root => {
+            de.state = State::Normal;
+            try!(de.ignore_map(true));
+        }
+        _ => try!(de.ignore_value()),
+    }
+    de.end()
+}
+
+/// Decodes a Hjson value from a `&str` on a best-effort basis, e.g. for the preview of a
+/// document that is being edited. On an error, the value holds everything that was decoded
+/// before it: the members and elements read so far of the arrays and objects the error
+/// occurred in, nested like in the input.
+///
+/// ```rust
+/// use serde_hjson::Value;
+///
+/// let (value, err) = serde_hjson::from_str_partial("{\n  a: 1\n  b: [2, 3\n");
+/// assert_eq!(value.find("a"), Some(&Value::F64(1.0)));
+/// assert_eq!(value.find("b"), Some(&Value::Array(vec![Value::F64(2.0), Value::F64(3.0)])));
+/// assert!(err.is_some());
+/// ```
+pub fn from_str_partial(s: &str) -> (Value, Option<Error>) {
+    // like `from_str_visitor`, the first token decides whether this is an object without braces
+    let mut de = Deserializer::new(s.bytes());
+    match de.rdr.parse_whitespace().and_then(|_| de.at_key()) {
+        Ok(true) => de.state = State::Root,
+        Ok(false) => {}
+        Err(err) => return (Value::Null, Some(err)),
+    }
+
+    match partial_value(&mut de) {
+        Partial::Complete(value) => {
+            let err = de.end().err();
+            (value, err)
+        }
+        Partial::Broken(value, err) => (value.unwrap_or(Value::Null), Some(err)),
+    }
+}
+
+/// What `partial_value` decoded.
+enum Partial {
+    /// The whole value.
+    Complete(Value),
+    /// An error, with what was decoded before it of the array or object it occurred in.
+    Broken(Option<Value>, Error),
+}
+
+// Decodes the value at the position of `de`, keeping the members and elements of arrays and
+// objects read before an error.
+fn partial_value<Iter>(de: &mut Deserializer<Iter>) -> Partial
+    where Iter: Iterator<Item=u8>,
+{
+    if let Err(err) = de.rdr.parse_whitespace() {
+        return Partial::Broken(None, err);
+    }
+
+    let eof = match de.rdr.eof() {
+        Ok(eof) => eof,
+        Err(err) => return Partial::Broken(None, err),
+    };
+    if let State::Root = de.state {
+        de.state = State::Normal;
+        if eof {
+            // an empty document (or one with only comments) is null
+            return Partial::Complete(Value::Null);
+        }
+        return partial_map(MapVisitor::new(de, true));
+    }
+
+    match de.rdr.peek_or_null() {
+        Ok(b'[') => {
+            de.rdr.eat_char();
+            partial_seq(SeqVisitor::new(de))
+        }
+        Ok(b'{') => {
+            de.rdr.eat_char();
+            partial_map(MapVisitor::new(de, false))
+        }
+        Ok(_) => match de::Deserialize::deserialize(de) {
+            Ok(value) => Partial::Complete(value),
+            Err(err) => Partial::Broken(None, err),
+        },
+        Err(err) => Partial::Broken(None, err),
+    }
+}
+
+fn partial_seq<Iter>(mut visitor: SeqVisitor<Iter>) -> Partial
+    where Iter: Iterator<Item=u8>,
+{
+    let mut values = Vec::new();
+    loop {
+        match visitor.has_next() {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => return Partial::Broken(Some(Value::Array(values)), err),
+        }
+        match partial_value(visitor.de) {
+            Partial::Complete(value) => values.push(value),
+            Partial::Broken(value, err) => {
+                values.extend(value);
+                return Partial::Broken(Some(Value::Array(values)), err);
+            }
+        }
+        if let Err(err) = visitor.skip_comma() {
+            return Partial::Broken(Some(Value::Array(values)), err);
+        }
+    }
+    match de::SeqVisitor::end(&mut visitor) {
+        Ok(()) => Partial::Complete(Value::Array(values)),
+        Err(err) => Partial::Broken(Some(Value::Array(values)), err),
+    }
+}
+
+fn partial_map<Iter>(mut visitor: MapVisitor<Iter>) -> Partial
+    where Iter: Iterator<Item=u8>,
+{
+    let mut values = Map::new();
+    loop {
+        let key: String = match de::MapVisitor::visit_key(&mut visitor) {
+            Ok(Some(key)) => key,
+            Ok(None) => break,
+            Err(err) => return Partial::Broken(Some(Value::Object(values)), err),
+        };
+        if let Err(err) = visitor.de.parse_object_colon() {
+            return Partial::Broken(Some(Value::Object(values)), err);
+        }
+        match partial_value(visitor.de) {
+            Partial::Complete(value) => { values.insert(key, value); }
+            Partial::Broken(value, err) => {
+                if let Some(value) = value {
+                    values.insert(key, value);
+                }
+                return Partial::Broken(Some(Value::Object(values)), err);
+            }
+        }
+    }
+    match de::MapVisitor::end(&mut visitor) {
+        Ok(()) => Partial::Complete(Value::Object(values)),
+        Err(err) => Partial::Broken(Some(Value::Object(values)), err),
+    }
+}
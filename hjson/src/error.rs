@@ -27,6 +27,9 @@ pub enum ErrorCode {
     /// Invalid length
     InvalidLength(usize),
 
+    /// Tuple with the wrong number of elements (expected, found).
+    InvalidTupleLength(usize, usize),
+
     /// Unknown variant in an enum.
     UnknownVariant(String),
 
@@ -66,15 +69,34 @@ pub enum ErrorCode {
     /// Invalid hex escape code.
     InvalidEscape,
 
+    /// Invalid escape in a quoted key, as opposed to `InvalidEscape` in a string value.
+    InvalidEscapeInKey,
+
     /// Invalid number.
     InvalidNumber,
 
+    /// An integer literal outside of the range of the integer type it is deserialized into,
+    /// with the literal and the name of the type.
+    NumberOutOfRange(String, &'static str),
+
     /// Invalid unicode code point.
     InvalidUnicodeCodePoint,
 
     /// Object key is not a string.
     KeyMustBeAString,
 
+    /// Object key appears more than once, see `Deserializer::duplicate_keys`.
+    DuplicateKey(String),
+
+    /// Object key contains a line break, see `Serializer::reject_multiline_keys`.
+    MultilineKey,
+
+    /// Arrays and objects are nested too deeply, see `Serializer::max_depth`.
+    DepthLimitExceeded,
+
+    /// A tab character between tokens, see `Deserializer::reject_tabs`.
+    TabOutsideString,
+
     /// Lone leading surrogate in hex escape.
     LoneLeadingSurrogateInHexEscape,
 
@@ -86,6 +108,10 @@ pub enum ErrorCode {
 
     /// Found a punctuator character when expecting a quoteless string.
     PunctuatorInQlString,
+
+    /// A quoteless string contains a punctuator or a comment, see
+    /// `Deserializer::strict_bare_words`.
+    ReservedCharInQlString,
 }
 
 impl fmt::Debug for ErrorCode {
@@ -97,6 +123,9 @@ impl fmt::Debug for ErrorCode {
             ErrorCode::InvalidType(ref ty) => write!(f, "invalid type: {:?}", ty),
             ErrorCode::InvalidValue(ref msg) => write!(f, "invalid value: {}", msg),
             ErrorCode::InvalidLength(ref len) => write!(f, "invalid value length {}", len),
+            ErrorCode::InvalidTupleLength(expected, found) => {
+                write!(f, "invalid tuple length: expected {} elements, found {}", expected, found)
+            }
             ErrorCode::UnknownVariant(ref variant) => write!(f, "unknown variant \"{}\"", variant),
             ErrorCode::UnknownField(ref field) => write!(f, "unknown field \"{}\"", field),
             ErrorCode::MissingField(ref field) => write!(f, "missing field \"{}\"", field),
@@ -110,13 +139,22 @@ impl fmt::Debug for ErrorCode {
             ErrorCode::ExpectedSomeIdent => "expected ident".fmt(f),
             ErrorCode::ExpectedSomeValue => "expected value".fmt(f),
             ErrorCode::InvalidEscape => "invalid escape".fmt(f),
+            ErrorCode::InvalidEscapeInKey => "invalid escape in key".fmt(f),
             ErrorCode::InvalidNumber => "invalid number".fmt(f),
+            ErrorCode::NumberOutOfRange(ref number, ty) => {
+                write!(f, "number {} is out of range for {}", number, ty)
+            }
             ErrorCode::InvalidUnicodeCodePoint => "invalid unicode code point".fmt(f),
             ErrorCode::KeyMustBeAString => "key must be a string".fmt(f),
+            ErrorCode::DuplicateKey(ref key) => write!(f, "duplicate key \"{}\"", key),
+            ErrorCode::MultilineKey => "key contains a line break".fmt(f),
+            ErrorCode::DepthLimitExceeded => "nesting exceeds the maximum depth".fmt(f),
+            ErrorCode::TabOutsideString => "tab character outside a string".fmt(f),
             ErrorCode::LoneLeadingSurrogateInHexEscape => "lone leading surrogate in hex escape".fmt(f),
             ErrorCode::TrailingCharacters => "trailing characters".fmt(f),
             ErrorCode::UnexpectedEndOfHexEscape => "unexpected end of hex escape".fmt(f),
             ErrorCode::PunctuatorInQlString => "found a punctuator character when expecting a quoteless string".fmt(f),
+            ErrorCode::ReservedCharInQlString => "found a punctuator or comment in a quoteless string (use quotes to include it)".fmt(f),
         }
     }
 }
@@ -125,8 +163,9 @@ impl fmt::Debug for ErrorCode {
 /// value into JSON.
 #[derive(Debug)]
 pub enum Error {
-    /// The JSON value had some syntatic error.
-    Syntax(ErrorCode, usize, usize),
+    /// The JSON value had some syntatic error, reported with its line, column and
+    /// absolute byte offset.
+    Syntax(ErrorCode, usize, usize, usize),
 
     /// Some IO error occurred when serializing or deserializing a value.
     Io(io::Error),
@@ -135,6 +174,42 @@ pub enum Error {
     FromUtf8(FromUtf8Error),
 }
 
+impl Error {
+    /// Returns the absolute byte offset into the input at which a syntax error was detected,
+    /// or `None` if this is not a syntax error.
+    pub fn offset(&self) -> Option<usize> {
+        match *self {
+            Error::Syntax(_, _, _, offset) => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Renders the error together with the offending line of `source`, the input that was
+    /// being parsed, and a `^` marker under the column at which the error was detected.
+    ///
+    /// Errors without a position are rendered like `Display`.
+    pub fn display_with_source(&self, source: &str) -> String {
+        let (line, col) = match *self {
+            Error::Syntax(_, line, col, _) if line > 0 => (line, col),
+            _ => return self.to_string(),
+        };
+
+        let text = source.split('\n').nth(line - 1).unwrap_or("").trim_right_matches('\r');
+
+        // keep tabs in the marker line so the caret lines up with the source
+        let mut marker = String::new();
+        for (i, ch) in text.char_indices() {
+            if i + 1 >= col { break; }
+            marker.push(if ch == '\t' { '\t' } else { ' ' });
+        }
+        marker.push('^');
+
+        let number = line.to_string();
+        let gutter: String = number.chars().map(|_| ' ').collect();
+        format!("{}\n{} |\n{} | {}\n{} | {}", self, gutter, number, text, gutter, marker)
+    }
+}
+
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
@@ -157,7 +232,7 @@ impl error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::Syntax(ref code, line, col) => {
+            Error::Syntax(ref code, line, col, _) => {
                 write!(fmt, "{:?} at line {} column {}", code, line, col)
             }
             Error::Io(ref error) => fmt::Display::fmt(error, fmt),
@@ -182,28 +257,28 @@ impl From<de::value::Error> for Error {
     fn from(error: de::value::Error) -> Error {
         match error {
             de::value::Error::Custom(e) => {
-                Error::Syntax(ErrorCode::Custom(e), 0, 0)
+                Error::Syntax(ErrorCode::Custom(e), 0, 0, 0)
             }
             de::value::Error::EndOfStream => {
                 de::Error::end_of_stream()
             }
             de::value::Error::InvalidType(ty) => {
-                Error::Syntax(ErrorCode::InvalidType(ty), 0, 0)
+                Error::Syntax(ErrorCode::InvalidType(ty), 0, 0, 0)
             }
             de::value::Error::InvalidValue(msg) => {
-                Error::Syntax(ErrorCode::InvalidValue(msg), 0, 0)
+                Error::Syntax(ErrorCode::InvalidValue(msg), 0, 0, 0)
             }
             de::value::Error::InvalidLength(len) => {
-                Error::Syntax(ErrorCode::InvalidLength(len), 0, 0)
+                Error::Syntax(ErrorCode::InvalidLength(len), 0, 0, 0)
             }
             de::value::Error::UnknownVariant(variant) => {
-                Error::Syntax(ErrorCode::UnknownVariant(variant), 0, 0)
+                Error::Syntax(ErrorCode::UnknownVariant(variant), 0, 0, 0)
             }
             de::value::Error::UnknownField(field) => {
-                Error::Syntax(ErrorCode::UnknownField(field), 0, 0)
+                Error::Syntax(ErrorCode::UnknownField(field), 0, 0, 0)
             }
             de::value::Error::MissingField(field) => {
-                Error::Syntax(ErrorCode::MissingField(field), 0, 0)
+                Error::Syntax(ErrorCode::MissingField(field), 0, 0, 0)
             }
         }
     }
@@ -211,42 +286,42 @@ impl From<de::value::Error> for Error {
 
 impl de::Error for Error {
     fn custom<T: Into<String>>(msg: T) -> Error {
-        Error::Syntax(ErrorCode::Custom(msg.into()), 0, 0)
+        Error::Syntax(ErrorCode::Custom(msg.into()), 0, 0, 0)
     }
 
     fn end_of_stream() -> Error {
-        Error::Syntax(ErrorCode::EOFWhileParsingValue, 0, 0)
+        Error::Syntax(ErrorCode::EOFWhileParsingValue, 0, 0, 0)
     }
 
     fn invalid_type(ty: de::Type) -> Error {
-        Error::Syntax(ErrorCode::InvalidType(ty), 0, 0)
+        Error::Syntax(ErrorCode::InvalidType(ty), 0, 0, 0)
     }
 
     fn invalid_value(msg: &str) -> Error {
-        Error::Syntax(ErrorCode::InvalidValue(msg.to_owned()), 0, 0)
+        Error::Syntax(ErrorCode::InvalidValue(msg.to_owned()), 0, 0, 0)
     }
 
     fn invalid_length(len: usize) -> Error {
-        Error::Syntax(ErrorCode::InvalidLength(len), 0, 0)
+        Error::Syntax(ErrorCode::InvalidLength(len), 0, 0, 0)
     }
 
     fn unknown_variant(variant: &str) -> Error {
-        Error::Syntax(ErrorCode::UnknownVariant(String::from(variant)), 0, 0)
+        Error::Syntax(ErrorCode::UnknownVariant(String::from(variant)), 0, 0, 0)
     }
 
     fn unknown_field(field: &str) -> Error {
-        Error::Syntax(ErrorCode::UnknownField(String::from(field)), 0, 0)
+        Error::Syntax(ErrorCode::UnknownField(String::from(field)), 0, 0, 0)
     }
 
     fn missing_field(field: &'static str) -> Error {
-        Error::Syntax(ErrorCode::MissingField(field), 0, 0)
+        Error::Syntax(ErrorCode::MissingField(field), 0, 0, 0)
     }
 }
 
 impl ser::Error for Error {
     /// Raised when there is general error when deserializing a type.
     fn custom<T: Into<String>>(msg: T) -> Error {
-        Error::Syntax(ErrorCode::Custom(msg.into()), 0, 0)
+        Error::Syntax(ErrorCode::Custom(msg.into()), 0, 0, 0)
     }
 }
 
@@ -10,6 +10,7 @@
 //! * `I64`: equivalent to rust's `i64`
 //! * `U64`: equivalent to rust's `u64`
 //! * `F64`: equivalent to rust's `f64`
+//! * `Number`: a number whose literal is kept, e.g. `1.50`, see `serde_hjson::RawNumber`
 //! * `String`: equivalent to rust's `String`
 //! * `Array`: equivalent to rust's `Vec<T>`, but also allowing objects of different types in the
 //!    same array
@@ -70,16 +71,26 @@ pub use self::de::{
     StreamDeserializer,
     from_iter,
     from_reader,
+    from_reader_with_trailing,
     from_slice,
     from_str,
+    from_str_partial,
+    from_str_visitor,
+    validate,
 };
 pub use self::error::{Error, ErrorCode, Result};
+pub use self::number::{Number, RawNumber};
 pub use self::ser::{
     Serializer,
+    pretty_print,
     to_writer,
     to_vec,
+    to_buf,
     to_string,
+    to_string_escaped_ascii,
 };
+#[cfg(feature = "preserve_order")]
+pub use self::ser::reformat_preserving_order;
 pub use self::value::{Value, Map, to_value, from_value};
 
 #[macro_use]
@@ -88,6 +99,7 @@ mod forward;
 pub mod builder;
 pub mod de;
 pub mod error;
+pub mod number;
 pub mod ser;
 mod util;
 pub mod value;
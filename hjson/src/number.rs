@@ -0,0 +1,368 @@
+//! Hjson Number
+//!
+//! This module provides the `Number` type, which holds any Hjson number and, unlike `f64`,
+//! implements `Eq` and `Hash` so that it can be used in sets and as a map key, `RawNumber`,
+//! which keeps a number as it was written, as well as `ParseNumber`, the parser used for
+//! numbers in Hjson text.
+//!
+//! # Equality
+//!
+//! Two `Number`s are equal if they have the same mathematical value, no matter if they were
+//! created from an integer or a float: `Number::from(1)` equals `Number::from(1.0)` and both
+//! hash the same. On top of that:
+//!
+//! * `0.0` and `-0.0` are equal.
+//! * `NaN` is equal to itself (and to any other `NaN`), otherwise `Eq` could not be implemented.
+//!
+//! ```rust
+//! use serde_hjson::Number;
+//!
+//! assert_eq!(Number::from(1), Number::from(1.0));
+//! assert!(Number::from(1) != Number::from(1.5));
+//! ```
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str;
+
+use serde::de;
+use serde::ser;
+
+pub use util::ParseNumber;
+
+use super::error::{Error, ErrorCode};
+use super::util::fmt_small;
+
+/// Represents an Hjson number, which is either an integer or a float.
+#[derive(Clone, Copy, Debug)]
+pub struct Number {
+    n: N,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum N {
+    PosInt(u64),
+    NegInt(i64),
+    Float(f64),
+}
+
+/// The normalized form of a number that equality and hashing are based on.
+#[derive(Eq, Hash, PartialEq)]
+enum Key {
+    PosInt(u64),
+    NegInt(i64),
+    Float(u64),
+    NaN,
+}
+
+impl Number {
+    /// Returns true if the `Number` is an integer that fits in an i64.
+    pub fn is_i64(&self) -> bool {
+        self.as_i64().is_some()
+    }
+
+    /// Returns true if the `Number` is an integer that fits in an u64.
+    pub fn is_u64(&self) -> bool {
+        self.as_u64().is_some()
+    }
+
+    /// Returns true if the `Number` was created from a float.
+    pub fn is_f64(&self) -> bool {
+        match self.n {
+            N::Float(_) => true,
+            _ => false,
+        }
+    }
+
+    /// If the `Number` is an integer that fits in an i64, returns it.
+    /// Returns None otherwise.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.n {
+            N::PosInt(n) if n <= i64::max_value() as u64 => Some(n as i64),
+            N::NegInt(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// If the `Number` is an integer that fits in an u64, returns it.
+    /// Returns None otherwise.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.n {
+            N::PosInt(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Number` as a f64, which may lose precision for large integers.
+    pub fn as_f64(&self) -> f64 {
+        match self.n {
+            N::PosInt(n) => n as f64,
+            N::NegInt(n) => n as f64,
+            N::Float(n) => n,
+        }
+    }
+
+    fn key(&self) -> Key {
+        match self.n {
+            N::PosInt(n) => Key::PosInt(n),
+            N::NegInt(n) => Key::NegInt(n),
+            N::Float(f) => {
+                if f.is_nan() {
+                    Key::NaN
+                } else if f.trunc() == f && f >= 0.0 && f < 18446744073709551616.0 {
+                    Key::PosInt(f as u64)
+                } else if f.trunc() == f && f < 0.0 && f >= -9223372036854775808.0 {
+                    Key::NegInt(f as i64)
+                } else {
+                    Key::Float(f.to_bits())
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Number {}
+
+impl Hash for Number {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.n {
+            N::PosInt(n) => fmt::Display::fmt(&n, f),
+            N::NegInt(n) => fmt::Display::fmt(&n, f),
+            N::Float(n) => fmt::Display::fmt(&n, f),
+        }
+    }
+}
+
+macro_rules! impl_from_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Number {
+                fn from(n: $ty) -> Number {
+                    Number { n: N::PosInt(n as u64) }
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_from_signed {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Number {
+                fn from(n: $ty) -> Number {
+                    if n < 0 {
+                        Number { n: N::NegInt(n as i64) }
+                    } else {
+                        Number { n: N::PosInt(n as u64) }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_unsigned!(u8, u16, u32, u64, usize);
+impl_from_signed!(i8, i16, i32, i64, isize);
+
+impl From<f32> for Number {
+    fn from(n: f32) -> Number {
+        Number { n: N::Float(n as f64) }
+    }
+}
+
+impl From<f64> for Number {
+    fn from(n: f64) -> Number {
+        Number { n: N::Float(n) }
+    }
+}
+
+impl ser::Serialize for Number {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        match self.n {
+            N::PosInt(n) => serializer.serialize_u64(n),
+            N::NegInt(n) => serializer.serialize_i64(n),
+            N::Float(n) => serializer.serialize_f64(n),
+        }
+    }
+}
+
+impl de::Deserialize for Number {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<Number, D::Error>
+        where D: de::Deserializer,
+    {
+        struct NumberVisitor;
+
+        impl de::Visitor for NumberVisitor {
+            type Value = Number;
+
+            #[inline]
+            fn visit_i64<E>(&mut self, value: i64) -> Result<Number, E> {
+                Ok(Number::from(value))
+            }
+
+            #[inline]
+            fn visit_u64<E>(&mut self, value: u64) -> Result<Number, E> {
+                Ok(Number::from(value))
+            }
+
+            #[inline]
+            fn visit_f64<E>(&mut self, value: f64) -> Result<Number, E> {
+                Ok(Number::from(value))
+            }
+        }
+
+        deserializer.deserialize(NumberVisitor)
+    }
+}
+
+#[doc(hidden)]
+pub const RAW_NUMBER_TOKEN: &'static str = "$__serde_hjson_RawNumber";
+
+/// A number that keeps the text it was written with, e.g. `1.50` or `1e3`.
+///
+/// Use a `RawNumber` field where the literal matters (e.g. for currency): the Hjson
+/// `Deserializer` keeps the text of the number and the Hjson `Serializer` writes it unchanged.
+/// A `Value` keeps such literals as well, in `Value::Number`. Arithmetic still has to go
+/// through `as_f64`, which converts the literal like any other number.
+///
+/// Other serializers see the number as a string, except `to_value` which gives a
+/// `Value::Number`, or a `Value::F64` if that is written the same way. Other deserializers produce it from a number value, a float is written the way
+/// the `Serializer` writes it and `NaN` or an infinity is rejected.
+///
+/// ```rust
+/// use serde_hjson::RawNumber;
+///
+/// let price: RawNumber = "1.50".parse().unwrap();
+/// assert_eq!(price.as_f64(), 1.5);
+/// assert_eq!(serde_hjson::to_string(&vec![price]).unwrap(), "[\n  1.50\n]");
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RawNumber {
+    text: String,
+}
+
+impl RawNumber {
+    /// Returns the number as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the value of the number.
+    pub fn as_f64(&self) -> f64 {
+        // every way of creating a `RawNumber` checks that the parser accepts its text
+        ParseNumber::new(self.text.bytes()).extended(true).parse(false).unwrap()
+    }
+
+    /// Returns the number if it is a decimal integer that fits in an `i128`.
+    pub fn as_i128(&self) -> Option<i128> {
+        self.text.parse().ok()
+    }
+
+    /// Returns the number if it is a decimal integer that fits in a `u128`.
+    pub fn as_u128(&self) -> Option<u128> {
+        self.text.parse().ok()
+    }
+}
+
+// serde 0.8 has no 128-bit integers, a `RawNumber` carries them unchanged, e.g. as map keys
+// with `Serializer::stringify_keys`
+
+impl From<i128> for RawNumber {
+    fn from(value: i128) -> RawNumber {
+        RawNumber { text: value.to_string() }
+    }
+}
+
+impl From<u128> for RawNumber {
+    fn from(value: u128) -> RawNumber {
+        RawNumber { text: value.to_string() }
+    }
+}
+
+impl str::FromStr for RawNumber {
+    type Err = Error;
+
+    /// Parses a number literal (extended numbers like `0xFF` are accepted), keeping its text.
+    /// Returns `ErrorCode::InvalidNumber` if `text` is not a number.
+    fn from_str(text: &str) -> Result<RawNumber, Error> {
+        let text = text.trim();
+        match ParseNumber::new(text.bytes()).extended(true).parse(false) {
+            Ok(_) => Ok(RawNumber { text: text.to_owned() }),
+            Err(_) => Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)),
+        }
+    }
+}
+
+impl fmt::Display for RawNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl ser::Serialize for RawNumber {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_NUMBER_TOKEN, &self.text)
+    }
+}
+
+impl de::Deserialize for RawNumber {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<RawNumber, D::Error>
+        where D: de::Deserializer,
+    {
+        struct RawNumberVisitor;
+
+        impl de::Visitor for RawNumberVisitor {
+            type Value = RawNumber;
+
+            #[inline]
+            fn visit_i64<E>(&mut self, value: i64) -> Result<RawNumber, E> {
+                Ok(RawNumber { text: value.to_string() })
+            }
+
+            #[inline]
+            fn visit_u64<E>(&mut self, value: u64) -> Result<RawNumber, E> {
+                Ok(RawNumber { text: value.to_string() })
+            }
+
+            #[inline]
+            fn visit_f64<E>(&mut self, value: f64) -> Result<RawNumber, E>
+                where E: de::Error,
+            {
+                if !value.is_finite() {
+                    return Err(E::invalid_value("expected a finite number"));
+                }
+                // written the way the `Serializer` would write the float
+                Ok(RawNumber { text: fmt_small(value, false) })
+            }
+
+            #[inline]
+            fn visit_str<E>(&mut self, value: &str) -> Result<RawNumber, E>
+                where E: de::Error,
+            {
+                value.parse().map_err(|_| E::invalid_value("expected a number"))
+            }
+        }
+
+        deserializer.deserialize_struct(RAW_NUMBER_TOKEN, &["number"], RawNumberVisitor)
+    }
+}
@@ -2,23 +2,62 @@
 //!
 //! This module provides for Hjson serialization with the type `Serializer`.
 
-use std::io;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem;
 use std::num::FpCategory;
-use std::fmt::{ Display, LowerExp };
+use std::str;
+use std::fmt::Display;
 
 use serde::ser;
+use super::de::from_str;
 use super::error::{Error, ErrorCode, Result};
 
-use super::util::ParseNumber;
+use super::util::{ParseNumber, encode_base64, fmt_small};
+use super::number::{Number, RAW_NUMBER_TOKEN};
+use super::value::{RawValue, Value, RAW_VALUE_TOKEN};
 
 use regex::Regex;
 
 /// A structure for serializing Rust values into Hjson.
 pub struct Serializer<W, F> {
-    writer: W,
+    writer: Output<W>,
     formatter: F,
+    skip_none_in_seq: bool,
+    skip_none_in_map: bool,
+    string_style: Option<StringStyle>,
+    inline: bool,
+    raw_str: bool,
+    raw_value: bool,
+    key_filter: Option<Box<KeyFilter>>,
+    number_format: Option<Box<NumberFormat>>,
+    key_order: Option<Box<KeyOrder>>,
+    quote_profile: QuoteProfile,
+    non_finite: NonFiniteMode,
+    bool_style: BoolStyle,
+    keep_float_point: bool,
+    force_quote_strings: bool,
+    stringify_keys: bool,
+    reject_multiline_keys: bool,
+    tuples_as_objects: bool,
+    max_depth: Option<usize>,
+    depth: usize,
+    entries: Vec<Entries>,
+    comments: HashMap<(&'static str, &'static str), String>,
 }
 
+/// Checks, and possibly renames, the keys of maps, see `Serializer::key_filter`.
+type KeyFilter = Fn(&str) -> Result<Cow<str>>;
+
+/// Writes numbers in a custom way, see `Serializer::number_format`.
+type NumberFormat = Fn(Number) -> Option<String>;
+
+/// Orders the members of maps by their keys, see `Serializer::sort_keys_by`.
+type KeyOrder = Fn(&str, &str) -> cmp::Ordering;
+
 impl<'a, W> Serializer<W, HjsonFormatter<'a>>
     where W: io::Write {
     /// Creates a new Hjson serializer.
@@ -36,24 +75,902 @@ impl<W, F> Serializer<W, F>
     #[inline]
     pub fn with_formatter(writer: W, formatter: F) -> Self {
         Serializer {
-            writer: writer,
+            writer: Output { inner: writer, buffers: Vec::new() },
             formatter: formatter,
+            skip_none_in_seq: false,
+            skip_none_in_map: false,
+            string_style: None,
+            inline: false,
+            raw_str: false,
+            raw_value: false,
+            key_filter: None,
+            number_format: None,
+            key_order: None,
+            quote_profile: QuoteProfile::Minimal,
+            non_finite: NonFiniteMode::Null,
+            bool_style: BoolStyle::TrueFalse,
+            keep_float_point: false,
+            force_quote_strings: false,
+            stringify_keys: false,
+            reject_multiline_keys: false,
+            tuples_as_objects: false,
+            max_depth: None,
+            depth: 0,
+            entries: Vec::new(),
+            comments: HashMap::new(),
         }
     }
 
+    /// Skip `None` elements of sequences instead of writing them as `null`.
+    ///
+    /// This only applies to sequences (e.g. `Vec<Option<T>>`), tuples keep their
+    /// `null` elements so that they can still be deserialized.
+    #[inline]
+    pub fn skip_none_in_seq(mut self, skip: bool) -> Self {
+        self.skip_none_in_seq = skip;
+        self
+    }
+
+    /// Omit map entries whose value is `None` instead of writing them as `key: null`.
+    ///
+    /// This only applies to maps (e.g. `BTreeMap<String, Option<T>>`), not to struct fields.
+    #[inline]
+    pub fn skip_none_in_map(mut self, skip: bool) -> Self {
+        self.skip_none_in_map = skip;
+        self
+    }
+
+    /// Select how `NaN` and the infinities are written, see `NonFiniteMode`.
+    #[inline]
+    pub fn non_finite(mut self, mode: NonFiniteMode) -> Self {
+        self.non_finite = mode;
+        self
+    }
+
+    /// Select how booleans are written, see `BoolStyle`.
+    #[inline]
+    pub fn bool_style(mut self, style: BoolStyle) -> Self {
+        self.bool_style = style;
+        self
+    }
+
+    /// Write floats without a fractional part with a `.0` suffix, e.g. `3.0` instead of `3`,
+    /// so that they can be told apart from integers.
+    #[inline]
+    pub fn keep_float_point(mut self, keep: bool) -> Self {
+        self.keep_float_point = keep;
+        self
+    }
+
+    /// Write every string value quoted, never quoteless or as a multiline string, also those
+    /// with a `StringStyle`. Keys are not affected.
+    #[inline]
+    pub fn force_quote_strings(mut self, force: bool) -> Self {
+        self.force_quote_strings = force;
+        self
+    }
+
+    /// Write map keys that are numbers, booleans, chars or unit enum variants (e.g. of a
+    /// `HashMap<char, T>`) as strings instead of failing with `ErrorCode::KeyMustBeAString`.
+    /// This includes `RawNumber` keys, e.g. for 128-bit integers.
+    #[inline]
+    pub fn stringify_keys(mut self, stringify: bool) -> Self {
+        self.stringify_keys = stringify;
+        self
+    }
+
+    /// Fail with `ErrorCode::MultilineKey` for map keys that contain a line break. Such keys
+    /// are always quoted, with the line break escaped, but are rarely intended.
+    #[inline]
+    pub fn reject_multiline_keys(mut self, reject: bool) -> Self {
+        self.reject_multiline_keys = reject;
+        self
+    }
+
+    /// Write tuples and tuple structs as objects with positional keys, e.g. `(1, "a")` as
+    /// `{ _0: 1, _1: a }` instead of `[1, "a"]`. Such objects cannot be read back into tuples.
+    #[inline]
+    pub fn tuples_as_objects(mut self, enabled: bool) -> Self {
+        self.tuples_as_objects = enabled;
+        self
+    }
+
+    /// Fail with `ErrorCode::DepthLimitExceeded` for values with arrays and objects nested
+    /// more than `depth` levels deep, instead of overflowing the stack. There is no limit by
+    /// default.
+    #[inline]
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Write `comment` as `#` comment lines above the field `field` of the struct `name`. The
+    /// names are those that are serialized, i.e. after a `#[serde(rename)]`. For struct
+    /// variants of an enum, `name` is the name of the variant.
+    ///
+    /// The doc comments of a struct cannot be read at runtime, but they can be registered here
+    /// to document a generated config file, e.g. `.field_comment("Config", "port", "The port
+    /// to listen on.")` writes `# The port to listen on.` above `port: 80`.
+    pub fn field_comment(mut self, name: &'static str, field: &'static str, comment: &str) -> Self {
+        self.comments.insert((name, field), comment.to_owned());
+        self
+    }
+
+    /// Select which strings are quoted, see `QuoteProfile`.
+    #[inline]
+    pub fn quote_profile(mut self, profile: QuoteProfile) -> Self {
+        self.quote_profile = profile;
+        self
+    }
+
+    /// Pass every map key through `filter` before it is written. The filter returns the key
+    /// to write, which may be transformed, or an error to abort serializing, e.g. to reject
+    /// reserved keys like `__proto__`.
+    ///
+    /// Struct fields and enum variants are not filtered, their names are known in advance.
+    ///
+    /// ```rust
+    /// extern crate serde;
+    /// extern crate serde_hjson;
+    ///
+    /// use std::borrow::Cow;
+    /// use std::collections::BTreeMap;
+    /// use serde::ser::{Error, Serialize};
+    ///
+    /// # fn main() {
+    ///
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new()).key_filter(|key| {
+    ///     if key == "__proto__" {
+    ///         Err(serde_hjson::Error::custom("reserved key"))
+    ///     } else {
+    ///         Ok(Cow::Borrowed(key))
+    ///     }
+    /// });
+    ///
+    /// let mut map = BTreeMap::new();
+    /// map.insert("__proto__", 1);
+    /// assert!(map.serialize(&mut ser).is_err());
+    /// # }
+    /// ```
+    #[inline]
+    pub fn key_filter<K>(mut self, filter: K) -> Self
+        where K: Fn(&str) -> Result<Cow<str>> + 'static {
+        self.key_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Write numbers with `format` instead of the default notation, e.g. with a fixed number of
+    /// decimals. `format` gets every integer and float (`-0.0` as `0.0`) and returns the text to
+    /// write, or `None` to write the number as usual. The text is written as it is, so it must
+    /// be a single line Hjson value; a number with thousands separators, for example, has to
+    /// be quoted and then reads back as a string. Keys are not affected.
+    ///
+    /// ```rust
+    /// extern crate serde;
+    /// extern crate serde_hjson;
+    ///
+    /// use serde::ser::Serialize;
+    ///
+    /// # fn main() {
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new()).number_format(|number| {
+    ///     if number.is_f64() { Some(format!("{:.2}", number.as_f64())) } else { None }
+    /// });
+    /// vec![1.5, 2.0].serialize(&mut ser).unwrap();
+    /// assert_eq!(ser.into_inner(), b"[\n  1.50\n  2.00\n]");
+    /// # }
+    /// ```
+    #[inline]
+    pub fn number_format<N>(mut self, format: N) -> Self
+        where N: Fn(Number) -> Option<String> + 'static {
+        self.number_format = Some(Box::new(format));
+        self
+    }
+
+    /// Write the members of maps in the order of their keys given by `compare`, e.g. with
+    /// the important keys first. The members are buffered until the map is complete and then
+    /// sorted, keeping members that compare as equal in their original order. `compare` gets
+    /// the keys before a `key_filter` renames them. Structs keep the order of their fields.
+    ///
+    /// ```rust
+    /// extern crate serde;
+    /// extern crate serde_hjson;
+    ///
+    /// use std::collections::HashMap;
+    /// use serde::ser::Serialize;
+    ///
+    /// # fn main() {
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new()).sort_keys_by(|a, b| a.cmp(b));
+    /// let mut map = HashMap::new();
+    /// map.insert("b", 2);
+    /// map.insert("a", 1);
+    /// map.serialize(&mut ser).unwrap();
+    /// assert_eq!(ser.into_inner(), b"{\n  a: 1\n  b: 2\n}");
+    /// # }
+    /// ```
+    #[inline]
+    pub fn sort_keys_by<C>(mut self, compare: C) -> Self
+        where C: Fn(&str, &str) -> cmp::Ordering + 'static {
+        self.key_order = Some(Box::new(compare));
+        self
+    }
+
     /// Unwrap the `Writer` from the `Serializer`.
     #[inline]
     pub fn into_inner(self) -> W {
-        self.writer
+        self.writer.inner
+    }
+
+    /// Flushes the writer. The output is written as it is produced (except for the parts held
+    /// back, e.g. for `HjsonFormatter::align_keys`), but neither the serializer nor
+    /// `to_writer` flush at the end, see also `Formatter::flush`.
+    #[inline]
+    pub fn flush(&mut self) -> Result<()> {
+        io::Write::flush(&mut self.writer).map_err(From::from)
+    }
+
+    /// Starts writing an object whose members are added one by one with
+    /// `ObjectSerializer::field`, without building a map first. The members are written as
+    /// those of a map, so the map options (e.g. `skip_none_in_map`) apply.
+    ///
+    /// ```rust
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new());
+    /// {
+    ///     let mut object = ser.serialize_object().unwrap();
+    ///     object.field("name", &"test").unwrap();
+    ///     object.field("list", &vec![1, 2]).unwrap();
+    ///     object.finish().unwrap();
+    /// }
+    /// assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+    ///            "{\n  name: test\n  list:\n  [\n    1\n    2\n  ]\n}");
+    /// ```
+    pub fn serialize_object(&mut self) -> Result<ObjectSerializer<W, F>> {
+        let state = try!(ser::Serializer::serialize_map(self, None));
+        Ok(ObjectSerializer { ser: self, state: state })
+    }
+
+    /// Starts writing an array whose elements are added one by one with
+    /// `ArraySerializer::element`. Unlike with `serialize_seq`, the caller can see and reset
+    /// the state that decides whether a separator is written before the next element (the
+    /// `first` argument of `Formatter::comma`), e.g. for a formatter that writes commas.
+    /// The array is never written on one line, see `Formatter::compacts_arrays_of_primitives`.
+    ///
+    /// ```rust
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new());
+    /// {
+    ///     let mut array = ser.serialize_array().unwrap();
+    ///     assert!(array.is_first());
+    ///     array.element(&1).unwrap();
+    ///     array.element(&"two").unwrap();
+    ///     assert!(!array.is_first());
+    ///     array.finish().unwrap();
+    /// }
+    /// assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  1\n  two\n]");
+    /// ```
+    pub fn serialize_array(&mut self) -> Result<ArraySerializer<W, F>> {
+        let state = try!(self.start_array(false));
+        Ok(ArraySerializer { ser: self, state: state })
+    }
+
+    /// Writes an object from an iterator of key-value pairs, e.g. computed or filtered
+    /// members, without collecting them into a map first. As with `serialize_object`, the
+    /// map options apply.
+    ///
+    /// ```rust
+    /// let pairs = vec![("a", 1), ("b", 2), ("c", 3)];
+    /// let mut ser = serde_hjson::Serializer::new(Vec::new());
+    /// ser.serialize_entries(pairs.into_iter().filter(|&(_, v)| v != 2)).unwrap();
+    /// assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a: 1\n  c: 3\n}");
+    /// ```
+    pub fn serialize_entries<I, K, V>(&mut self, entries: I) -> Result<()>
+        where I: IntoIterator<Item = (K, V)>,
+              K: ser::Serialize,
+              V: ser::Serialize,
+    {
+        let mut state = try!(ser::Serializer::serialize_map(self, None));
+        for (key, value) in entries {
+            try!(ser::Serializer::serialize_map_key(self, &mut state, key));
+            try!(ser::Serializer::serialize_map_value(self, &mut state, value));
+        }
+        ser::Serializer::serialize_map_end(self, state)
+    }
+
+    // Serializes `value` as the next entry of the innermost array or object, after the key
+    // that `serialize_key` holds back for a member. Nothing is written if the value is `None`
+    // and `skip_none` is set.
+    fn serialize_entry<T>(&mut self, value: T, skip_none: bool) -> Result<()>
+        where T: ser::Serialize,
+    {
+        {
+            let entries = self.entries.last_mut().unwrap();
+            entries.len += 1;
+            entries.pending = true;
+            entries.skip_none = skip_none;
+        }
+        let inline = self.inline;
+        let mut res = value.serialize(&mut *self);
+        if res.is_ok() && self.entries.last().unwrap().pending {
+            // the value did not write anything
+            res = self.begin_value(Shape::Short);
+        }
+        self.inline = inline;
+
+        let (start, key) = {
+            let entries = self.entries.last_mut().unwrap();
+            entries.pending = false;
+            (mem::replace(&mut entries.start, Start::Written), entries.key.take())
+        };
+        match start {
+            Start::Written => {
+                try!(res);
+                self.flush_point()
+            }
+            Start::Skipped => res,
+            Start::Held(text) => {
+                let value = self.writer.buffers.pop().unwrap();
+                try!(res);
+                match self.entries.last_mut().unwrap().layout {
+                    Layout::Compact { ref mut elements, .. } => elements.push((text, value)),
+                    Layout::Rows { ref mut rows, .. } => rows.push(Row { key: key.unwrap(), value: value }),
+                    _ => unreachable!(),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Starts the pending entry of the innermost array or object once the `shape` of its value
+    // is known, so that the layout can depend on the value without serializing it twice. It
+    // opens the collection if nothing was written yet and writes the separator and key, or
+    // holds the entry back as the `Layout` requires. Every value calls this before it writes
+    // anything, but only the entry itself finds it pending.
+    fn begin_value(&mut self, shape: Shape) -> Result<()> {
+        let entries = match self.entries.last_mut() {
+            Some(entries) if entries.pending => entries,
+            _ => return Ok(()),
+        };
+        entries.pending = false;
+        if shape == Shape::None && entries.skip_none {
+            entries.start = Start::Skipped;
+            return Ok(());
+        }
+        let writer = &mut self.writer;
+        let formatter = &mut self.formatter;
+
+        match entries.layout {
+            Layout::Compact { .. } if shape == Shape::None || shape == Shape::Short => {
+                writer.buffers.push(Vec::new());
+                entries.start = Start::Held(None);
+                return Ok(());
+            }
+            Layout::Compact { .. } => {
+                // the array is written on lines after all, starting with the elements so far
+                if let Layout::Compact { elements, .. } = mem::replace(&mut entries.layout, Layout::Lines) {
+                    try!(formatter.begin_array(writer));
+                    entries.open = true;
+                    for (_, element) in elements {
+                        try!(formatter.comma(writer, entries.first));
+                        entries.first = false;
+                        try!(writer.write_all(&element));
+                    }
+                }
+            }
+            Layout::Single if shape != Shape::Lines && entries.key.as_ref().unwrap().comment.is_none() => {
+                let key = entries.key.take().unwrap();
+                try!(formatter.start_value(writer));
+                try!(writer.write_all(b"{ "));
+                try!(writer.write_all(&key.text));
+                try!(formatter.colon(writer));
+                try!(formatter.start_value(writer));
+                self.inline = true;
+                entries.layout = Layout::Collapsed;
+                entries.open = true;
+                return Ok(());
+            }
+            Layout::Single => entries.layout = Layout::Lines,
+            Layout::Rows { .. } => {
+                if !entries.open {
+                    try!(formatter.begin_object(writer));
+                    entries.open = true;
+                }
+                // the key stays with the entry, it is written with the rows
+                writer.buffers.push(Vec::new());
+                entries.start = Start::Held(None);
+                return formatter.colon(writer);
+            }
+            Layout::Lines | Layout::Collapsed => {}
+        }
+
+        if !entries.open {
+            try!(if entries.object { formatter.begin_object(writer) } else { formatter.begin_array(writer) });
+            entries.open = true;
+        }
+        try!(formatter.comma(writer, entries.first));
+        entries.first = false;
+        if let Some(key) = entries.key.take() {
+            if let Some(ref comment) = key.comment {
+                try!(write_comment(writer, formatter, comment));
+            }
+            try!(writer.write_all(&key.text));
+            try!(formatter.colon(writer));
+        }
+        Ok(())
+    }
+
+    // Writes `number` with the `number_format`, returns false if there is none or it leaves
+    // the number to the default notation.
+    fn write_formatted_number(&mut self, number: Number) -> Result<bool> {
+        let text = match self.number_format {
+            Some(ref format) => format(number),
+            None => return Ok(false),
+        };
+        match text {
+            Some(text) => {
+                try!(self.writer.write_all(text.as_bytes()));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Lets the formatter flush after an element or member, unless the output is held back.
+    fn flush_point(&mut self) -> Result<()> {
+        if self.writer.buffers.is_empty() {
+            self.formatter.flush(&mut self.writer)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Counts an array or object that is started, see `max_depth`.
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        match self.max_depth {
+            Some(max) if self.depth > max => Err(Error::Syntax(ErrorCode::DepthLimitExceeded, 0, 0, 0)),
+            _ => Ok(()),
+        }
+    }
+
+    // Starts an array, which is held back to be written on one line if `compact` is set.
+    fn start_array(&mut self, compact: bool) -> Result<State> {
+        try!(self.begin_value(Shape::Lines));
+        try!(self.enter());
+        let layout = if compact {
+            // the space after a colon, if any, is written with the array
+            let mut prefix = Vec::new();
+            try!(self.formatter.start_value(&mut prefix));
+            Layout::Compact { prefix: prefix, elements: Vec::new() }
+        } else {
+            Layout::Lines
+        };
+        self.entries.push(Entries::new(false, layout));
+        Ok(State(()))
+    }
+
+    // Starts an object, whose members are sorted with the `key_order` if `sorted` is set.
+    fn start_object(&mut self, len: Option<usize>, sorted: bool) -> Result<State> {
+        try!(self.begin_value(Shape::Lines));
+        try!(self.enter());
+        let layout = if len == Some(1) && self.formatter.collapses_single_member() {
+            Layout::Single
+        } else if sorted || self.formatter.aligns_keys() {
+            Layout::Rows { rows: Vec::new(), sorted: sorted }
+        } else {
+            Layout::Lines
+        };
+        self.entries.push(Entries::new(true, layout));
+        Ok(State(()))
+    }
+
+    // Closes the innermost array or object, which is written as `[]` or `{}` if none of its
+    // entries was.
+    fn end_entries(&mut self) -> Result<()> {
+        let entries = self.entries.pop().unwrap();
+        self.depth -= 1;
+        match entries.layout {
+            Layout::Compact { prefix, elements } => self.write_compact(prefix, elements),
+            _ if !entries.open => {
+                try!(self.formatter.start_value(&mut self.writer));
+                self.writer.write_all(if entries.object { b"{}" } else { b"[]" }).map_err(From::from)
+            }
+            Layout::Collapsed => self.writer.write_all(b" }").map_err(From::from),
+            Layout::Rows { rows, sorted } => {
+                try!(self.write_rows(rows, sorted));
+                self.formatter.end_object(&mut self.writer)
+            }
+            _ if entries.object => self.formatter.end_object(&mut self.writer),
+            _ => self.formatter.end_array(&mut self.writer),
+        }
+    }
+
+    // Holds back the key of an object member, with the `comment` to write above it, until its
+    // value starts. The options for maps only apply if `map_key` is set.
+    fn serialize_key<T>(&mut self, key: T, map_key: bool, comment: Option<String>) -> Result<()>
+        where T: ser::Serialize,
+    {
+        let filter = if map_key { self.key_filter.as_ref().map(|f| &**f) } else { None };
+        let sorted = match self.entries.last().unwrap().layout {
+            Layout::Rows { sorted, .. } => sorted,
+            _ => false,
+        };
+        let mut text = Vec::new();
+        let name = {
+            let mut key_ser = MapKeySerializer::new(&mut text, &self.formatter, filter,
+                                                    self.stringify_keys, self.reject_multiline_keys);
+            // the `key_order` compares the keys as they are before the `key_filter`
+            if sorted {
+                key_ser.name = Some(String::new());
+            }
+            try!(key.serialize(&mut key_ser));
+            key_ser.name.unwrap_or_else(String::new)
+        };
+        self.entries.last_mut().unwrap().key = Some(Key { text: text, comment: comment, name: name });
+        Ok(())
+    }
+
+    fn serialize_tuple_start(&mut self, len: usize) -> Result<State> {
+        if self.tuples_as_objects {
+            self.start_object(Some(len), false)
+        } else {
+            ser::Serializer::serialize_seq(self, Some(len))
+        }
+    }
+
+    fn serialize_tuple_member<T>(&mut self, value: T) -> Result<()>
+        where T: ser::Serialize,
+    {
+        if self.tuples_as_objects {
+            let index = self.entries.last().unwrap().len;
+            try!(self.serialize_key(&format!("_{}", index)[..], false, None));
+        }
+        self.serialize_entry(value, false)
+    }
+
+    // Writes an array that was held back by `compacts_arrays_of_primitives` on one line,
+    // after the `prefix` the formatter wrote for it.
+    fn write_compact(&mut self, prefix: Vec<u8>, elements: Vec<(Option<String>, Vec<u8>)>) -> Result<()> {
+        try!(self.writer.write_all(&prefix));
+        try!(self.writer.write_all(b"["));
+        for (i, (text, value)) in elements.into_iter().enumerate() {
+            if i > 0 {
+                try!(self.writer.write_all(b", "));
+            }
+            match text {
+                // a quoteless string would run on to the end of the line
+                Some(text) => try!(escape_bytes_with(&mut self.writer, &self.formatter, text.as_bytes())),
+                None => try!(self.writer.write_all(&value)),
+            }
+        }
+        self.writer.write_all(b"]").map_err(From::from)
+    }
+
+    // Writes the members that were held back as rows, in the order of the `key_order` if
+    // `sorted` is set, and with their values in one column if the formatter aligns keys.
+    fn write_rows(&mut self, mut rows: Vec<Row>, sorted: bool) -> Result<()> {
+        if sorted {
+            let compare = self.key_order.as_ref().unwrap();
+            rows.sort_by(|a, b| compare(&a.key.name, &b.key.name));
+        }
+        let align = self.formatter.aligns_keys();
+        let width = rows.iter().map(|row| key_width(&row.key.text)).max().unwrap_or(0);
+
+        for (i, row) in rows.into_iter().enumerate() {
+            try!(self.formatter.comma(&mut self.writer, i == 0));
+            if let Some(ref comment) = row.key.comment {
+                try!(write_comment(&mut self.writer, &mut self.formatter, comment));
+            }
+            try!(self.writer.write_all(&row.key.text));
+
+            // the value follows the colon (or `=`) unless it starts on a new line
+            let value = row.value;
+            let colon = value.iter().take(2).position(|&ch| ch == b':' || ch == b'=').map_or(0, |i| i + 1);
+            try!(self.writer.write_all(&value[..colon]));
+            if align && value.get(colon) != Some(&b'\n') {
+                try!(indent(&mut self.writer, width - key_width(&row.key.text), b" "));
+            }
+            try!(self.writer.write_all(&value[colon..]));
+        }
+        Ok(())
     }
 }
 
-#[doc(hidden)]
+// The width of a key as it is written.
+fn key_width(key: &[u8]) -> usize {
+    String::from_utf8_lossy(key).chars().count()
+}
+
+// Writes the lines of `comment` as `#` comments, each followed by a new line at the current
+// indentation.
+fn write_comment<W, F>(wr: &mut W, formatter: &mut F, comment: &str) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    for line in comment.lines() {
+        let line = line.trim_right();
+        try!(wr.write_all(if line.is_empty() { b"#" } else { b"# " }));
+        try!(wr.write_all(line.as_bytes()));
+        try!(formatter.newline(wr, 0));
+    }
+    Ok(())
+}
+
+/// The writer of a `Serializer`, which can hold back output, e.g. the members of an object
+/// whose keys are aligned.
+struct Output<W> {
+    inner: W,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl<W> io::Write for Output<W>
+    where W: io::Write {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.buffers.last_mut() {
+            Some(buffer) => buffer.write(buf),
+            None => self.inner.write(buf),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the members of an object one at a time, see `Serializer::serialize_object`.
+pub struct ObjectSerializer<'a, W: 'a, F: 'a> {
+    ser: &'a mut Serializer<W, F>,
+    state: State,
+}
+
+impl<'a, W, F> ObjectSerializer<'a, W, F>
+    where W: io::Write,
+          F: Formatter {
+    /// Writes a member of the object.
+    pub fn field<T>(&mut self, key: &str, value: &T) -> Result<()>
+        where T: ser::Serialize,
+    {
+        try!(ser::Serializer::serialize_map_key(self.ser, &mut self.state, key));
+        ser::Serializer::serialize_map_value(self.ser, &mut self.state, value)
+    }
+
+    /// Closes the object.
+    pub fn finish(self) -> Result<()> {
+        ser::Serializer::serialize_map_end(self.ser, self.state)
+    }
+}
+
+/// Writes the elements of an array one at a time, see `Serializer::serialize_array`.
+pub struct ArraySerializer<'a, W: 'a, F: 'a> {
+    ser: &'a mut Serializer<W, F>,
+    state: State,
+}
+
+impl<'a, W, F> ArraySerializer<'a, W, F>
+    where W: io::Write,
+          F: Formatter {
+    /// Writes an element of the array. As with `serialize_seq`, `skip_none_in_seq` applies.
+    pub fn element<T>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize,
+    {
+        ser::Serializer::serialize_seq_elt(self.ser, &mut self.state, value)
+    }
+
+    /// Returns true if the next element is written as the first one, without a separator.
+    pub fn is_first(&self) -> bool {
+        self.ser.entries.last().unwrap().first
+    }
+
+    /// Writes the next element as if it were the first one, without a separator.
+    pub fn reset_first(&mut self) {
+        self.ser.entries.last_mut().unwrap().first = true;
+    }
+
+    /// Closes the array.
+    pub fn finish(self) -> Result<()> {
+        ser::Serializer::serialize_seq_end(self.ser, self.state)
+    }
+}
+
+/// The kind of a value, as far as the layout of what surrounds it depends on it.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Shape {
+    /// `None`.
+    None,
+    /// A number, boolean, null or a single-line string of up to `COMPACT_STRING_LEN`
+    /// characters.
+    Short,
+    /// A longer single-line string.
+    Line,
+    /// A collection, a multiline string or a raw value.
+    Lines,
+}
+
+impl Shape {
+    fn of_str(value: &str) -> Self {
+        if value.contains('\n') {
+            Shape::Lines
+        } else if value.chars().count() <= COMPACT_STRING_LEN {
+            Shape::Short
+        } else {
+            Shape::Line
+        }
+    }
+}
+
+/// An array or object that is being serialized, see `Serializer::begin_value`. Its entries
+/// are written as they start or held back as the `Layout` requires; either way it is only
+/// opened right before the first entry that is written, so without one it is `[]` or `{}`.
+struct Entries {
+    /// Whether this is an object rather than an array.
+    object: bool,
+    layout: Layout,
+    /// Whether the collection was opened.
+    open: bool,
+    /// Whether the next entry is written without a separator, see `Formatter::comma`.
+    first: bool,
+    /// The number of entries so far, including skipped ones.
+    len: usize,
+    /// The struct whose fields the members are, for `Serializer::field_comment`.
+    name: Option<&'static str>,
+    /// The key of the next member, held back until its value starts.
+    key: Option<Key>,
+    /// Whether the next entry is yet to start.
+    pending: bool,
+    /// Whether the next entry is skipped if it is `None`.
+    skip_none: bool,
+    /// How the next entry started.
+    start: Start,
+}
+
+impl Entries {
+    fn new(object: bool, layout: Layout) -> Self {
+        Entries {
+            object: object,
+            layout: layout,
+            open: false,
+            first: true,
+            len: 0,
+            name: None,
+            key: None,
+            pending: false,
+            skip_none: false,
+            start: Start::Written,
+        }
+    }
+}
+
+/// How the entries of an array or object are written.
+enum Layout {
+    /// Each on a line of its own as it starts.
+    Lines,
+    /// The single member of an object, on one line as `{ key: value }` unless the value spans
+    /// lines or the member has a comment.
+    Single,
+    /// A `Single` member that was written on one line, the object is closed with ` }`.
+    Collapsed,
+    /// The elements of an array that is held back by `compacts_arrays_of_primitives`. They
+    /// are buffered while they are short, a string is also kept to be quoted if the array
+    /// fits on one line. `prefix` is what the formatter writes before the array, e.g. the
+    /// space after a colon.
+    Compact { prefix: Vec<u8>, elements: Vec<(Option<String>, Vec<u8>)> },
+    /// The members of an object, held back as rows to line up their values, and to sort them
+    /// with the `key_order` if `sorted` is set.
+    Rows { rows: Vec<Row>, sorted: bool },
+}
+
+/// The key of an object member, see `Serializer::serialize_key`.
+struct Key {
+    text: Vec<u8>,
+    /// The comment to write above the key.
+    comment: Option<String>,
+    /// The key before the `key_filter`, if it is sorted.
+    name: String,
+}
+
+/// An object member that is held back, see `Layout::Rows`.
+struct Row {
+    key: Key,
+    /// The colon (or `=`) followed by the value.
+    value: Vec<u8>,
+}
+
+/// How `Serializer::begin_value` started an entry.
 #[derive(Eq, PartialEq)]
-pub enum State {
-    Empty,
-    First,
-    Rest,
+enum Start {
+    /// It wrote the separator and key, or the start of a collapsed object, and the value
+    /// follows.
+    Written,
+    /// Nothing, the value is a `None` that is skipped.
+    Skipped,
+    /// Nothing, the value goes to a buffer that is kept in the `Layout`. A string is also kept
+    /// for `Layout::Compact`.
+    Held(Option<String>),
+}
+
+/// The state of an array or object, which the `Serializer` keeps itself.
+#[doc(hidden)]
+pub struct State(());
+
+/// Selects which string values are written without quotes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QuoteProfile {
+    /// Only quote strings that Hjson would otherwise read as something else, e.g. `"true"`
+    /// or `"12"`. This is the default.
+    Minimal,
+    /// Also quote strings that a more lenient parser (e.g. for YAML) could read as a number,
+    /// boolean or null, such as `"yes"`, `"Off"`, `"~"`, `"0o17"`, `".5"` or `"null value"`.
+    /// Use this for files that are not only read by Hjson parsers.
+    Safe,
+}
+
+/// Selects how floats that are `NaN` or infinite are written, as Hjson has no numbers for
+/// them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NonFiniteMode {
+    /// Write them as `null`, which reads back as `None` or a missing value. This is the
+    /// default.
+    Null,
+    /// Write the bare words `NaN`, `Infinity` and `-Infinity`, as JSON5 does. Hjson parsers
+    /// read these as strings, except for `Deserializer::allow_non_finite`. Strings with
+    /// these values are quoted.
+    Token,
+}
+
+/// Selects how booleans are written, for configuration files that use other words for them.
+/// Hjson parsers read `yes`, `no`, `on` and `off` as strings, except for
+/// `Deserializer::allow_bool_words`; strings with the words of the chosen style are quoted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BoolStyle {
+    /// Write `true` and `false`. This is the default.
+    TrueFalse,
+    /// Write `yes` and `no`.
+    YesNo,
+    /// Write `on` and `off`.
+    OnOff,
+}
+
+impl BoolStyle {
+    fn token(&self, value: bool) -> &'static str {
+        match (*self, value) {
+            (BoolStyle::TrueFalse, true) => "true",
+            (BoolStyle::TrueFalse, false) => "false",
+            (BoolStyle::YesNo, true) => "yes",
+            (BoolStyle::YesNo, false) => "no",
+            (BoolStyle::OnOff, true) => "on",
+            (BoolStyle::OnOff, false) => "off",
+        }
+    }
+}
+
+/// The way a string is written in Hjson.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StringStyle {
+    /// A quoteless string, e.g. `key: value`.
+    Quoteless,
+    /// A quoted string, e.g. `key: "value"`.
+    Quoted,
+    /// A multiline string, e.g. `key: '''value'''`.
+    Multiline,
+}
+
+impl StringStyle {
+    #[doc(hidden)]
+    pub fn token(&self) -> &'static str {
+        match *self {
+            StringStyle::Quoteless => "$__serde_hjson_quoteless",
+            StringStyle::Quoted => "$__serde_hjson_quoted",
+            StringStyle::Multiline => "$__serde_hjson_multiline",
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn from_token(token: &str) -> Option<StringStyle> {
+        match token {
+            "$__serde_hjson_quoteless" => Some(StringStyle::Quoteless),
+            "$__serde_hjson_quoted" => Some(StringStyle::Quoted),
+            "$__serde_hjson_multiline" => Some(StringStyle::Multiline),
+            _ => None,
+        }
+    }
 }
 
 impl<W, F> ser::Serializer for Serializer<W, F>
@@ -71,108 +988,173 @@ impl<W, F> ser::Serializer for Serializer<W, F>
 
     #[inline]
     fn serialize_bool(&mut self, value: bool) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
-        if value {
-            self.writer.write_all(b"true").map_err(From::from)
-        } else {
-            self.writer.write_all(b"false").map_err(From::from)
-        }
+        self.writer.write_all(self.bool_style.token(value).as_bytes()).map_err(From::from)
     }
 
     #[inline]
     fn serialize_isize(&mut self, value: isize) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i8(&mut self, value: i8) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i16(&mut self, value: i16) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i32(&mut self, value: i32) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i64(&mut self, value: i64) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_usize(&mut self, value: usize) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_u8(&mut self, value: u8) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_u16(&mut self, value: u16) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_u32(&mut self, value: u32) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_u64(&mut self, value: u64) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
         write!(&mut self.writer, "{}", value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_f32(&mut self, value: f32) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
-        fmt_f32_or_null(&mut self.writer, if value == -0f32 { 0f32 } else { value }).map_err(From::from)
+        let value = if value == -0f32 { 0f32 } else { value };
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
+        fmt_f32_or_null(&mut self.writer, value, self.keep_float_point, self.non_finite)
     }
 
     #[inline]
     fn serialize_f64(&mut self, value: f64) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
-        fmt_f64_or_null(&mut self.writer, if value == -0f64 { 0f64 } else { value }).map_err(From::from)
+        let value = if value == -0f64 { 0f64 } else { value };
+        if try!(self.write_formatted_number(Number::from(value))) { return Ok(()); }
+        fmt_f64_or_null(&mut self.writer, value, self.keep_float_point, self.non_finite)
     }
 
     #[inline]
     fn serialize_char(&mut self, value: char) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
-        escape_char(&mut self.writer, value).map_err(From::from)
+        escape_char(&mut self.writer, &self.formatter, value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_str(&mut self, value: &str) -> Result<()> {
-        quote_str(&mut self.writer, &mut self.formatter, value).map_err(From::from)
+        if self.entries.last().map_or(false, |entries| entries.pending) {
+            try!(self.begin_value(Shape::of_str(value)));
+            let entries = self.entries.last_mut().unwrap();
+            if let Layout::Compact { .. } = entries.layout {
+                if entries.start == Start::Held(None) {
+                    // the array may yet be written on one line, where the string is quoted
+                    entries.start = Start::Held(Some(value.to_owned()));
+                }
+            }
+        }
+        if self.raw_value {
+            if needs_escapes(&self.formatter, value) {
+                // the fragment can only be escaped after parsing it
+                self.raw_value = false;
+                let value: Value = try!(from_str(value));
+                return ser::Serialize::serialize(&value, self);
+            }
+            return raw_fragment(&mut self.writer, &mut self.formatter, value);
+        }
+        if self.inline || self.formatter.writes_one_line() || self.formatter.escapes_non_ascii() ||
+           self.force_quote_strings || needs_escapes(&self.formatter, value) {
+            // a quoteless string would run on to the end of the line, and the escapes are
+            // only written in quoted strings
+            self.string_style = None;
+            try!(self.formatter.start_value(&mut self.writer));
+            return escape_bytes_with(&mut self.writer, &self.formatter, value.as_bytes());
+        }
+        if self.raw_str {
+            debug_assert!(!needs_quotes(value), "RawStr {:?} must be quoted", value);
+            try!(self.formatter.start_value(&mut self.writer));
+            return self.writer.write_all(value.as_bytes()).map_err(From::from);
+        }
+        if self.quote_profile == QuoteProfile::Safe && is_ambiguous(value) ||
+           self.non_finite == NonFiniteMode::Token && is_non_finite_token(value) ||
+           self.bool_style != BoolStyle::TrueFalse &&
+           (value == self.bool_style.token(true) || value == self.bool_style.token(false)) {
+            self.string_style = None;
+            try!(self.formatter.start_value(&mut self.writer));
+            return escape_bytes_with(&mut self.writer, &self.formatter, value.as_bytes());
+        }
+        match self.string_style.take() {
+            Some(style) => styled_str(&mut self.writer, &mut self.formatter, value, style),
+            None => quote_str(&mut self.writer, &mut self.formatter, value).map_err(From::from),
+        }
     }
 
     #[inline]
+    /// Serializes bytes (e.g. a `serde::bytes::ByteBuf`) as a base64 string.
     fn serialize_bytes(&mut self, value: &[u8]) -> Result<()> {
-        let mut state = try!(self.serialize_seq(Some(value.len())));
-        for byte in value {
-            try!(self.serialize_seq_elt(&mut state, byte));
-        }
-        self.serialize_seq_end(state)
+        self.serialize_str(&encode_base64(value))
     }
 
     #[inline]
     fn serialize_unit(&mut self) -> Result<()> {
+        try!(self.begin_value(Shape::Short));
         try!(self.formatter.start_value(&mut self.writer));
         self.writer.write_all(b"null").map_err(From::from)
     }
@@ -193,14 +1175,36 @@ impl<W, F> ser::Serializer for Serializer<W, F>
     }
 
     /// Serialize newtypes without an object wrapper.
+    ///
+    /// The `StringStyle` tokens select the style of the wrapped string.
     #[inline]
     fn serialize_newtype_struct<T>(
         &mut self,
-        _name: &'static str,
+        name: &'static str,
         value: T
     ) -> Result<()>
         where T: ser::Serialize,
     {
+        if let Some(style) = StringStyle::from_token(name) {
+            self.string_style = Some(style);
+            let res = value.serialize(self);
+            self.string_style = None;
+            return res;
+        }
+        if name == RAW_STR_TOKEN {
+            self.raw_str = true;
+            let res = value.serialize(self);
+            self.raw_str = false;
+            return res;
+        }
+        if name == RAW_VALUE_TOKEN || name == RAW_NUMBER_TOKEN {
+            // a raw fragment may well end in a comment
+            try!(self.begin_value(if name == RAW_NUMBER_TOKEN { Shape::Short } else { Shape::Lines }));
+            self.raw_value = true;
+            let res = value.serialize(self);
+            self.raw_value = false;
+            return res;
+        }
         value.serialize(self)
     }
 
@@ -214,56 +1218,57 @@ impl<W, F> ser::Serializer for Serializer<W, F>
     ) -> Result<()>
         where T: ser::Serialize,
     {
-        try!(self.formatter.open(&mut self.writer, b'{'));
+        try!(self.begin_value(Shape::Lines));
+        try!(self.enter());
+        try!(self.formatter.begin_object(&mut self.writer));
         try!(self.formatter.comma(&mut self.writer, true));
-        try!(escape_key(&mut self.writer, variant));
+        try!(escape_key_with(&mut self.writer, &self.formatter, variant));
         try!(self.formatter.colon(&mut self.writer));
-        try!(value.serialize(self));
-        self.formatter.close(&mut self.writer, b'}')
+        try!(value.serialize(&mut *self));
+        self.depth -= 1;
+        self.formatter.end_object(&mut self.writer)
     }
 
     #[inline]
     fn serialize_none(&mut self) -> Result<()> {
+        try!(self.begin_value(Shape::None));
+        if self.entries.last().map_or(false, |entries| entries.start == Start::Skipped) {
+            return Ok(());
+        }
         self.serialize_unit()
     }
 
     #[inline]
     fn serialize_some<V>(&mut self, value: V) -> Result<()>
         where V: ser::Serialize {
+        // not `None`, even if `value` is
+        if let Some(entries) = self.entries.last_mut() {
+            entries.skip_none = false;
+        }
         value.serialize(self)
     }
 
     #[inline]
-    fn serialize_seq(&mut self, len: Option<usize>) -> Result<State> {
-        if len == Some(0) {
-            try!(self.formatter.start_value(&mut self.writer));
-            try!(self.writer.write_all(b"[]"));
-            Ok(State::Empty)
-        } else {
-            try!(self.formatter.open(&mut self.writer, b'['));
-            Ok(State::First)
-        }
+    fn serialize_seq(&mut self, _len: Option<usize>) -> Result<State> {
+        let compact = self.formatter.compacts_arrays_of_primitives();
+        self.start_array(compact)
     }
 
     #[inline]
     fn serialize_seq_elt<T: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         value: T
     ) -> Result<()>
         where T: ser::Serialize,
     {
-        try!(self.formatter.comma(&mut self.writer, *state == State::First));
-        *state = State::Rest;
-        value.serialize(self)
+        let skip_none = self.skip_none_in_seq;
+        self.serialize_entry(value, skip_none)
     }
 
     #[inline]
-    fn serialize_seq_end(&mut self, state: State) -> Result<()> {
-        match state {
-            State::Empty => Ok(()),
-            _ => self.formatter.close(&mut self.writer, b']'),
-        }
+    fn serialize_seq_end(&mut self, _state: State) -> Result<()> {
+        self.end_entries()
     }
 
     #[inline]
@@ -273,21 +1278,21 @@ impl<W, F> ser::Serializer for Serializer<W, F>
 
     #[inline]
     fn serialize_tuple(&mut self, len: usize) -> Result<State> {
-        self.serialize_seq(Some(len))
+        self.serialize_tuple_start(len)
     }
 
     #[inline]
     fn serialize_tuple_elt<T: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         value: T
     ) -> Result<()> {
-        self.serialize_seq_elt(state, value)
+        self.serialize_tuple_member(value)
     }
 
     #[inline]
-    fn serialize_tuple_end(&mut self, state: State) -> Result<()> {
-        self.serialize_seq_end(state)
+    fn serialize_tuple_end(&mut self, _state: State) -> Result<()> {
+        self.end_entries()
     }
 
     #[inline]
@@ -296,21 +1301,21 @@ impl<W, F> ser::Serializer for Serializer<W, F>
         _name: &'static str,
         len: usize
     ) -> Result<State> {
-        self.serialize_seq(Some(len))
+        self.serialize_tuple_start(len)
     }
 
     #[inline]
     fn serialize_tuple_struct_elt<T: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         value: T
     ) -> Result<()> {
-        self.serialize_seq_elt(state, value)
+        self.serialize_tuple_member(value)
     }
 
     #[inline]
-    fn serialize_tuple_struct_end(&mut self, state: State) -> Result<()> {
-        self.serialize_seq_end(state)
+    fn serialize_tuple_struct_end(&mut self, _state: State) -> Result<()> {
+        self.end_entries()
     }
 
     #[inline]
@@ -321,9 +1326,11 @@ impl<W, F> ser::Serializer for Serializer<W, F>
         variant: &'static str,
         len: usize
     ) -> Result<State> {
-        try!(self.formatter.open(&mut self.writer, b'{'));
+        try!(self.begin_value(Shape::Lines));
+        try!(self.enter());
+        try!(self.formatter.begin_object(&mut self.writer));
         try!(self.formatter.comma(&mut self.writer, true));
-        try!(escape_key(&mut self.writer, variant));
+        try!(escape_key_with(&mut self.writer, &self.formatter, variant));
         try!(self.formatter.colon(&mut self.writer));
         self.serialize_seq(Some(len))
     }
@@ -331,86 +1338,78 @@ impl<W, F> ser::Serializer for Serializer<W, F>
     #[inline]
     fn serialize_tuple_variant_elt<T: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         value: T
     ) -> Result<()> {
-        self.serialize_seq_elt(state, value)
+        self.serialize_entry(value, false)
     }
 
     #[inline]
-    fn serialize_tuple_variant_end(&mut self, state: State) -> Result<()> {
-        try!(self.serialize_seq_end(state));
-        self.formatter.close(&mut self.writer, b'}')
+    fn serialize_tuple_variant_end(&mut self, _state: State) -> Result<()> {
+        try!(self.end_entries());
+        self.depth -= 1;
+        self.formatter.end_object(&mut self.writer)
     }
 
     #[inline]
     fn serialize_map(&mut self, len: Option<usize>) -> Result<State> {
-        if len == Some(0) {
-            try!(self.formatter.start_value(&mut self.writer));
-            try!(self.writer.write_all(b"{}"));
-            Ok(State::Empty)
-        } else {
-            try!(self.formatter.open(&mut self.writer, b'{'));
-            Ok(State::First)
-        }
+        let sorted = self.key_order.is_some();
+        self.start_object(len, sorted)
     }
 
     #[inline]
     fn serialize_map_key<T: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         key: T,
     ) -> Result<()> {
-        try!(self.formatter.comma(&mut self.writer, *state == State::First));
-        *state = State::Rest;
-
-        try!(key.serialize(&mut MapKeySerializer {
-            ser: self,
-        }));
-
-        self.formatter.colon(&mut self.writer)
+        self.serialize_key(key, true, None)
     }
 
     #[inline]
     fn serialize_map_value<T: ser::Serialize>(
         &mut self,
-        _: &mut State,
+        _state: &mut State,
         value: T,
     ) -> Result<()> {
-        value.serialize(self)
+        let skip_none = self.skip_none_in_map;
+        self.serialize_entry(value, skip_none)
     }
 
     #[inline]
-    fn serialize_map_end(&mut self, state: State) -> Result<()> {
-        match state {
-            State::Empty => Ok(()),
-            _ => self.formatter.close(&mut self.writer, b'}'),
-        }
+    fn serialize_map_end(&mut self, _state: State) -> Result<()> {
+        self.end_entries()
     }
 
     #[inline]
     fn serialize_struct(
         &mut self,
-        _name: &'static str,
+        name: &'static str,
         len: usize
     ) -> Result<State> {
-        self.serialize_map(Some(len))
+        let state = try!(self.start_object(Some(len), false));
+        self.entries.last_mut().unwrap().name = Some(name);
+        Ok(state)
     }
 
     #[inline]
     fn serialize_struct_elt<V: ser::Serialize>(
         &mut self,
-        state: &mut State,
+        _state: &mut State,
         key: &'static str,
         value: V
     ) -> Result<()> {
-        try!(self.serialize_map_key(state, key));
-        self.serialize_map_value(state, value)
+        let comment = match self.entries.last().unwrap().name {
+            Some(name) if !self.comments.is_empty() => self.comments.get(&(name, key)).cloned(),
+            _ => None,
+        };
+        try!(self.serialize_key(key, false, comment));
+        self.serialize_entry(value, false)
     }
 
     #[inline]
-    fn serialize_struct_end(&mut self, state: State) -> Result<()> {
-        self.serialize_map_end(state)
+    fn serialize_struct_end(&mut self, _state: State) -> Result<()> {
+        self.end_entries()
     }
 
     #[inline]
@@ -421,11 +1420,13 @@ impl<W, F> ser::Serializer for Serializer<W, F>
         variant: &'static str,
         len: usize
     ) -> Result<State> {
-        try!(self.formatter.open(&mut self.writer, b'{'));
+        try!(self.begin_value(Shape::Lines));
+        try!(self.enter());
+        try!(self.formatter.begin_object(&mut self.writer));
         try!(self.formatter.comma(&mut self.writer, true));
-        try!(escape_key(&mut self.writer, variant));
+        try!(escape_key_with(&mut self.writer, &self.formatter, variant));
         try!(self.formatter.colon(&mut self.writer));
-        self.serialize_map(Some(len))
+        self.serialize_struct(variant, len)
     }
 
     #[inline]
@@ -439,14 +1440,48 @@ impl<W, F> ser::Serializer for Serializer<W, F>
     }
 
     #[inline]
-    fn serialize_struct_variant_end(&mut self, state: State) -> Result<()> {
-        try!(self.serialize_struct_end(state));
-        self.formatter.close(&mut self.writer, b'}')
+    fn serialize_struct_variant_end(&mut self, _state: State) -> Result<()> {
+        try!(self.end_entries());
+        self.depth -= 1;
+        self.formatter.end_object(&mut self.writer)
     }
 }
 
 struct MapKeySerializer<'a, W: 'a, F: 'a> {
-    ser: &'a mut Serializer<W, F>,
+    writer: &'a mut W,
+    formatter: &'a F,
+    key_filter: Option<&'a KeyFilter>,
+    raw_str: bool,
+    stringify: bool,
+    reject_multiline: bool,
+    // the key as given, if it is kept
+    name: Option<String>,
+}
+
+impl<'a, W, F> MapKeySerializer<'a, W, F>
+    where W: io::Write,
+          F: Formatter {
+    fn new(writer: &'a mut W, formatter: &'a F, key_filter: Option<&'a KeyFilter>,
+           stringify: bool, reject_multiline: bool) -> Self {
+        MapKeySerializer {
+            writer: writer,
+            formatter: formatter,
+            key_filter: key_filter,
+            raw_str: false,
+            stringify: stringify,
+            reject_multiline: reject_multiline,
+            name: None,
+        }
+    }
+
+    // Writes a number, boolean, char or unit variant key as a string if the serializer
+    // stringifies keys.
+    fn serialize_scalar<T: Display>(&mut self, value: T) -> Result<()> {
+        if !self.stringify {
+            return Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0));
+        }
+        ser::Serializer::serialize_str(self, &value.to_string())
+    }
 }
 
 impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
@@ -456,7 +1491,22 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
 
     #[inline]
     fn serialize_str(&mut self, value: &str) -> Result<()> {
-        escape_key(&mut self.ser.writer, value).map_err(From::from)
+        if let Some(ref mut name) = self.name {
+            name.push_str(value);
+        }
+        let filtered;
+        let value = match self.key_filter {
+            Some(filter) => { filtered = try!(filter(value)); &*filtered }
+            None => value,
+        };
+        if self.reject_multiline && value.contains(|c| c == '\n' || c == '\r') {
+            return Err(Error::Syntax(ErrorCode::MultilineKey, 0, 0, 0));
+        }
+        if self.raw_str && !needs_escapes(self.formatter, value) {
+            debug_assert!(!key_needs_quotes(value), "RawStr {:?} must be quoted", value);
+            return self.writer.write_all(value.as_bytes()).map_err(From::from);
+        }
+        escape_key_with(self.writer, self.formatter, value).map_err(From::from)
     }
 
     type SeqState = ();
@@ -467,91 +1517,101 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
     type StructState = ();
     type StructVariantState = ();
 
-    fn serialize_bool(&mut self, _value: bool) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_bool(&mut self, value: bool) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_isize(&mut self, _value: isize) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_isize(&mut self, value: isize) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_i8(&mut self, _value: i8) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_i8(&mut self, value: i8) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_i16(&mut self, _value: i16) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_i16(&mut self, value: i16) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_i32(&mut self, _value: i32) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_i32(&mut self, value: i32) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_i64(&mut self, _value: i64) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_i64(&mut self, value: i64) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_usize(&mut self, _value: usize) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_usize(&mut self, value: usize) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_u8(&mut self, _value: u8) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_u8(&mut self, value: u8) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_u16(&mut self, _value: u16) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_u16(&mut self, value: u16) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_u32(&mut self, _value: u32) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_u32(&mut self, value: u32) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_u64(&mut self, _value: u64) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_u64(&mut self, value: u64) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_f32(&mut self, _value: f32) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_f32(&mut self, value: f32) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_f64(&mut self, _value: f64) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_f64(&mut self, value: f64) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
-    fn serialize_char(&mut self, _value: char) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+    fn serialize_char(&mut self, value: char) -> Result<()> {
+        self.serialize_scalar(value)
     }
 
     fn serialize_bytes(&mut self, _value: &[u8]) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_unit(&mut self) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_unit_struct(&mut self, _name: &'static str) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_unit_variant(
         &mut self,
         _name: &'static str,
         _variant_index: usize,
-        _variant: &'static str
+        variant: &'static str
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        self.serialize_scalar(variant)
     }
 
     fn serialize_newtype_struct<T>(
         &mut self,
-        _name: &'static str,
-        _value: T
+        name: &'static str,
+        value: T
     ) -> Result<()>
         where T: ser::Serialize,
     {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        if name == RAW_STR_TOKEN {
+            self.raw_str = true;
+            let res = value.serialize(&mut *self);
+            self.raw_str = false;
+            return res;
+        }
+        if name == RAW_NUMBER_TOKEN && self.stringify {
+            // the text of the number
+            return value.serialize(&mut *self);
+        }
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_newtype_variant<T>(
@@ -563,21 +1623,21 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
     ) -> Result<()>
         where T: ser::Serialize,
     {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_none(&mut self) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_some<T>(&mut self, _value: T) -> Result<()>
         where T: ser::Serialize,
     {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_seq(&mut self, _len: Option<usize>) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_seq_elt<T: ser::Serialize>(
@@ -587,19 +1647,19 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
     ) -> Result<()>
         where T: ser::Serialize,
     {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_seq_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_seq_fixed_size(&mut self, _size: usize) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple(&mut self, _len: usize) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_elt<T: ser::Serialize>(
@@ -607,11 +1667,11 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _state: &mut (),
         _value: T
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_struct(
@@ -619,7 +1679,7 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _name: &'static str,
         _len: usize
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_struct_elt<T: ser::Serialize>(
@@ -627,11 +1687,11 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _state: &mut (),
         _value: T
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_struct_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_variant(
@@ -641,7 +1701,7 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _variant: &'static str,
         _len: usize
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_variant_elt<T: ser::Serialize>(
@@ -649,15 +1709,15 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _state: &mut (),
         _value: T
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_tuple_variant_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_map(&mut self, _len: Option<usize>) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_map_key<T: ser::Serialize>(
@@ -665,7 +1725,7 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _state: &mut (),
         _key: T,
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_map_value<T: ser::Serialize>(
@@ -673,11 +1733,11 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _state: &mut (),
         _value: T,
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_map_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct(
@@ -685,7 +1745,7 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _name: &'static str,
         _len: usize
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct_elt<V: ser::Serialize>(
@@ -694,11 +1754,11 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _key: &'static str,
         _value: V
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct_variant(
@@ -708,7 +1768,7 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _variant: &'static str,
         _len: usize
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct_variant_elt<V: ser::Serialize>(
@@ -717,14 +1777,18 @@ impl<'a, W, F> ser::Serializer for MapKeySerializer<'a, W, F>
         _key: &'static str,
         _value: V
     ) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 
     fn serialize_struct_variant_end(&mut self, _state: ()) -> Result<()> {
-        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0))
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0))
     }
 }
 
+/// The longest string, in characters, that `compacts_arrays_of_primitives` keeps in an array
+/// written on one line.
+const COMPACT_STRING_LEN: usize = 20;
+
 /// This trait abstracts away serializing the JSON control characters
 pub trait Formatter {
     /// Called when serializing a '{' or '['.
@@ -743,6 +1807,30 @@ pub trait Formatter {
     fn close<W>(&mut self, writer: &mut W, ch: u8) -> Result<()>
         where W: io::Write;
 
+    /// Called when serializing a '['; the default calls `open`.
+    fn begin_array<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        self.open(writer, b'[')
+    }
+
+    /// Called when serializing a ']'; the default calls `close`.
+    fn end_array<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        self.close(writer, b']')
+    }
+
+    /// Called when serializing a '{'; the default calls `open`.
+    fn begin_object<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        self.open(writer, b'{')
+    }
+
+    /// Called when serializing a '}'; the default calls `close`.
+    fn end_object<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        self.close(writer, b'}')
+    }
+
     /// Newline with indent.
     fn newline<W>(&mut self, writer: &mut W, add_indent: i32) -> Result<()>
         where W: io::Write;
@@ -750,15 +1838,95 @@ pub trait Formatter {
     /// Start a value.
     fn start_value<W>(&mut self, writer: &mut W) -> Result<()>
         where W: io::Write;
+
+    /// Writes `level` levels of indentation, two spaces each unless overridden, e.g. for a
+    /// custom `comma` or `close`.
+    fn write_indent<W>(&self, writer: &mut W, level: usize) -> Result<()>
+        where W: io::Write {
+        indent(writer, level, b"  ")
+    }
+
+    /// Whether an object with a single member should be written on one line, as
+    /// `{ key: value }`. Objects whose member is a collection are never collapsed.
+    fn collapses_single_member(&self) -> bool {
+        false
+    }
+
+    /// Whether the values of an object's members should be lined up in one column, padding
+    /// the shorter keys. The members of such an object are buffered until it is complete.
+    fn aligns_keys(&self) -> bool {
+        false
+    }
+
+    /// Whether all strings should be quoted and their non-ASCII characters written as `\u`
+    /// escapes, so that the output is pure ASCII.
+    fn escapes_non_ascii(&self) -> bool {
+        false
+    }
+
+    /// Whether strings that contain `<`, `>` or `&` should be quoted with these characters
+    /// written as `\u` escapes, so that the output can be embedded in HTML, e.g. in a
+    /// `<script>` element.
+    fn escapes_html(&self) -> bool {
+        false
+    }
+
+    /// Whether strings and keys that contain control characters should be quoted with these
+    /// written as `\u` escapes, e.g. `"\u007f"`, so that the output never contains them raw.
+    /// This covers C0 controls without a short escape like `\n`, DEL and the C1 controls
+    /// U+0080 to U+009F.
+    fn escapes_control_chars(&self) -> bool {
+        false
+    }
+
+    /// Whether arrays whose elements are all numbers, booleans, nulls or short strings should
+    /// be written on one line, as `[1, 2, 3]`. As this is only known after the last element,
+    /// `start_value` is called before the array is opened with `open` or written inline.
+    fn compacts_arrays_of_primitives(&self) -> bool {
+        false
+    }
+
+    /// Called after each element of an array and each member of an object has been written,
+    /// also for nested collections, so that e.g. a socket can be flushed as the output grows.
+    /// It is not called while output is held back, like the members of an object with aligned
+    /// keys. The default does nothing.
+    fn flush<W>(&mut self, _writer: &mut W) -> Result<()>
+        where W: io::Write {
+        Ok(())
+    }
+
+    /// Whether everything is written on one line, like `CompactFormatter` does. All strings
+    /// are then quoted, as a quoteless string would run on to the end of the line.
+    fn writes_one_line(&self) -> bool {
+        false
+    }
+
+    /// Returns the bytes to write for `byte` inside a quoted string, or `None` to use the
+    /// default escaping. This allows e.g. writing `/` as `\/`; only ASCII bytes should be
+    /// overridden.
+    fn escape_byte(&self, _byte: u8) -> Option<&'static [u8]> {
+        None
+    }
 }
 
-struct HjsonFormatter<'a> {
+/// The default `Formatter`, writing indented Hjson.
+pub struct HjsonFormatter<'a> {
     current_indent: usize,
     current_is_array: bool,
     stack: Vec<bool>,
     at_colon: bool,
     indent: &'a [u8],
     braces_same_line: bool,
+    collapse_single_member: bool,
+    align_keys: bool,
+    escape_non_ascii: bool,
+    escape_html: bool,
+    escape_control_chars: bool,
+    blank_line_between_members: bool,
+    compact_arrays_of_primitives: bool,
+    flush_after_members: bool,
+    separator: &'static [u8],
+    space_before_value: bool,
 }
 
 impl<'a> HjsonFormatter<'a> {
@@ -776,8 +1944,94 @@ impl<'a> HjsonFormatter<'a> {
             at_colon: false,
             indent: indent,
             braces_same_line: false,
+            collapse_single_member: false,
+            align_keys: false,
+            escape_non_ascii: false,
+            escape_html: false,
+            escape_control_chars: false,
+            blank_line_between_members: false,
+            compact_arrays_of_primitives: false,
+            flush_after_members: false,
+            separator: b":",
+            space_before_value: true,
         }
     }
+
+    /// Write objects that have exactly one member on a single line, e.g. `{ key: value }`,
+    /// unless that member is itself a collection.
+    pub fn collapse_single_member(mut self, collapse: bool) -> Self {
+        self.collapse_single_member = collapse;
+        self
+    }
+
+    /// Line up the values of each object in one column, e.g.
+    ///
+    /// ```text
+    /// {
+    ///   name:    web
+    ///   port:    80
+    ///   timeout: 30
+    /// }
+    /// ```
+    ///
+    /// Values that start on a new line, like nested objects, are not padded.
+    pub fn align_keys(mut self, align: bool) -> Self {
+        self.align_keys = align;
+        self
+    }
+
+    /// Quote all strings and write non-ASCII characters as `\u` escapes, e.g. `"caf\u00e9"`.
+    /// Keys are only quoted if they contain such characters.
+    pub fn escape_non_ascii(mut self, escape: bool) -> Self {
+        self.escape_non_ascii = escape;
+        self
+    }
+
+    /// Quote the strings and keys that contain `<`, `>` or `&` and write these as `\u003c`,
+    /// `\u003e` and `\u0026`, e.g. `"\u003c/script\u003e"`, for Hjson embedded in HTML.
+    pub fn escape_html(mut self, escape: bool) -> Self {
+        self.escape_html = escape;
+        self
+    }
+
+    /// Quote the strings and keys that contain control characters and write these as `\u`
+    /// escapes, see `Formatter::escapes_control_chars`.
+    pub fn escape_control_chars(mut self, escape: bool) -> Self {
+        self.escape_control_chars = escape;
+        self
+    }
+
+    /// Separate the members of the root object with an empty line. Nested objects and
+    /// arrays are not affected.
+    pub fn blank_line_between_members(mut self, blank_line: bool) -> Self {
+        self.blank_line_between_members = blank_line;
+        self
+    }
+
+    /// Write arrays that only contain numbers, booleans, nulls and short strings (of at most
+    /// 20 characters) on one line, e.g. `ports: [80, 443]`. Arrays that contain a collection
+    /// or a longer string are written with one element per line as usual.
+    pub fn compact_arrays_of_primitives(mut self, compact: bool) -> Self {
+        self.compact_arrays_of_primitives = compact;
+        self
+    }
+
+    /// Flush the writer after each element and member, see `Formatter::flush`, for a slow
+    /// writer like a network socket. Wrap the socket in a `BufWriter` to still write the
+    /// output in larger chunks.
+    pub fn flush_after_members(mut self, flush: bool) -> Self {
+        self.flush_after_members = flush;
+        self
+    }
+
+    /// Write `=` instead of `:` between keys and values, as `key = value` with `spaces` or
+    /// `key=value` without. Such output can only be read with
+    /// `Deserializer::allow_equals_separator`.
+    pub fn equals_separator(mut self, spaces: bool) -> Self {
+        self.separator = if spaces { b" =" } else { b"=" };
+        self.space_before_value = spaces;
+        self
+    }
 }
 
 impl<'a> Formatter for HjsonFormatter<'a> {
@@ -794,16 +2048,24 @@ impl<'a> Formatter for HjsonFormatter<'a> {
         writer.write_all(&[ch]).map_err(From::from)
     }
 
-    fn comma<W>(&mut self, writer: &mut W, _: bool) -> Result<()>
+    fn comma<W>(&mut self, writer: &mut W, first: bool) -> Result<()>
         where W: io::Write {
+        if self.blank_line_between_members && !first &&
+           self.current_indent == 1 && !self.current_is_array {
+            try!(writer.write_all(b"\n"));
+        }
         try!(writer.write_all(b"\n"));
-        indent(writer, self.current_indent, self.indent)
+        self.write_indent(writer, self.current_indent)
     }
 
     fn colon<W>(&mut self, writer: &mut W) -> Result<()>
         where W: io::Write {
-        self.at_colon = !self.braces_same_line;
-        writer.write_all(if self.braces_same_line { b": " } else { b":" }).map_err(From::from)
+        self.at_colon = !self.braces_same_line && self.space_before_value;
+        try!(writer.write_all(self.separator));
+        if self.braces_same_line && self.space_before_value {
+            try!(writer.write_all(b" "));
+        }
+        Ok(())
     }
 
     fn close<W>(&mut self, writer: &mut W, ch: u8) -> Result<()>
@@ -811,7 +2073,7 @@ impl<'a> Formatter for HjsonFormatter<'a> {
         self.current_indent -= 1;
         self.current_is_array = self.stack.pop().unwrap();
         try!(writer.write(b"\n"));
-        try!(indent(writer, self.current_indent, self.indent));
+        try!(self.write_indent(writer, self.current_indent));
         writer.write_all(&[ch]).map_err(From::from)
     }
 
@@ -820,7 +2082,7 @@ impl<'a> Formatter for HjsonFormatter<'a> {
         self.at_colon = false;
         try!(writer.write_all(b"\n"));
         let ii = self.current_indent as i32 + add_indent;
-        indent(writer, if ii < 0 { 0 } else { ii as usize }, self.indent)
+        self.write_indent(writer, if ii < 0 { 0 } else { ii as usize })
     }
 
     fn start_value<W>(&mut self, writer: &mut W) -> Result<()>
@@ -831,25 +2093,181 @@ impl<'a> Formatter for HjsonFormatter<'a> {
         }
         Ok(())
     }
+
+    fn write_indent<W>(&self, writer: &mut W, level: usize) -> Result<()>
+        where W: io::Write {
+        indent(writer, level, self.indent)
+    }
+
+    fn collapses_single_member(&self) -> bool {
+        self.collapse_single_member
+    }
+
+    fn aligns_keys(&self) -> bool {
+        self.align_keys
+    }
+
+    fn escapes_non_ascii(&self) -> bool {
+        self.escape_non_ascii
+    }
+
+    fn escapes_html(&self) -> bool {
+        self.escape_html
+    }
+
+    fn escapes_control_chars(&self) -> bool {
+        self.escape_control_chars
+    }
+
+    fn compacts_arrays_of_primitives(&self) -> bool {
+        self.compact_arrays_of_primitives
+    }
+
+    fn flush<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        if self.flush_after_members {
+            try!(writer.flush());
+        }
+        Ok(())
+    }
+}
+
+/// A `Formatter` that writes Hjson without any whitespace, e.g. `{a:1,b:["x","y"]}`, with
+/// all strings quoted.
+///
+/// Comments, e.g. from `Serializer::field_comment`, and raw values that span lines still end in a
+/// line break.
+pub struct CompactFormatter;
+
+impl CompactFormatter {
+    /// Construct a compact formatter.
+    pub fn new() -> Self {
+        CompactFormatter
+    }
+}
+
+impl Formatter for CompactFormatter {
+    fn open<W>(&mut self, writer: &mut W, ch: u8) -> Result<()>
+        where W: io::Write {
+        writer.write_all(&[ch]).map_err(From::from)
+    }
+
+    fn comma<W>(&mut self, writer: &mut W, first: bool) -> Result<()>
+        where W: io::Write {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b",").map_err(From::from)
+        }
+    }
+
+    fn colon<W>(&mut self, writer: &mut W) -> Result<()>
+        where W: io::Write {
+        writer.write_all(b":").map_err(From::from)
+    }
+
+    fn close<W>(&mut self, writer: &mut W, ch: u8) -> Result<()>
+        where W: io::Write {
+        writer.write_all(&[ch]).map_err(From::from)
+    }
+
+    fn newline<W>(&mut self, writer: &mut W, _add_indent: i32) -> Result<()>
+        where W: io::Write {
+        writer.write_all(b"\n").map_err(From::from)
+    }
+
+    fn start_value<W>(&mut self, _writer: &mut W) -> Result<()>
+        where W: io::Write {
+        Ok(())
+    }
+
+    fn writes_one_line(&self) -> bool {
+        true
+    }
+}
+
+// Check if the formatter writes escapes for some of the characters of the string, which must
+// then be quoted
+fn needs_escapes<F: Formatter>(formatter: &F, value: &str) -> bool {
+    formatter.escapes_non_ascii() && !value.is_ascii() ||
+        formatter.escapes_html() && value.contains(|c| c == '<' || c == '>' || c == '&') ||
+        formatter.escapes_control_chars() && value.contains(|c| c <= '\x1f' || c >= '\x7f' && c <= '\u{9f}')
 }
 
 /// Serializes and escapes a `&[u8]` into a Hjson string.
 #[inline]
 pub fn escape_bytes<W>(wr: &mut W, bytes: &[u8]) -> Result<()>
     where W: io::Write {
+    escape_bytes_using(wr, bytes, |_| None, false, false, false)
+}
+
+/// Serializes and escapes a `&[u8]` into a Hjson string, using the escapes of `formatter`
+/// where it overrides them (see `Formatter::escape_byte`).
+#[inline]
+pub fn escape_bytes_with<W, F>(wr: &mut W, formatter: &F, bytes: &[u8]) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    escape_bytes_using(wr, bytes, |byte| formatter.escape_byte(byte), formatter.escapes_non_ascii(),
+                       formatter.escapes_html(), formatter.escapes_control_chars())
+}
+
+fn escape_bytes_using<W, E>(wr: &mut W, bytes: &[u8], escape: E, ascii: bool, html: bool,
+                           controls: bool) -> Result<()>
+    where W: io::Write,
+          E: Fn(u8) -> Option<&'static [u8]> {
     try!(wr.write_all(b"\""));
 
     let mut start = 0;
 
     for (i, byte) in bytes.iter().enumerate() {
-        let escaped = match *byte {
-            b'"' => b"\\\"",
-            b'\\' => b"\\\\",
-            b'\x08' => b"\\b",
-            b'\x0c' => b"\\f",
-            b'\n' => b"\\n",
-            b'\r' => b"\\r",
-            b'\t' => b"\\t",
+        if i < start {
+            // the rest of a character that was escaped
+            continue;
+        }
+
+        if ascii && *byte >= 0x80 {
+            if start < i {
+                try!(wr.write_all(&bytes[start..i]));
+            }
+            let ch = str::from_utf8(&bytes[i..cmp::min(i + utf8_width(*byte), bytes.len())])
+                .ok().and_then(|s| s.chars().next());
+            let mut buf = [0; 2];
+            for unit in ch.unwrap_or('\u{fffd}').encode_utf16(&mut buf) {
+                try!(write!(wr, "\\u{:04x}", unit));
+            }
+            start = i + ch.map_or(1, |ch| ch.len_utf8());
+            continue;
+        }
+
+        let escaped: &[u8] = match (escape(*byte), *byte) {
+            (Some(escaped), _) => escaped,
+            (None, b'"') => b"\\\"",
+            (None, b'\\') => b"\\\\",
+            (None, b'\x08') => b"\\b",
+            (None, b'\x0c') => b"\\f",
+            (None, b'\n') => b"\\n",
+            (None, b'\r') => b"\\r",
+            (None, b'\t') => b"\\t",
+            (None, b'<') if html => b"\\u003c",
+            (None, b'>') if html => b"\\u003e",
+            (None, b'&') if html => b"\\u0026",
+            (None, 0x00 ... 0x1f) | (None, 0x7f) if controls => {
+                if start < i {
+                    try!(wr.write_all(&bytes[start..i]));
+                }
+                try!(write!(wr, "\\u{:04x}", byte));
+                start = i + 1;
+                continue;
+            }
+            // the C1 controls are encoded as 0xc2 0x80 to 0xc2 0x9f
+            (None, 0xc2) if controls && bytes.get(i + 1).map_or(false, |&next| next <= 0x9f) => {
+                if start < i {
+                    try!(wr.write_all(&bytes[start..i]));
+                }
+                try!(write!(wr, "\\u{:04x}", bytes[i + 1]));
+                start = i + 2;
+                continue;
+            }
             _ => { continue; }
         };
 
@@ -870,46 +2288,80 @@ pub fn escape_bytes<W>(wr: &mut W, bytes: &[u8]) -> Result<()>
     Ok(())
 }
 
-/// Serializes and escapes a `&str` into a Hjson string.
-#[inline]
-pub fn quote_str<W, F>(wr: &mut W, formatter: &mut F, value: &str) -> Result<()>
-    where W: io::Write,
-          F: Formatter {
-    lazy_static! {
-        // NEEDS_ESCAPE tests if the string can be written without escapes
-        static ref NEEDS_ESCAPE: Regex = Regex::new("[\\\\\"\x00-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
-        // NEEDS_QUOTES tests if the string can be written as a quoteless string (includes needsEscape but without \\ and \")
-        static ref NEEDS_QUOTES: Regex = Regex::new("^\\s|^\"|^'''|^#|^/\\*|^//|^\\{|^\\}|^\\[|^\\]|^:|^,|\\s$|[\x00-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
-        // NEEDS_ESCAPEML tests if the string can be written as a multiline string (includes needsEscape but without \n, \r, \\ and \")
-        static ref NEEDS_ESCAPEML: Regex = Regex::new("'''|[\x00-\x09\x0b\x0c\x0e-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
-        // starts with a keyword and optionally is followed by a comment
-        static ref STARTS_WITH_KEYWORD: Regex = Regex::new(r#"^(true|false|null)\s*((,|\]|\}|#|//|/\*).*)?$"#).unwrap();
+// The length of the UTF-8 sequence that starts with `byte`.
+fn utf8_width(byte: u8) -> usize {
+    match byte {
+        0xc0 ... 0xdf => 2,
+        0xe0 ... 0xef => 3,
+        0xf0 ... 0xf7 => 4,
+        _ => 1,
     }
+}
+
+lazy_static! {
+    // NEEDS_ESCAPE tests if the string can be written without escapes
+    static ref NEEDS_ESCAPE: Regex = Regex::new("[\\\\\"\x00-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
+    // NEEDS_QUOTES tests if the string can be written as a quoteless string (includes needsEscape but without \\ and \")
+    static ref NEEDS_QUOTES: Regex = Regex::new("^\\s|^\"|^'|^#|^/\\*|^//|^\\{|^\\}|^\\[|^\\]|^:|^,|\\s$|[\x00-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
+    // NEEDS_ESCAPEML tests if the string can be written as a multiline string (includes needsEscape but without \n, \r, \\ and \")
+    static ref NEEDS_ESCAPEML: Regex = Regex::new("'''|[\x00-\x09\x0b\x0c\x0e-\x1f\x7f-\u{9f}\u{00ad}\u{0600}-\u{0604}\u{070f}\u{17b4}\u{17b5}\u{200c}-\u{200f}\u{2028}-\u{202f}\u{2060}-\u{206f}\u{feff}\u{fff0}-\u{ffff}]").unwrap();
+    // starts with a keyword and optionally is followed by a comment
+    static ref STARTS_WITH_KEYWORD: Regex = Regex::new(r#"^(true|false|null)\s*((,|\]|\}|#|//|/\*).*)?$"#).unwrap();
+    // AMBIGUOUS_WORD and AMBIGUOUS_NUMBER test for the scalars of lenient parsers (QuoteProfile::Safe)
+    static ref AMBIGUOUS_WORD: Regex = Regex::new(r#"(?i)^(~|y|n|yes|no|on|off|[-+]?\.?(inf|infinity)|\.?nan|(true|false|null|nil|none)\b.*)$"#).unwrap();
+    static ref AMBIGUOUS_NUMBER: Regex = Regex::new(r#"^[-+]?(\d[\d_]*\.?[\d_]*|\.\d[\d_]*)([eE][-+]?\d+)?$|^[-+]?0[xXoObB][\da-fA-F_]+$"#).unwrap();
+}
 
+// Check if we can insert this string without quotes
+// see hjson syntax (must not parse as true, false, null or number,
+// including the extended numbers a `Deserializer` may accept)
+fn needs_quotes(value: &str) -> bool {
     if value.len() == 0 {
-        try!(formatter.start_value(wr));
-        return escape_bytes(wr, value.as_bytes());
+        return true;
     }
 
-    // Check if we can insert this string without quotes
-    // see hjson syntax (must not parse as true, false, null or number)
-
-    let mut pn = ParseNumber::new(value.bytes());
+    let mut pn = ParseNumber::new(value.bytes()).extended(true);
     let is_number = match pn.parse(true) {
         Ok(_) => true,
         Err(_) => false,
     };
 
-    if is_number || NEEDS_QUOTES.is_match(value) || STARTS_WITH_KEYWORD.is_match(value) {
+    is_number || NEEDS_QUOTES.is_match(value) || STARTS_WITH_KEYWORD.is_match(value)
+}
+
+// Check if the string can be written in the multiline format. A single line is written right
+// after the opening ''', where a parser skips whitespace.
+fn fits_multiline(value: &str) -> bool {
+    !NEEDS_ESCAPEML.is_match(value) &&
+        (value.contains('\n') || !value.starts_with(|c| c == ' ' || c == '\t' || c == '\r'))
+}
+
+// Check if a lenient parser could read this string as something else
+fn is_ambiguous(value: &str) -> bool {
+    let value = value.trim();
+    value.is_empty() || AMBIGUOUS_WORD.is_match(value) || AMBIGUOUS_NUMBER.is_match(value)
+}
+
+/// Serializes and escapes a `&str` into a Hjson string.
+#[inline]
+pub fn quote_str<W, F>(wr: &mut W, formatter: &mut F, value: &str) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    if value.len() == 0 {
+        try!(formatter.start_value(wr));
+        return escape_bytes_with(wr, formatter, value.as_bytes());
+    }
+
+    if needs_quotes(value) {
 
         // First check if the string can be expressed in multiline format or
         // we must replace the offending characters with safe escape sequences.
 
-        if NEEDS_ESCAPE.is_match(value) && !NEEDS_ESCAPEML.is_match(value)  /* && !isRootObject */ {
+        if NEEDS_ESCAPE.is_match(value) && fits_multiline(value)  /* && !isRootObject */ {
             ml_str(wr, formatter, value)
         } else {
             try!(formatter.start_value(wr));
-            escape_bytes(wr, value.as_bytes())
+            escape_bytes_with(wr, formatter, value.as_bytes())
         }
     }
     else {
@@ -919,6 +2371,28 @@ pub fn quote_str<W, F>(wr: &mut W, formatter: &mut F, value: &str) -> Result<()>
     }
 }
 
+/// Serializes a `&str` into a Hjson string using the given `StringStyle`.
+///
+/// Falls back to `quote_str` if the string cannot be represented in that style.
+pub fn styled_str<W, F>(wr: &mut W, formatter: &mut F, value: &str, style: StringStyle) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    match style {
+        StringStyle::Quoted => {
+            try!(formatter.start_value(wr));
+            escape_bytes_with(wr, formatter, value.as_bytes())
+        }
+        StringStyle::Multiline if fits_multiline(value) => {
+            ml_str(wr, formatter, value)
+        }
+        StringStyle::Quoteless if !needs_quotes(value) => {
+            try!(formatter.start_value(wr));
+            wr.write_all(value.as_bytes()).map_err(From::from)
+        }
+        _ => quote_str(wr, formatter, value),
+    }
+}
+
 /// Serializes and escapes a `&str` into a multiline Hjson string.
 pub fn ml_str<W, F>(wr: &mut W, formatter: &mut F, value: &str) -> Result<()>
     where W: io::Write,
@@ -949,61 +2423,318 @@ pub fn ml_str<W, F>(wr: &mut W, formatter: &mut F, value: &str) -> Result<()>
     Ok(())
 }
 
+/// Writes an already formatted Hjson fragment as a value, indenting every line after the
+/// first to the current level.
+fn raw_fragment<W, F>(wr: &mut W, formatter: &mut F, fragment: &str) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    try!(formatter.start_value(wr));
+    for (i, line) in fragment.trim().split('\n').enumerate() {
+        if i > 0 {
+            try!(formatter.newline(wr, 0));
+        }
+        try!(wr.write_all(line.trim_right().as_bytes()));
+    }
+    Ok(())
+}
+
+/// A string that is hard-wrapped when serialized.
+///
+/// Lines longer than `column` characters are broken at the last space before the column
+/// (or at the column itself if there is none) by inserting real newlines, so the value is
+/// written as a `'''` multiline string. Since this changes the content of the string it
+/// only applies to values wrapped in this type.
+pub struct WrappedString<'a> {
+    value: &'a str,
+    column: usize,
+}
+
+impl<'a> WrappedString<'a> {
+    /// Wraps `value` at `column` characters (not counting indentation). A `column` of `0`
+    /// disables wrapping.
+    pub fn new(value: &'a str, column: usize) -> Self {
+        WrappedString {
+            value: value,
+            column: column,
+        }
+    }
+}
+
+impl<'a> ser::Serialize for WrappedString<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+        where S: ser::Serializer {
+        serializer.serialize_str(&wrap_str(self.value, self.column))
+    }
+}
+
+fn wrap_str(value: &str, column: usize) -> String {
+    if column == 0 {
+        return value.to_owned();
+    }
+
+    let mut res = String::with_capacity(value.len() + value.len() / column);
+    for (i, line) in value.split('\n').enumerate() {
+        if i > 0 { res.push('\n'); }
+        let mut len = 0;
+        for (j, word) in line.split(' ').enumerate() {
+            if j > 0 {
+                if len + 1 + word.chars().count() <= column {
+                    res.push(' ');
+                    len += 1;
+                } else {
+                    res.push('\n');
+                    len = 0;
+                }
+            }
+            // words that don't fit on a line of their own are split
+            for ch in word.chars() {
+                if len == column {
+                    res.push('\n');
+                    len = 0;
+                }
+                res.push(ch);
+                len += 1;
+            }
+        }
+    }
+    res
+}
+
+const RAW_STR_TOKEN: &'static str = "$__serde_hjson_RawStr";
+
+/// A string that is known to need neither quotes nor escapes, e.g. an identifier.
+///
+/// It is written as is, as a value or key, without running the checks that normally
+/// decide how to quote a string. This is only verified in debug builds; an unsafe string
+/// produces invalid Hjson.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RawStr<'a> {
+    value: &'a str,
+}
+
+impl<'a> RawStr<'a> {
+    /// Wraps a string that can be written without quotes.
+    pub fn new(value: &'a str) -> Self {
+        RawStr {
+            value: value,
+        }
+    }
+}
+
+impl<'a> ser::Serialize for RawStr<'a> {
+    fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+        where S: ser::Serializer {
+        serializer.serialize_newtype_struct(RAW_STR_TOKEN, self.value)
+    }
+}
+
+/// A value that is only computed when it is serialized, by calling `func`.
+///
+/// The result is kept, so `func` runs at most once even if the `Lazy` is serialized again.
+pub struct Lazy<T, F>
+    where F: Fn() -> T {
+    func: F,
+    value: RefCell<Option<T>>,
+}
+
+impl<T, F> Lazy<T, F>
+    where F: Fn() -> T {
+    /// Wraps the function that computes the value.
+    pub fn new(func: F) -> Self {
+        Lazy {
+            func: func,
+            value: RefCell::new(None),
+        }
+    }
+}
+
+impl<T, F> ser::Serialize for Lazy<T, F>
+    where T: ser::Serialize,
+          F: Fn() -> T {
+    fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+        where S: ser::Serializer {
+        if self.value.borrow().is_none() {
+            let value = (self.func)();
+            *self.value.borrow_mut() = Some(value);
+        }
+        self.value.borrow().as_ref().unwrap().serialize(serializer)
+    }
+}
+
+/// A sequence whose elements are written as the text that `format` returns for them, e.g.
+/// zero-padded or with a fixed number of decimals.
+///
+/// The text is written like a `RawValue`, so it must be a valid Hjson value (a number may
+/// well read back as a string, e.g. `007`) and surrounding whitespace is dropped. Other
+/// serializers see the elements as strings.
+///
+/// ```rust
+/// use serde_hjson::ser::FormattedSeq;
+///
+/// let prices = [1.5, 20.0];
+/// let hjson = serde_hjson::to_string(&FormattedSeq::new(&prices, |price| format!("{:.2}", price))).unwrap();
+/// assert_eq!(hjson, "[\n  1.50\n  20.00\n]");
+/// ```
+pub struct FormattedSeq<'a, T: 'a, F>
+    where F: Fn(&T) -> String {
+    elements: &'a [T],
+    format: F,
+}
+
+impl<'a, T, F> FormattedSeq<'a, T, F>
+    where F: Fn(&T) -> String {
+    /// Wraps the elements and the function that formats each of them.
+    pub fn new(elements: &'a [T], format: F) -> Self {
+        FormattedSeq {
+            elements: elements,
+            format: format,
+        }
+    }
+}
+
+impl<'a, T, F> ser::Serialize for FormattedSeq<'a, T, F>
+    where F: Fn(&T) -> String {
+    fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+        where S: ser::Serializer {
+        let mut state = try!(serializer.serialize_seq(Some(self.elements.len())));
+        for element in self.elements {
+            let text = RawValue::from_string((self.format)(element));
+            try!(serializer.serialize_seq_elt(&mut state, text));
+        }
+        serializer.serialize_seq_end(state)
+    }
+}
+
+/// A `Result` written as an object with a single `Ok` or `Err` member, like an enum variant,
+/// whose error is written as its `Display` text, e.g. for logging the outcome of an I/O
+/// operation.
+///
+/// A `Result` whose error type is serializable is already written this way, so it does not
+/// need this wrapper.
+///
+/// ```rust
+/// use std::io;
+/// use serde_hjson::ser::SerializeResult;
+///
+/// let result: Result<u32, io::Error> = Err(io::Error::new(io::ErrorKind::Other, "disk full"));
+/// let hjson = serde_hjson::to_string(&SerializeResult(&result)).unwrap();
+/// assert_eq!(hjson, "{\n  Err: disk full\n}");
+/// ```
+pub struct SerializeResult<'a, T: 'a, E: 'a>(pub &'a ::std::result::Result<T, E>);
+
+impl<'a, T, E> ser::Serialize for SerializeResult<'a, T, E>
+    where T: ser::Serialize,
+          E: Display {
+    fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+        where S: ser::Serializer {
+        match *self.0 {
+            Ok(ref value) => serializer.serialize_newtype_variant("Result", 0, "Ok", value),
+            Err(ref error) => {
+                serializer.serialize_newtype_variant("Result", 1, "Err", error.to_string())
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref NEEDS_ESCAPE_NAME: Regex = Regex::new(r#"[,\{\[\}\]\s:#"]|//|/\*|'''|^'|^$"#).unwrap();
+}
+
+/// Returns whether `escape_key` quotes `value`: keys that are empty, start with a single
+/// quote or contain whitespace, punctuators, double quotes or the start of a comment cannot
+/// be written without quotes. Formatters
+/// may quote further keys, e.g. with `HjsonFormatter::escape_non_ascii`.
+pub fn key_needs_quotes(value: &str) -> bool {
+    NEEDS_ESCAPE_NAME.is_match(value)
+}
+
 /// Serializes and escapes a `&str` into a Hjson key.
 #[inline]
 pub fn escape_key<W>(wr: &mut W, value: &str) -> Result<()>
     where W: io::Write {
 
-    lazy_static! {
-        static ref NEEDS_ESCAPE_NAME: Regex = Regex::new(r#"[,\{\[\}\]\s:#"]|//|/\*|'''|^$"#).unwrap();
-    }
-
     // Check if we can insert this name without quotes
-    if NEEDS_ESCAPE_NAME.is_match(value) {
+    if key_needs_quotes(value) {
         escape_bytes(wr, value.as_bytes()).map_err(From::from)
     } else {
         wr.write_all(value.as_bytes()).map_err(From::from)
     }
 }
 
+/// Serializes and escapes a `&str` into a quoted Hjson key, also if `escape_key` would write
+/// it without quotes.
 #[inline]
-fn escape_char<W>(wr: &mut W, value: char) -> Result<()>
+pub fn escape_key_quoted<W>(wr: &mut W, value: &str) -> Result<()>
     where W: io::Write {
+    escape_bytes(wr, value.as_bytes())
+}
+
+/// Serializes and escapes a `&str` into a Hjson key, using the escapes of `formatter`.
+#[inline]
+pub fn escape_key_with<W, F>(wr: &mut W, formatter: &F, value: &str) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
+    if key_needs_quotes(value) || needs_escapes(formatter, value) {
+        escape_bytes_with(wr, formatter, value.as_bytes()).map_err(From::from)
+    } else {
+        wr.write_all(value.as_bytes()).map_err(From::from)
+    }
+}
+
+#[inline]
+fn escape_char<W, F>(wr: &mut W, formatter: &F, value: char) -> Result<()>
+    where W: io::Write,
+          F: Formatter {
     // FIXME: this allocation is required in order to be compatible with stable
     // rust, which doesn't support encoding a `char` into a stack buffer.
     let mut s = String::new();
     s.push(value);
-    escape_bytes(wr, s.as_bytes())
+    escape_bytes_with(wr, formatter, s.as_bytes())
 }
 
-fn fmt_f32_or_null<W>(wr: &mut W, value: f32) -> Result<()>
+fn fmt_f32_or_null<W>(wr: &mut W, value: f32, keep_point: bool, non_finite: NonFiniteMode) -> Result<()>
     where W: io::Write {
     match value.classify() {
         FpCategory::Nan | FpCategory::Infinite => {
-            try!(wr.write_all(b"null"))
+            try!(wr.write_all(non_finite_token(value as f64, non_finite)))
         }
         _ => {
-            try!(wr.write_all(fmt_small(value).as_bytes()))
+            try!(wr.write_all(fmt_small(value, keep_point).as_bytes()))
         }
     }
 
     Ok(())
 }
 
-fn fmt_f64_or_null<W>(wr: &mut W, value: f64) -> Result<()>
+fn fmt_f64_or_null<W>(wr: &mut W, value: f64, keep_point: bool, non_finite: NonFiniteMode) -> Result<()>
     where W: io::Write {
     match value.classify() {
         FpCategory::Nan | FpCategory::Infinite => {
-            try!(wr.write_all(b"null"))
+            try!(wr.write_all(non_finite_token(value, non_finite)))
         }
         _ => {
-            try!(wr.write_all(fmt_small(value).as_bytes()))
+            try!(wr.write_all(fmt_small(value, keep_point).as_bytes()))
         }
     }
 
     Ok(())
 }
 
+// What to write for a `NaN` or infinite `value`.
+fn non_finite_token(value: f64, non_finite: NonFiniteMode) -> &'static [u8] {
+    match non_finite {
+        NonFiniteMode::Null => b"null",
+        NonFiniteMode::Token if value.is_nan() => b"NaN",
+        NonFiniteMode::Token if value > 0.0 => b"Infinity",
+        NonFiniteMode::Token => b"-Infinity",
+    }
+}
+
+// Check if a string would be read as a number by `Deserializer::allow_non_finite`.
+fn is_non_finite_token(value: &str) -> bool {
+    value == "NaN" || value == "Infinity" || value == "-Infinity"
+}
+
 fn indent<W>(wr: &mut W, n: usize, s: &[u8]) -> Result<()>
     where W: io::Write {
     for _ in 0 .. n {
@@ -1013,18 +2744,6 @@ fn indent<W>(wr: &mut W, n: usize, s: &[u8]) -> Result<()>
     Ok(())
 }
 
-// format similar to es6
-fn fmt_small<N>(value: N) -> String
-    where N: Display + LowerExp {
-    let f1 = format!("{}", value);
-    let f2 = format!("{:e}", value);
-    if f1.len() <= f2.len() + 1 {
-        f1
-    } else {
-        if !f2.contains("e-") { f2.replace("e", "e+") } else { f2 }
-    }
-}
-
 /// Encode the specified struct into a Hjson `[u8]` writer.
 #[inline]
 pub fn to_writer<W, T>(writer: &mut W, value: &T) -> Result<()>
@@ -1046,11 +2765,91 @@ pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
     Ok(writer)
 }
 
+/// Encode the specified struct into `buf`, which is cleared first. Reusing one buffer for
+/// many values keeps its capacity, so that it only allocates while it grows.
+///
+/// ```rust
+/// let mut buf = Vec::new();
+/// for i in 0..3 {
+///     serde_hjson::to_buf(&vec![i, i + 1], &mut buf).unwrap();
+/// }
+/// assert_eq!(buf, b"[\n  2\n  3\n]");
+/// ```
+#[inline]
+pub fn to_buf<T>(value: &T, buf: &mut Vec<u8>) -> Result<()>
+    where T: ser::Serialize {
+    buf.clear();
+    to_writer(buf, value)
+}
+
 /// Encode the specified struct into a Hjson `String` buffer.
 #[inline]
 pub fn to_string<T>(value: &T) -> Result<String>
     where T: ser::Serialize {
     let vec = try!(to_vec(value));
+    // the output is valid UTF-8, but checking it is one pass over bytes that were just written
+    // (see `bench_utf8_check`), which does not justify an `unsafe` unchecked conversion
     let string = try!(String::from_utf8(vec));
     Ok(string)
 }
+
+/// Encode the specified struct into a Hjson `String` that only contains ASCII characters, for
+/// transports that cannot handle anything else. All strings are quoted and other characters
+/// are written as `\u` escapes (see `HjsonFormatter::escape_non_ascii`).
+///
+/// ```rust
+/// let hjson = serde_hjson::to_string_escaped_ascii(&vec!["café", "plain"]).unwrap();
+/// assert_eq!(hjson, "[\n  \"caf\\u00e9\"\n  \"plain\"\n]");
+/// ```
+pub fn to_string_escaped_ascii<T>(value: &T) -> Result<String>
+    where T: ser::Serialize {
+    let mut writer = Vec::with_capacity(128);
+    {
+        let formatter = HjsonFormatter::new().escape_non_ascii(true);
+        let mut ser = Serializer::with_formatter(&mut writer, formatter);
+        try!(value.serialize(&mut ser));
+    }
+    let string = try!(String::from_utf8(writer));
+    Ok(string)
+}
+
+/// Reformats the Hjson text `input` with the default layout (two space indentation, one member
+/// per line) while keeping its structure.
+///
+/// The input is parsed into a `Value` and serialized again, so comments are dropped, strings are
+/// written in the style `to_string` picks for them and numbers are normalized (`1.50` becomes
+/// `1.5`). Object members keep their order with the default `preserve_order` feature, otherwise
+/// they are sorted by key.
+///
+/// ```rust
+/// let pretty = serde_hjson::ser::pretty_print("{a:1,   b:[ 1,2 ]}").unwrap();
+/// assert_eq!(pretty, "{\n  a: 1\n  b:\n  [\n    1\n    2\n  ]\n}");
+/// ```
+pub fn pretty_print(input: &str) -> Result<String> {
+    let value: Value = try!(from_str(input));
+    to_string(&value)
+}
+
+/// Reformats the Hjson text `input` like `pretty_print`, but with the layout of `formatter`,
+/// e.g. for a formatting tool with its own indentation settings. Object members are always
+/// written in the order of the input, so this is only available with the `preserve_order`
+/// feature.
+///
+/// ```rust
+/// use serde_hjson::ser::HjsonFormatter;
+///
+/// let formatter = HjsonFormatter::with_indent(b"    ");
+/// let output = serde_hjson::ser::reformat_preserving_order("{b: 1, a: {d: 2, c: 3}}", formatter).unwrap();
+/// assert_eq!(output, "{\n    b: 1\n    a:\n    {\n        d: 2\n        c: 3\n    }\n}");
+/// ```
+#[cfg(feature = "preserve_order")]
+pub fn reformat_preserving_order(input: &str, formatter: HjsonFormatter) -> Result<String> {
+    let value: Value = try!(from_str(input));
+    let mut writer = Vec::with_capacity(input.len());
+    {
+        let mut ser = Serializer::with_formatter(&mut writer, formatter);
+        try!(ser::Serialize::serialize(&value, &mut ser));
+    }
+    let string = try!(String::from_utf8(writer));
+    Ok(string)
+}
@@ -1,4 +1,5 @@
 
+use std::fmt;
 use std::str;
 use std::io;
 
@@ -8,7 +9,12 @@ pub struct StringReader<Iter: Iterator<Item=u8>> {
     iter: Iter,
     line: usize,
     col: usize,
+    offset: usize,
+    vcol: usize,
+    tab_width: usize,
+    reject_tabs: bool,
     ch: Vec<u8>,
+    capture: Option<Vec<u8>>,
 }
 
 impl<Iter> StringReader<Iter>
@@ -20,7 +26,12 @@ impl<Iter> StringReader<Iter>
             iter: iter,
             line: 1,
             col: 0,
+            offset: 0,
+            vcol: 0,
+            tab_width: 1,
+            reject_tabs: false,
             ch: Vec::new(),
+            capture: None,
         }
     }
 
@@ -30,10 +41,20 @@ impl<Iter> StringReader<Iter>
             Some(b'\n') => {
                 self.line += 1;
                 self.col = 0;
+                self.vcol = 0;
+                self.offset += 1;
                 Some(Ok(b'\n'))
             },
+            Some(b'\t') => {
+                self.col += 1;
+                self.vcol = next_tab_stop(self.vcol, self.tab_width);
+                self.offset += 1;
+                Some(Ok(b'\t'))
+            },
             Some(c) => {
                 self.col += 1;
+                self.vcol += 1;
+                self.offset += 1;
                 Some(Ok(c))
             },
         }
@@ -43,6 +64,33 @@ impl<Iter> StringReader<Iter>
         (self.line, self.col)
     }
 
+    /// Returns the absolute byte offset of the next character to be consumed.
+    pub fn offset(&self) -> usize {
+        self.offset - self.ch.len()
+    }
+
+    /// Returns the column like `pos`, but with tabs advancing to the next multiple of the
+    /// tab width.
+    pub fn vcol(&self) -> usize {
+        self.vcol
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width;
+    }
+
+    pub fn reject_tabs(&self) -> bool {
+        self.reject_tabs
+    }
+
+    pub fn set_reject_tabs(&mut self, reject: bool) {
+        self.reject_tabs = reject;
+    }
+
     pub fn eof(&mut self) -> Result<bool> {
         Ok(try!(self.peek()).is_none())
     }
@@ -71,10 +119,17 @@ impl<Iter> StringReader<Iter>
     }
 
     pub fn eat_char(&mut self) -> u8 {
-        self.ch.remove(0)
+        let ch = self.ch.remove(0);
+        if let Some(ref mut capture) = self.capture {
+            capture.push(ch);
+        }
+        ch
     }
 
     pub fn uneat_char(&mut self, ch: u8) {
+        if let Some(ref mut capture) = self.capture {
+            capture.pop();
+        }
         self.ch.insert(0, ch);
     }
 
@@ -84,13 +139,33 @@ impl<Iter> StringReader<Iter>
             None => {
                 match self.next() {
                     Some(Err(err)) => Err(Error::Io(err)),
-                    Some(Ok(ch)) => Ok(Some(ch)),
+                    Some(Ok(ch)) => {
+                        if let Some(ref mut capture) = self.capture {
+                            capture.push(ch);
+                        }
+                        Ok(Some(ch))
+                    }
                     None => Ok(None),
                 }
             }
         }
     }
 
+    /// Starts recording the consumed input, until `end_capture` is called.
+    pub fn start_capture(&mut self) {
+        self.capture = Some(Vec::new());
+    }
+
+    /// Returns the input consumed since `start_capture`.
+    pub fn end_capture(&mut self) -> Vec<u8> {
+        self.capture.take().unwrap_or_default()
+    }
+
+    /// Returns the bytes that were read ahead from the iterator but not consumed yet.
+    pub fn take_peeked(&mut self) -> Vec<u8> {
+        self.ch.drain(..).collect()
+    }
+
     pub fn next_char_or_null(&mut self) -> Result<u8> {
         Ok(try!(self.next_char()).unwrap_or(b'\x00'))
     }
@@ -108,6 +183,7 @@ impl<Iter> StringReader<Iter>
     pub fn parse_whitespace(&mut self) -> Result<()> {
         loop {
             match try!(self.peek_or_null()) {
+                b'\t' if self.reject_tabs => return Err(self.error(ErrorCode::TabOutsideString)),
                 b' ' | b'\n' | b'\t' | b'\r' => {
                     self.eat_char();
                 }
@@ -124,7 +200,8 @@ impl<Iter> StringReader<Iter>
                             self.eat_char();
                             self.eat_char();
                         },
-                        Some(_) => { self.eat_char(); },
+                        // not a comment, e.g. a quoteless string like /usr/bin
+                        Some(_) => { return Ok(()); },
                         None => return Err(self.error(ErrorCode::TrailingCharacters)), //todo
                     }
                 }
@@ -134,35 +211,69 @@ impl<Iter> StringReader<Iter>
     }
 
     pub fn error(&mut self, reason: ErrorCode) -> Error {
-        Error::Syntax(reason, self.line, self.col)
+        Error::Syntax(reason, self.line, self.col, self.offset)
     }
 }
 
+/// Returns the column after a tab at `col`.
+pub fn next_tab_stop(col: usize, tab_width: usize) -> usize {
+    (col / tab_width + 1) * tab_width
+}
+
 
+/// Parses a Hjson number from an iterator over bytes.
+///
+/// The bytes are pulled from the iterator as they are needed, so the number may be split
+/// over any number of chunks of the underlying input.
+///
+/// ```rust
+/// use serde_hjson::number::ParseNumber;
+///
+/// let mut pn = ParseNumber::new("-12.5e3".bytes());
+/// assert_eq!(pn.parse(false).unwrap(), -12500.0);
+/// ```
 pub struct ParseNumber<Iter: Iterator<Item=u8>> {
     rdr: StringReader<Iter>,
     result: Vec<u8>,
+    extended: bool,
 }
 
 macro_rules! try_or_invalid {
     ($e:expr) => {
         match $e {
             Some(v) => v,
-            None => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+            None => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
         }
     }
 }
 
 impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
 
+    /// Creates a parser reading the number from `iter`.
     #[inline]
     pub fn new(iter: Iter) -> Self {
         ParseNumber {
             rdr: StringReader::new(iter),
             result: Vec::new(),
+            extended: false,
         }
     }
 
+    /// Also accept hexadecimal integers (`0xFF`) and underscores between digits (`1_000`).
+    #[inline]
+    pub fn extended(mut self, extended: bool) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    /// Parses the number, consuming the iterator up to its end.
+    ///
+    /// The number may be followed by whitespace and comments. If `stop_at_next` is true, it
+    /// may also be followed by a `,`, `}` or `]` (or a comment), after which the rest of the
+    /// input is ignored, as when the number is a value inside an object or array. Otherwise
+    /// any other trailing character is an error.
+    ///
+    /// Returns `ErrorCode::InvalidNumber` (without a position) if the input is not a number.
     pub fn parse(&mut self, stop_at_next: bool) -> Result<f64> {
 
         match self.try_parse() {
@@ -186,7 +297,7 @@ impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
                         let res = str::from_utf8(&self.result).unwrap();
                         return Ok(res.parse::<f64>().unwrap());
                     },
-                    _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); },
+                    _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); },
                 }
             },
             Err(e) => Err(e),
@@ -206,7 +317,14 @@ impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
             // There can be only one leading '0'.
             match try!(self.rdr.peek_or_null()) {
                 b'0' ... b'9' => {
-                    return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0));
+                    return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0));
+                }
+                b'_' if self.extended => {
+                    return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0));
+                }
+                b'x' | b'X' if self.extended => {
+                    self.rdr.eat_char();
+                    return self.try_hex();
                 }
                 _ => { }
             }
@@ -218,18 +336,21 @@ impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
                     self.result.push(self.rdr.eat_char());
                     has_value = true;
                 }
+                b'_' if self.extended && has_value => {
+                    try!(self.skip_underscore(false));
+                }
                 b'.' => {
-                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
                     self.rdr.eat_char();
                     return self.try_decimal();
                 }
                 b'e' | b'E' => {
-                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
                     self.rdr.eat_char();
                     return self.try_exponent();
                 }
                 _ => {
-                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+                    if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
                     return Ok(());
                 }
             }
@@ -243,12 +364,13 @@ impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
         // Make sure a digit follows the decimal place.
         match try!(self.rdr.next_char_or_null()) {
             c @ b'0' ... b'9' => { self.result.push(c); }
-             _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+             _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
         };
 
         loop {
             match try!(self.rdr.peek_or_null()) {
                 b'0' ... b'9' => { self.result.push(self.rdr.eat_char()); }
+                b'_' if self.extended => { try!(self.skip_underscore(false)); }
                 _ => { break; }
             }
         }
@@ -272,16 +394,132 @@ impl<Iter: Iterator<Item=u8>> ParseNumber<Iter> {
         // Make sure a digit follows the exponent place.
         match try!(self.rdr.next_char_or_null()) {
             c @ b'0' ... b'9' => { self.result.push(c); }
-            _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0)); }
+            _ => { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
         };
 
         loop {
             match try!(self.rdr.peek_or_null()) {
                 b'0' ... b'9' => { self.result.push(self.rdr.eat_char()); }
+                b'_' if self.extended => { try!(self.skip_underscore(false)); }
                 _ => { break; }
             }
         }
 
         Ok(())
     }
+
+    fn try_hex(&mut self) -> Result<()> {
+        let negative = self.result.first() == Some(&b'-');
+        let mut value: u64 = 0;
+        let mut has_value = false;
+
+        loop {
+            let digit = match try!(self.rdr.peek_or_null()) {
+                c @ b'0' ... b'9' => c - b'0',
+                c @ b'a' ... b'f' => c - b'a' + 10,
+                c @ b'A' ... b'F' => c - b'A' + 10,
+                b'_' if has_value => { try!(self.skip_underscore(true)); continue; }
+                _ => { break; }
+            };
+            self.rdr.eat_char();
+            value = try_or_invalid!(value.checked_mul(16).and_then(|v| v.checked_add(digit as u64)));
+            has_value = true;
+        }
+
+        if !has_value { return Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)); }
+
+        // store the value in decimal for parse()
+        self.result.clear();
+        if negative { self.result.push(b'-'); }
+        self.result.extend(value.to_string().bytes());
+        Ok(())
+    }
+
+    // an underscore must be followed by another digit
+    fn skip_underscore(&mut self, hex: bool) -> Result<()> {
+        self.rdr.eat_char();
+        match try!(self.rdr.peek_or_null()) {
+            b'0' ... b'9' => Ok(()),
+            b'a' ... b'f' | b'A' ... b'F' if hex => Ok(()),
+            _ => Err(Error::Syntax(ErrorCode::InvalidNumber, 0, 0, 0)),
+        }
+    }
+}
+
+const BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as standard base64, with padding.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut res = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+/// Decodes standard base64 with padding, ignoring whitespace. Returns `None` if `text` is
+/// not valid base64.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !(*b as char).is_whitespace()).collect();
+    if digits.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut res = Vec::with_capacity(digits.len() / 4 * 3);
+    for (index, chunk) in digits.chunks(4).enumerate() {
+        let last = index + 1 == digits.len() / 4;
+        let mut n = 0u32;
+        let mut len = 0;
+        for (i, b) in chunk.iter().enumerate() {
+            let value = match *b {
+                b'A' ... b'Z' => b - b'A',
+                b'a' ... b'z' => b - b'a' + 26,
+                b'0' ... b'9' => b - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                // padding is only allowed at the end, for the last one or two digits
+                b'=' if last && i >= 2 && chunk[i..].iter().all(|b| *b == b'=') => continue,
+                _ => return None,
+            };
+            n |= (value as u32) << (18 - 6 * i);
+            len = i;
+        }
+        for i in 0..len {
+            res.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(res)
+}
+
+// format similar to es6
+pub fn fmt_small<N>(value: N, keep_point: bool) -> String
+    where N: fmt::Display + fmt::LowerExp {
+    let f1 = format!("{}", value);
+    let f2 = format!("{:e}", value);
+    if f1.len() <= f2.len() + 1 {
+        if keep_point && !f1.contains('.') { f1 + ".0" } else { f1 }
+    } else {
+        if !f2.contains("e-") { f2.replace("e", "e+") } else { f2 }
+    }
+}
+
+// Checks if the `Serializer` writes the number `value` as its literal `text`, so that nothing
+// is lost by keeping only the value.
+pub fn writes_back(text: &str, value: f64) -> bool {
+    let digits = if text.starts_with('-') { &text[1..] } else { text };
+    if !digits.is_empty() && digits.len() < 16 && digits.bytes().all(|ch| ch >= b'0' && ch <= b'9') &&
+       (digits.len() == 1 || !digits.starts_with('0')) {
+        // most numbers are short integers, which are written as they are
+        return text != "-0";
+    }
+    // as in `Serializer::serialize_f64`
+    let value = if value == 0.0 { 0.0 } else { value };
+    value.is_finite() && fmt_small(value, false) == text
 }
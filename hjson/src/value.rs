@@ -40,6 +40,7 @@ use linked_hash_map::{self, LinkedHashMap};
 
 use std::fmt;
 use std::io;
+use std::iter;
 use std::str;
 use std::vec;
 
@@ -49,6 +50,9 @@ use serde::de;
 use serde::ser;
 
 use error::{Error, ErrorCode};
+use number::{Number, RawNumber, RAW_NUMBER_TOKEN};
+use ser::{CompactFormatter, StringStyle};
+use util::writes_back;
 
 /// Represents a key/value type.
 #[cfg(not(feature = "preserve_order"))]
@@ -64,10 +68,12 @@ pub type MapIntoIter<K, V> = btree_map::IntoIter<K, V>;
 #[cfg(feature = "preserve_order")]
 pub type MapIntoIter<K, V> = linked_hash_map::IntoIter<K, V>;
 
+/// Represents the `Iter` type.
 #[cfg(not(feature = "preserve_order"))]
-type MapVisitor<K, T> = de::impls::BTreeMapVisitor<K, T>;
+pub type MapIter<'a, K, V> = btree_map::Iter<'a, K, V>;
+/// Represents the `Iter` type.
 #[cfg(feature = "preserve_order")]
-type MapVisitor<K, T> = linked_hash_map::serde::LinkedHashMapVisitor<K, T>;
+pub type MapIter<'a, K, V> = linked_hash_map::Iter<'a, K, V>;
 
 /// Represents a Hjson/JSON value
 #[derive(Clone, PartialEq)]
@@ -87,6 +93,13 @@ pub enum Value {
     /// Represents a JSON floating point number
     F64(f64),
 
+    /// Represents a number whose literal is kept because writing its value would change it,
+    /// e.g. `1.50` or `1e3`. The Hjson `Deserializer` reads such numbers into this variant
+    /// and the Hjson `Serializer` writes the literal unchanged, see `RawNumber`. Other
+    /// serializers (e.g. for JSON) see the literal as a string. The numeric accessors treat
+    /// it like the `F64` of its value, so arithmetic through `as_f64` still coerces.
+    Number(RawNumber),
+
     /// Represents a JSON string
     String(String),
 
@@ -212,6 +225,15 @@ impl Value {
         }
     }
 
+    /// If the `Value` is an Object, returns the associated Map, consuming the `Value`.
+    /// Returns None otherwise.
+    pub fn into_object(self) -> Option<Map<String, Value>> {
+        match self {
+            Value::Object(map) => Some(map),
+            _ => None,
+        }
+    }
+
     /// Returns true if the `Value` is an Array. Returns false otherwise.
     pub fn is_array(&self) -> bool {
         self.as_array().is_some()
@@ -235,6 +257,15 @@ impl Value {
         }
     }
 
+    /// If the `Value` is an Array, returns the associated vector, consuming the `Value`.
+    /// Returns None otherwise.
+    pub fn into_array(self) -> Option<Vec<Value>> {
+        match self {
+            Value::Array(list) => Some(list),
+            _ => None,
+        }
+    }
+
     /// Returns true if the `Value` is a String. Returns false otherwise.
     pub fn is_string(&self) -> bool {
         self.as_str().is_some()
@@ -252,11 +283,23 @@ impl Value {
     /// Returns true if the `Value` is a Number. Returns false otherwise.
     pub fn is_number(&self) -> bool {
         match *self {
-            Value::I64(_) | Value::U64(_) | Value::F64(_) => true,
+            Value::I64(_) | Value::U64(_) | Value::F64(_) | Value::Number(_) => true,
             _ => false,
         }
     }
 
+    /// If the `Value` is a number, returns it as a `Number`.
+    /// Returns None otherwise.
+    pub fn as_number(&self) -> Option<Number> {
+        match *self {
+            Value::I64(n) => Some(Number::from(n)),
+            Value::U64(n) => Some(Number::from(n)),
+            Value::F64(n) => Some(Number::from(n)),
+            Value::Number(ref n) => Some(Number::from(n.as_f64())),
+            _ => None,
+        }
+    }
+
     /// Returns true if the `Value` is a i64. Returns false otherwise.
     pub fn is_i64(&self) -> bool {
         match *self {
@@ -273,41 +316,92 @@ impl Value {
         }
     }
 
-    /// Returns true if the `Value` is a f64. Returns false otherwise.
+    /// Returns true if the `Value` is a f64 (or a `Number` literal). Returns false otherwise.
     pub fn is_f64(&self) -> bool {
         match *self {
-            Value::F64(_) => true,
+            Value::F64(_) | Value::Number(_) => true,
             _ => false,
         }
     }
 
-    /// If the `Value` is a number, return or cast it to a i64.
+    /// If the `Value` is a number that is exactly representable as a i64, returns it.
     /// Returns None otherwise.
+    ///
+    /// See `as_f64` for the rules; e.g. `5` and `5.0` both return `Some(5)`, while `5.5`
+    /// returns None.
     pub fn as_i64(&self) -> Option<i64> {
         match *self {
             Value::I64(n) => Some(n),
             Value::U64(n) => NumCast::from(n),
+            Value::F64(n) if n.trunc() == n && n >= -9223372036854775808.0 && n < 9223372036854775808.0 => {
+                Some(n as i64)
+            }
+            Value::Number(ref n) => Value::F64(n.as_f64()).as_i64(),
             _ => None,
         }
     }
 
-    /// If the `Value` is a number, return or cast it to a u64.
+    /// If the `Value` is a number that is exactly representable as a u64, returns it.
     /// Returns None otherwise.
+    ///
+    /// See `as_f64` for the rules.
     pub fn as_u64(&self) -> Option<u64> {
         match *self {
             Value::I64(n) => NumCast::from(n),
             Value::U64(n) => Some(n),
+            Value::F64(n) if n.trunc() == n && n >= 0.0 && n < 18446744073709551616.0 => {
+                Some(n as u64)
+            }
+            Value::Number(ref n) => Value::F64(n.as_f64()).as_u64(),
+            _ => None,
+        }
+    }
+
+    /// If the `Value` is a number that is exactly representable as a i128, returns it.
+    /// Returns None otherwise.
+    ///
+    /// Unlike `as_i64` this covers every `I64` and `U64`. See `as_f64` for the rules.
+    pub fn as_i128(&self) -> Option<i128> {
+        match *self {
+            Value::I64(n) => Some(n as i128),
+            Value::U64(n) => Some(n as i128),
+            Value::F64(n) if n.trunc() == n && n >= -1.7014118346046923e38 && n < 1.7014118346046923e38 => {
+                Some(n as i128)
+            }
+            // a decimal integer literal may be too long for an `f64`
+            Value::Number(ref n) => n.as_i128().or_else(|| Value::F64(n.as_f64()).as_i128()),
             _ => None,
         }
     }
 
-    /// If the `Value` is a number, return or cast it to a f64.
+    /// If the `Value` is a number that is exactly representable as a f64, returns it.
     /// Returns None otherwise.
+    ///
+    /// The numeric accessors convert between the number types only where this is lossless,
+    /// so they do not depend on whether a number was written as `5` or `5.0` (note that
+    /// the `Deserializer` always reads numbers as `F64`, or as `Number` to keep a literal):
+    ///
+    /// * A float converts to an integer type if it has no fractional part and is in the
+    ///   range of the type. `NaN` and the infinities never convert.
+    /// * An integer converts to another integer type if it is in its range.
+    /// * An integer converts to a float if the float holds exactly the same value, which is
+    ///   always the case for magnitudes up to 2^53 (e.g. `i64::MAX` does not convert).
+    ///
+    /// So an `I64` or `U64` that an `f64` does not hold exactly returns None instead of the
+    /// rounded value, which `n as f64` would give.
     pub fn as_f64(&self) -> Option<f64> {
         match *self {
-            Value::I64(n) => NumCast::from(n),
-            Value::U64(n) => NumCast::from(n),
+            Value::I64(n) => {
+                let f = n as f64;
+                if f < 9223372036854775808.0 && f as i64 == n { Some(f) } else { None }
+            }
+            Value::U64(n) => {
+                let f = n as f64;
+                if f < 18446744073709551616.0 && f as u64 == n { Some(f) } else { None }
+            }
             Value::F64(n) => Some(n),
+            // arithmetic on a kept literal goes through its value, e.g. `1.50` gives 1.5
+            Value::Number(ref n) => Some(n.as_f64()),
             _ => None,
         }
     }
@@ -339,6 +433,363 @@ impl Value {
             _ => None,
         }
     }
+
+    /// Returns true if the `Value` is an empty Array, Object or String. Returns false
+    /// otherwise, including for Null, booleans and numbers.
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            Value::String(ref s) => s.is_empty(),
+            Value::Array(ref v) => v.is_empty(),
+            Value::Object(ref map) => map.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// If the `Value` is an Object, returns an iterator over its keys and values, in the
+    /// order of the `Map`. Returns an empty iterator otherwise.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    ///
+    /// let value: Value = serde_hjson::from_str("{a: 1, b: 2}").unwrap();
+    /// let keys: Vec<&str> = value.entries().map(|(key, _)| key).collect();
+    /// assert_eq!(keys, vec!["a", "b"]);
+    /// assert_eq!(Value::Null.entries().count(), 0);
+    /// ```
+    pub fn entries(&self) -> Entries {
+        match *self {
+            Value::Object(ref map) => Entries { iter: Some(map.iter()) },
+            _ => Entries { iter: None },
+        }
+    }
+
+    /// If the `Value` is an Object, returns an iterator over its values, in the order of the
+    /// `Map`. Returns an empty iterator otherwise, also for an Array.
+    pub fn values(&self) -> Values {
+        Values { entries: self.entries() }
+    }
+
+    /// If the `Value` is an Object, removes the members for which `f` returns false, like
+    /// `HashMap::retain`. Nested objects are not affected, see `retain_recursive`.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&str, &Value) -> bool {
+        if let Value::Object(ref mut map) = *self {
+            let removed: Vec<String> = map.iter()
+                .filter(|&(key, value)| !f(key, value))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in removed {
+                map.remove(&key);
+            }
+        }
+    }
+
+    /// Like `retain`, but also removes the members of all nested objects, including those in
+    /// arrays, for which `f` returns false. The members of an object are checked before the
+    /// values that are kept.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    ///
+    /// let mut value: Value = serde_hjson::from_str("{a: 1, _b: 2, c: [{_d: 3, e: 4}]}").unwrap();
+    /// value.retain_recursive(|key, _| !key.starts_with('_'));
+    /// assert_eq!(value, serde_hjson::from_str::<Value>("{a: 1, c: [{e: 4}]}").unwrap());
+    /// ```
+    pub fn retain_recursive<F>(&mut self, mut f: F)
+        where F: FnMut(&str, &Value) -> bool {
+        fn walk<F: FnMut(&str, &Value) -> bool>(value: &mut Value, f: &mut F) {
+            value.retain(&mut *f);
+            match *value {
+                Value::Object(ref mut map) => {
+                    for (_, value) in map.iter_mut() {
+                        walk(value, f);
+                    }
+                }
+                Value::Array(ref mut vec) => {
+                    for value in vec {
+                        walk(value, f);
+                    }
+                }
+                _ => {}
+            }
+        }
+        walk(self, &mut f)
+    }
+
+    /// Returns the leaves of the value with their paths, the keys and array indices leading to
+    /// them joined with `separator`, e.g. for exporting a configuration as environment
+    /// variables. Empty objects and arrays are leaves too, and a value that is neither an object
+    /// nor an array is returned with an empty path.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    ///
+    /// let value: Value = serde_hjson::from_str("{a: {b: 1, c: [true, null]}}").unwrap();
+    /// assert_eq!(value.flatten_keys("."), vec![
+    ///     ("a.b".to_owned(), Value::F64(1.0)),
+    ///     ("a.c.0".to_owned(), Value::Bool(true)),
+    ///     ("a.c.1".to_owned(), Value::Null),
+    /// ]);
+    /// ```
+    pub fn flatten_keys(&self, separator: &str) -> Vec<(String, Value)> {
+        fn walk(value: &Value, path: String, separator: &str, leaves: &mut Vec<(String, Value)>) {
+            let join = |segment: &str| {
+                if path.is_empty() { segment.to_owned() } else { format!("{}{}{}", path, separator, segment) }
+            };
+            match *value {
+                Value::Object(ref map) if !map.is_empty() => {
+                    for (key, value) in map {
+                        walk(value, join(key), separator, leaves);
+                    }
+                }
+                Value::Array(ref vec) if !vec.is_empty() => {
+                    for (i, value) in vec.iter().enumerate() {
+                        walk(value, join(&i.to_string()), separator, leaves);
+                    }
+                }
+                _ => leaves.push((path, value.clone())),
+            }
+        }
+        let mut leaves = Vec::new();
+        walk(self, String::new(), separator, &mut leaves);
+        leaves
+    }
+
+    /// Returns the name of the type of the `Value` for messages, e.g. "expected object,
+    /// found array": one of `"null"`, `"bool"`, `"number"`, `"string"`, `"array"` or
+    /// `"object"`.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::I64(_) | Value::U64(_) | Value::F64(_) | Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    /// Compares two values like `==`, except that numbers are equal if their values are,
+    /// whether they are stored as `I64`, `U64`, `F64` or `Number` (so `1` equals `1.0` and
+    /// `1.50`), and that the members of objects may be in a different order.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    ///
+    /// let value: Value = serde_hjson::from_str("[1, 2.5]").unwrap();
+    /// let other = Value::Array(vec![Value::U64(1), Value::F64(2.5)]);
+    /// assert!(value != other);
+    /// assert!(value.deep_eq(&other));
+    /// ```
+    pub fn deep_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (&Value::Array(ref a), &Value::Array(ref b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.deep_eq(b))
+            }
+            (&Value::Object(ref a), &Value::Object(ref b)) => {
+                a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).map_or(false, |b| a.deep_eq(b)))
+            }
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(a), Some(b)) => a == b,
+                (None, None) => self == other,
+                _ => false,
+            },
+        }
+    }
+
+    /// Returns the value as indented Hjson, the same as `to_string`.
+    ///
+    /// ```rust
+    /// let value: serde_hjson::Value = serde_hjson::from_str("{a: 1, b: [\"x\"]}").unwrap();
+    /// assert_eq!(value.pretty(), "{\n  a: 1\n  b:\n  [\n    x\n  ]\n}");
+    /// ```
+    pub fn pretty(&self) -> String {
+        // Writing a `Value` with the default options cannot fail: the writer is a `Vec`, every
+        // key is a string and no depth limit or key check is set. `to_string` only errors for
+        // those, so the `expect` never fires, see `test_pretty_and_compact`.
+        super::ser::to_string(self).expect("failed to serialize")
+    }
+
+    /// Returns the value as Hjson without any whitespace, written with `CompactFormatter`.
+    ///
+    /// ```rust
+    /// let value: serde_hjson::Value = serde_hjson::from_str("{a: 1, b: [\"x\"]}").unwrap();
+    /// assert_eq!(value.compact(), "{a:1,b:[\"x\"]}");
+    /// ```
+    pub fn compact(&self) -> String {
+        let mut writer = Vec::with_capacity(128);
+        {
+            let mut ser = super::ser::Serializer::with_formatter(&mut writer, CompactFormatter::new());
+            // cannot fail for the same reasons as in `pretty`
+            ser::Serialize::serialize(self, &mut ser).expect("failed to serialize");
+        }
+        // the serializer only writes whole `str`s and ASCII punctuation
+        String::from_utf8(writer).expect("failed to serialize")
+    }
+
+    /// Walks the `Value` depth-first, calling `visitor` for each value with the path that
+    /// leads to it from `self`, see `ValueVisitor`.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    /// use serde_hjson::value::{PathSegment, ValueVisitor};
+    ///
+    /// struct Leaves(Vec<String>);
+    ///
+    /// impl ValueVisitor for Leaves {
+    ///     fn visit_value(&mut self, path: &[PathSegment], value: &Value) {
+    ///         if !value.is_array() && !value.is_object() {
+    ///             let path: Vec<String> = path.iter().map(|segment| segment.to_string()).collect();
+    ///             self.0.push(path.join("."));
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let value: Value = serde_hjson::from_str("{a: {b: 1}, c: [true, null]}").unwrap();
+    /// let mut leaves = Leaves(Vec::new());
+    /// value.visit(&mut leaves);
+    /// assert_eq!(leaves.0, vec!["a.b", "c.0", "c.1"]);
+    /// ```
+    pub fn visit<V: ValueVisitor>(&self, visitor: &mut V) {
+        fn walk<'a, V: ValueVisitor>(value: &'a Value, path: &mut Vec<PathSegment<'a>>, visitor: &mut V) {
+            visitor.visit_value(path, value);
+            match *value {
+                Value::Array(ref list) => {
+                    for (i, element) in list.iter().enumerate() {
+                        path.push(PathSegment::Index(i));
+                        walk(element, path, visitor);
+                        path.pop();
+                    }
+                }
+                Value::Object(ref map) => {
+                    for (key, member) in map.iter() {
+                        path.push(PathSegment::Key(key));
+                        walk(member, path, visitor);
+                        path.pop();
+                    }
+                }
+                _ => return,
+            }
+            visitor.leave_value(path, value);
+        }
+
+        walk(self, &mut Vec::new(), visitor)
+    }
+}
+
+/// A step of the path to a value passed to a `ValueVisitor`: the key of an object member or
+/// the index of an array element.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PathSegment<'a> {
+    /// The value is the member of an object with this key.
+    Key(&'a str),
+    /// The value is the element of an array at this index.
+    Index(usize),
+}
+
+impl<'a> fmt::Display for PathSegment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathSegment::Key(key) => f.write_str(key),
+            PathSegment::Index(index) => fmt::Display::fmt(&index, f),
+        }
+    }
+}
+
+/// Callbacks for a read-only walk over a `Value` with `Value::visit`. The path is empty for
+/// the value the walk started at. Both methods do nothing by default.
+pub trait ValueVisitor {
+    /// Called for every value, before the members of an object or elements of an array.
+    fn visit_value(&mut self, _path: &[PathSegment], _value: &Value) {}
+
+    /// Called for objects and arrays after their members or elements were visited.
+    fn leave_value(&mut self, _path: &[PathSegment], _value: &Value) {}
+}
+
+/// An iterator over the members of an object, see `Value::entries`.
+pub struct Entries<'a> {
+    iter: Option<MapIter<'a, String, Value>>,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (&'a str, &'a Value);
+
+    fn next(&mut self) -> Option<(&'a str, &'a Value)> {
+        match self.iter {
+            Some(ref mut iter) => iter.next().map(|(key, value)| (&key[..], value)),
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.iter {
+            Some(ref iter) => iter.size_hint(),
+            None => (0, Some(0)),
+        }
+    }
+}
+
+/// An iterator over the values of the members of an object, see `Value::values`.
+pub struct Values<'a> {
+    entries: Entries<'a>,
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<&'a Value> {
+        self.entries.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+/// An iterator over the owned elements of an array, see `Value::into_iter`.
+pub struct IntoIter {
+    iter: Option<vec::IntoIter<Value>>,
+}
+
+impl Iterator for IntoIter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self.iter {
+            Some(ref mut iter) => iter.next(),
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.iter {
+            Some(ref iter) => iter.size_hint(),
+            None => (0, Some(0)),
+        }
+    }
+}
+
+impl IntoIterator for Value {
+    type Item = Value;
+    type IntoIter = IntoIter;
+
+    /// If the `Value` is an Array, returns an iterator over its owned elements. Returns an
+    /// empty iterator otherwise, also for an Object; use `into_object` to take its members.
+    ///
+    /// ```rust
+    /// use serde_hjson::Value;
+    ///
+    /// let value: Value = serde_hjson::from_str("[1, 2]").unwrap();
+    /// let items: Vec<Value> = value.into_iter().collect();
+    /// assert_eq!(items, vec![Value::F64(1.0), Value::F64(2.0)]);
+    /// assert_eq!(Value::Null.into_iter().count(), 0);
+    /// ```
+    fn into_iter(self) -> IntoIter {
+        match self {
+            Value::Array(list) => IntoIter { iter: Some(list.into_iter()) },
+            _ => IntoIter { iter: None },
+        }
+    }
 }
 
 impl ser::Serialize for Value {
@@ -352,7 +803,10 @@ impl ser::Serialize for Value {
             Value::I64(v) => serializer.serialize_i64(v),
             Value::U64(v) => serializer.serialize_u64(v),
             Value::F64(v) => serializer.serialize_f64(v),
+            Value::Number(ref v) => v.serialize(serializer),
             Value::String(ref v) => serializer.serialize_str(v),
+            // collections go through the serializer's sequence and map methods like any other
+            // data, which is where the Hjson serializer applies its formatting options
             Value::Array(ref v) => v.serialize(serializer),
             Value::Object(ref v) => v.serialize(serializer),
         }
@@ -435,15 +889,29 @@ impl de::Deserialize for Value {
             }
 
             #[inline]
-            fn visit_map<V>(&mut self, visitor: V) -> Result<Value, V::Error>
+            fn visit_map<V>(&mut self, mut visitor: V) -> Result<Value, V::Error>
                 where V: de::MapVisitor,
             {
-                let values = try!(MapVisitor::new().visit_map(visitor));
+                let mut values = Map::new();
+                while let Some(key) = try!(visitor.visit_key::<String>()) {
+                    if values.is_empty() && key == RAW_NUMBER_TOKEN {
+                        // a number whose literal the deserializer kept, see `VALUE_TOKEN`
+                        let text: String = try!(visitor.visit_value());
+                        try!(visitor.end());
+                        return match text.parse() {
+                            Ok(number) => Ok(Value::Number(number)),
+                            Err(_) => Err(de::Error::invalid_value("expected a number")),
+                        };
+                    }
+                    let value = try!(visitor.visit_value());
+                    values.insert(key, value);
+                }
+                try!(visitor.end());
                 Ok(Value::Object(values))
             }
         }
 
-        deserializer.deserialize(ValueVisitor)
+        deserializer.deserialize_struct(VALUE_TOKEN, &[], ValueVisitor)
     }
 }
 
@@ -495,6 +963,89 @@ impl str::FromStr for Value {
     }
 }
 
+// Comparisons with native types, e.g. `value == 5` or `value == "text"`. Numbers compare by
+// their value like `Number` does, so `Value::F64(5.0) == 5` holds, but a number never equals
+// a string or bool.
+
+impl PartialEq<str> for Value {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == Some(other)
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Value {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == Some(*other)
+    }
+}
+
+impl PartialEq<String> for Value {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == Some(other.as_str())
+    }
+}
+
+impl PartialEq<bool> for Value {
+    fn eq(&self, other: &bool) -> bool {
+        self.as_bool() == Some(*other)
+    }
+}
+
+impl PartialEq<Value> for str {
+    fn eq(&self, other: &Value) -> bool {
+        other == self
+    }
+}
+
+impl<'a> PartialEq<Value> for &'a str {
+    fn eq(&self, other: &Value) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Value> for String {
+    fn eq(&self, other: &Value) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<Value> for bool {
+    fn eq(&self, other: &Value) -> bool {
+        other == self
+    }
+}
+
+macro_rules! impl_partial_eq_number {
+    ($($ty:ty),*) => {
+        $(
+            impl PartialEq<$ty> for Value {
+                fn eq(&self, other: &$ty) -> bool {
+                    self.as_number() == Some(Number::from(*other))
+                }
+            }
+
+            impl PartialEq<Value> for $ty {
+                fn eq(&self, other: &Value) -> bool {
+                    other == self
+                }
+            }
+        )*
+    };
+}
+
+impl_partial_eq_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+// A `Value` for `number`, which keeps its literal only if the `F64` of its value would be
+// written differently.
+fn number_value(number: RawNumber) -> Value {
+    let value = number.as_f64();
+    if writes_back(number.as_str(), value) {
+        Value::F64(value)
+    } else {
+        Value::Number(number)
+    }
+}
+
 /// Create a `serde::Serializer` that serializes a `Serialize`e into a `Value`.
 pub struct Serializer {
     value: Value,
@@ -668,12 +1219,22 @@ impl ser::Serializer for Serializer {
     #[inline]
     fn serialize_newtype_struct<T>(
         &mut self,
-        _name: &'static str,
+        name: &'static str,
         value: T
     ) -> Result<(), Error>
         where T: ser::Serialize,
     {
-        value.serialize(self)
+        try!(value.serialize(&mut *self));
+        if name == RAW_NUMBER_TOKEN {
+            let number = match self.value {
+                Value::String(ref text) => text.parse::<RawNumber>().ok(),
+                _ => None,
+            };
+            if let Some(number) = number {
+                self.value = number_value(number);
+            }
+        }
+        Ok(())
     }
 
     fn serialize_newtype_variant<T>(
@@ -823,7 +1384,7 @@ impl ser::Serializer for Serializer {
     ) -> Result<(), Error> {
         match to_value(&key) {
             Value::String(s) => state.next_key = Some(s),
-            _ => return Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0)),
+            _ => return Err(Error::Syntax(ErrorCode::KeyMustBeAString, 0, 0, 0)),
         };
         Ok(())
     }
@@ -838,7 +1399,7 @@ impl ser::Serializer for Serializer {
             None => {
                 return Err(Error::Syntax(ErrorCode::Custom("serialize_map_value without \
                                                             matching serialize_map_key".to_owned()),
-                                         0, 0));
+                                         0, 0, 0));
             }
         };
         Ok(())
@@ -947,6 +1508,7 @@ impl de::Deserializer for Deserializer {
             Value::I64(v) => visitor.visit_i64(v),
             Value::U64(v) => visitor.visit_u64(v),
             Value::F64(v) => visitor.visit_f64(v),
+            Value::Number(v) => visitor.visit_f64(v.as_f64()),
             Value::String(v) => visitor.visit_string(v),
             Value::Array(v) => {
                 let len = v.len();
@@ -1033,6 +1595,31 @@ impl de::Deserializer for Deserializer {
         visitor.visit_newtype_struct(self)
     }
 
+    /// Keeps the literal of a `Value::Number` for a `Value` or a `RawNumber`, other structs
+    /// are deserialized from their value.
+    #[inline]
+    fn deserialize_struct<V>(
+        &mut self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        mut visitor: V
+    ) -> Result<V::Value, Error>
+        where V: de::Visitor,
+    {
+        let text = match self.value {
+            Some(Value::Number(ref number)) if name == VALUE_TOKEN || name == RAW_NUMBER_TOKEN => {
+                number.to_string()
+            }
+            _ => return self.deserialize(visitor),
+        };
+        self.value = None;
+        if name == RAW_NUMBER_TOKEN {
+            visitor.visit_string(text)
+        } else {
+            visitor.visit_map(de::value::MapDeserializer::new(iter::once((RAW_NUMBER_TOKEN, text)), 1))
+        }
+    }
+
     forward_to_deserialize!{
         deserialize_bool();
         deserialize_usize();
@@ -1057,7 +1644,6 @@ impl de::Deserializer for Deserializer {
         deserialize_map();
         deserialize_unit_struct(name: &'static str);
         deserialize_tuple_struct(name: &'static str, len: usize);
-        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
         deserialize_struct_field();
         deserialize_tuple(len: usize);
         deserialize_ignored_any();
@@ -1381,6 +1967,11 @@ impl<'a> de::Deserializer for MapDeserializer<'a> {
 /// let val = to_value("foo");
 /// assert_eq!(val.as_str(), Some("foo"))
 /// ```
+///
+/// # Panics
+///
+/// Panics if `value` fails to serialize, which happens when it contains a map whose keys are
+/// not strings. Use `Serializer` directly to get the error instead.
 pub fn to_value<T: ?Sized>(value: &T) -> Value
     where T: ser::Serialize,
 {
@@ -1410,3 +2001,166 @@ impl<T: ?Sized> ToJson for T
         to_value(&self)
     }
 }
+
+#[doc(hidden)]
+pub const STYLED_STRING_TOKEN: &'static str = "$__serde_hjson_StyledString";
+
+/// A string that remembers how it was written in the Hjson source.
+///
+/// When deserialized by the Hjson `Deserializer`, `style` records whether the string was
+/// quoteless, quoted or multiline. Serializing it again writes it in the same style where
+/// that is still valid for the value, so that a config file can be edited and written back
+/// with minimal changes. Other deserializers leave `style` as `None`.
+///
+/// Note that `Value` does not keep the style of its strings; use `StyledString` fields in
+/// your own types instead.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StyledString {
+    /// The string itself.
+    pub value: String,
+    /// The style to write the string in, or `None` for the default.
+    pub style: Option<StringStyle>,
+}
+
+impl StyledString {
+    /// Creates a `StyledString` written in the given style.
+    pub fn new<S: Into<String>>(value: S, style: Option<StringStyle>) -> StyledString {
+        StyledString { value: value.into(), style: style }
+    }
+}
+
+impl ser::Serialize for StyledString {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        match self.style {
+            Some(style) => serializer.serialize_newtype_struct(style.token(), &self.value),
+            None => serializer.serialize_str(&self.value),
+        }
+    }
+}
+
+impl de::Deserialize for StyledString {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<StyledString, D::Error>
+        where D: de::Deserializer,
+    {
+        struct StyledStringVisitor;
+
+        impl de::Visitor for StyledStringVisitor {
+            type Value = StyledString;
+
+            #[inline]
+            fn visit_str<E>(&mut self, value: &str) -> Result<StyledString, E> {
+                Ok(StyledString::new(value, None))
+            }
+
+            #[inline]
+            fn visit_string<E>(&mut self, value: String) -> Result<StyledString, E> {
+                Ok(StyledString::new(value, None))
+            }
+
+            #[inline]
+            fn visit_seq<V>(&mut self, mut visitor: V) -> Result<StyledString, V::Error>
+                where V: de::SeqVisitor,
+            {
+                let token: String = match try!(visitor.visit()) {
+                    Some(token) => token,
+                    None => return Err(de::Error::invalid_length(0)),
+                };
+                let value: String = match try!(visitor.visit()) {
+                    Some(value) => value,
+                    None => return Err(de::Error::invalid_length(1)),
+                };
+                try!(visitor.end());
+                Ok(StyledString::new(value, StringStyle::from_token(&token)))
+            }
+        }
+
+        deserializer.deserialize_struct(STYLED_STRING_TOKEN, &["style", "value"], StyledStringVisitor)
+    }
+}
+
+#[doc(hidden)]
+pub const VALUE_TOKEN: &'static str = "$__serde_hjson_Value";
+
+#[doc(hidden)]
+pub const RAW_VALUE_TOKEN: &'static str = "$__serde_hjson_RawValue";
+
+/// A fragment of already formatted Hjson text that is written as a value without being
+/// parsed or escaped, e.g. to splice a template into a generated document.
+///
+/// When deserialized by the Hjson `Deserializer`, a `RawValue` captures the source text of a
+/// value (including any comments inside it) instead of parsing it into a type, so that it can
+/// be processed later or written again in place. The indentation its lines after the first
+/// have in common is removed. The text is only checked to be balanced, not that e.g. its
+/// numbers are valid. Other deserializers only accept a string for it.
+///
+/// The fragment is trusted: it must be a single valid Hjson value, otherwise the output
+/// is invalid. Leading and trailing whitespace is dropped and every line after the first
+/// is indented to the level of the value, so a multi-line fragment should be written
+/// as if it started in column 0 (which also keeps `'''` strings intact, as their
+/// indentation is relative). A fragment is never collapsed onto the line of its parent.
+///
+/// Other serializers, such as the one used by `to_value`, see the fragment as a string.
+///
+/// ```rust
+/// use serde_hjson::Map;
+/// use serde_hjson::value::RawValue;
+///
+/// let mut map = Map::new();
+/// map.insert("server", RawValue::from_string("{\n  port: 8080\n}".to_owned()));
+/// assert_eq!(serde_hjson::to_string(&map).unwrap(),
+///            "{\n  server: {\n    port: 8080\n  }\n}");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawValue {
+    hjson: String,
+}
+
+impl RawValue {
+    /// Creates a `RawValue` from the Hjson text of a value.
+    pub fn from_string(hjson: String) -> RawValue {
+        RawValue { hjson: hjson }
+    }
+
+    /// Returns the Hjson text of the value.
+    pub fn get(&self) -> &str {
+        &self.hjson
+    }
+}
+
+impl ser::Serialize for RawValue {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_VALUE_TOKEN, &self.hjson)
+    }
+}
+
+impl de::Deserialize for RawValue {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<RawValue, D::Error>
+        where D: de::Deserializer,
+    {
+        struct RawValueVisitor;
+
+        impl de::Visitor for RawValueVisitor {
+            type Value = RawValue;
+
+            #[inline]
+            fn visit_str<E>(&mut self, value: &str) -> Result<RawValue, E> {
+                Ok(RawValue::from_string(String::from(value)))
+            }
+
+            #[inline]
+            fn visit_string<E>(&mut self, value: String) -> Result<RawValue, E> {
+                Ok(RawValue::from_string(value))
+            }
+        }
+
+        deserializer.deserialize_struct(RAW_VALUE_TOKEN, &["hjson"], RawValueVisitor)
+    }
+}
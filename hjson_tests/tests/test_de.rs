@@ -0,0 +1,1056 @@
+
+extern crate serde;
+extern crate serde_hjson;
+
+use serde_hjson::{Error, ErrorCode};
+
+#[test]
+fn test_tuple_arity() {
+    let value: (i32, String) = serde_hjson::from_str("[1, \"a\"]").unwrap();
+    assert_eq!(value, (1, "a".to_owned()));
+
+    match serde_hjson::from_str::<(i32, String)>("[1]") {
+        Err(Error::Syntax(ErrorCode::InvalidTupleLength(2, 1), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match serde_hjson::from_str::<(i32, String)>("[1, \"a\", \"b\"]") {
+        Err(Error::Syntax(ErrorCode::InvalidTupleLength(2, 3), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_ignore_unknown_fields() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        name: String,
+        count: i32,
+    }
+
+    let mut hjson = String::from("{\n  name: test\n  ignored: {\n");
+    for i in 0..1000 {
+        hjson.push_str(&format!("    key{}: [1, \"two\\n\\u0033\", {{ \"x\": null, y: true }}]\n", i));
+        hjson.push_str(&format!("    text{}: some quoteless text, with commas ] and }}\n", i));
+        hjson.push_str(&format!("    ml{}:\n      '''\n      multiline {{ [ \"\n      '''\n", i));
+    }
+    hjson.push_str("  }\n  count: 3\n}");
+
+    let config: Config = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(config, Config { name: "test".to_owned(), count: 3 });
+
+    // skipped values must still be well formed
+    assert!(serde_hjson::from_str::<Config>("{name: test, ignored: [1, 2, count: 3}").is_err());
+    assert!(serde_hjson::from_str::<Config>("{name: test, ignored: \"\\q\", count: 3}").is_err());
+}
+
+#[test]
+fn test_extended_numbers() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+
+    fn parse(text: &str, extended: bool) -> Vec<Value> {
+        let mut de = Deserializer::new(text.bytes()).extended_numbers(extended);
+        let value = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        value
+    }
+
+    let text = "[0xFF, -0x10, 1_000, 1_000.000_5\n0x_1\n1__0\n1_\n]";
+    assert_eq!(parse(text, true), vec![
+        Value::F64(255.0), Value::F64(-16.0), Value::F64(1000.0), Value::F64(1000.0005),
+        Value::String("0x_1".to_owned()), Value::String("1__0".to_owned()),
+        Value::String("1_".to_owned())]);
+
+    // strict Hjson by default
+    assert_eq!(parse("[\n0xFF\n1_000\n]", false), vec![
+        Value::String("0xFF".to_owned()), Value::String("1_000".to_owned())]);
+
+    // and strings that look like extended numbers are quoted
+    assert_eq!(serde_hjson::to_string(&vec!["0xFF", "1_000"]).unwrap(), "[\n  \"0xFF\"\n  \"1_000\"\n]");
+}
+
+#[test]
+fn test_empty_document() {
+    use serde_hjson::Value;
+
+    for text in &["", "  \n\t\n", "# just a comment\n// and another\n/* and a block */"] {
+        assert_eq!(serde_hjson::from_str::<Value>(text).unwrap(), Value::Null);
+        assert_eq!(serde_hjson::from_str::<Option<Vec<i32>>>(text).unwrap(), None);
+        assert_eq!(serde_hjson::from_str::<()>(text).unwrap(), ());
+    }
+
+    // a struct needs at least an empty object
+    #[derive(Deserialize)]
+    struct Config {
+        #[allow(dead_code)]
+        name: Option<String>,
+    }
+    assert!(serde_hjson::from_str::<Config>("").is_err());
+    assert!(serde_hjson::from_str::<Config>("{}").is_ok());
+}
+
+#[test]
+fn test_object_as_pairs() {
+    use serde_hjson::Value;
+
+    let pairs: Vec<(String, Value)> = serde_hjson::from_str("{a:1,a:2,b:3}").unwrap();
+    assert_eq!(pairs, vec![
+        ("a".to_owned(), Value::F64(1.0)),
+        ("a".to_owned(), Value::F64(2.0)),
+        ("b".to_owned(), Value::F64(3.0))]);
+
+    // also for the root object without braces, and for nested objects
+    let pairs: Vec<(String, Vec<(String, String)>)> =
+        serde_hjson::from_str("z: {\n  y: one\n  x: two\n  y: three\n}\na: {}\n").unwrap();
+    assert_eq!(pairs, vec![
+        ("z".to_owned(), vec![
+            ("y".to_owned(), "one".to_owned()),
+            ("x".to_owned(), "two".to_owned()),
+            ("y".to_owned(), "three".to_owned())]),
+        ("a".to_owned(), vec![])]);
+
+    // arrays still deserialize as usual
+    let values: Vec<i32> = serde_hjson::from_str("[1, 2]").unwrap();
+    assert_eq!(values, vec![1, 2]);
+}
+
+#[test]
+fn test_raw_value() {
+    use serde_hjson::Value;
+    use serde_hjson::value::RawValue;
+
+    #[derive(Deserialize)]
+    struct Config {
+        name: String,
+        plugin: RawValue,
+        list: RawValue,
+        last: RawValue,
+    }
+
+    let text = "name: test\nplugin: {\n  # keep me\n  a: 1, b: [1, 2]\n  c: \"}\"\n}\nlist: [ x\n]\nlast: quoteless  \n";
+    let config: Config = serde_hjson::from_str(text).unwrap();
+    assert_eq!(config.name, "test");
+    assert_eq!(config.plugin.get(), "{\n  # keep me\n  a: 1, b: [1, 2]\n  c: \"}\"\n}");
+    assert_eq!(config.list.get(), "[ x\n]");
+    assert_eq!(config.last.get(), "quoteless");
+
+    // the captured text can be parsed later
+    let plugin: Value = serde_hjson::from_str(config.plugin.get()).unwrap();
+    assert_eq!(plugin.find("c").unwrap().as_str(), Some("}"));
+
+    // the root object, with or without braces
+    let raw: RawValue = serde_hjson::from_str("a: 1\nb: 2\n").unwrap();
+    assert_eq!(raw.get(), "a: 1\nb: 2");
+    let raw: RawValue = serde_hjson::from_str("  {a: 1}  ").unwrap();
+    assert_eq!(raw.get(), "{a: 1}");
+
+    // unbalanced text is an error
+    assert!(serde_hjson::from_str::<Config>("name: test\nplugin: {\n  a: [1\n}\n").is_err());
+}
+
+#[test]
+fn test_trailing_content() {
+    use serde_hjson::Value;
+
+    // trailing content is an error for a single value
+    for text in &["{a:1} extra", "{a:1}\n}", "[1, 2] 3", "{a:1}{b:2}"] {
+        match serde_hjson::from_str::<Value>(text) {
+            Err(Error::Syntax(ErrorCode::TrailingCharacters, _, _, _)) => {}
+            other => panic!("{:?}: unexpected result: {:?}", text, other),
+        }
+    }
+
+    // but whitespace and comments are allowed
+    for text in &["{a:1}  \n", "{a:1} # done\n", "{a:1} // done", "{a:1}\n/* done */\n"] {
+        assert!(serde_hjson::from_str::<Value>(text).is_ok(), "{:?}", text);
+    }
+
+    // and the stream deserializer reads the next value instead
+    let values: Vec<Value> = serde_hjson::StreamDeserializer::new("{a:1} {b:2}\n".bytes())
+        .collect::<serde_hjson::Result<_>>().unwrap();
+    assert_eq!(values.len(), 2);
+}
+
+#[test]
+fn test_renamed_fields() {
+    // serde 0.8 has no `rename_all`, the fields are renamed one by one
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        #[serde(rename = "serverName")]
+        server_name: String,
+        #[serde(rename = "maxConnections")]
+        max_connections: u32,
+        #[serde(rename = "useTls", default)]
+        use_tls: bool,
+    }
+
+    let expected = Config { server_name: "web".to_owned(), max_connections: 10, use_tls: true };
+
+    // unquoted and quoted keys match the same way
+    let config: Config = serde_hjson::from_str("{\n  serverName: web\n  maxConnections: 10\n  useTls: true\n}").unwrap();
+    assert_eq!(config, expected);
+    let config: Config = serde_hjson::from_str("{\"serverName\": \"web\", \"maxConnections\": 10, \"useTls\": true}").unwrap();
+    assert_eq!(config, expected);
+    let config: Config = serde_hjson::from_str("serverName: web\nmaxConnections: 10\nuseTls: true").unwrap();
+    assert_eq!(config, expected);
+
+    // the Rust names are not accepted
+    match serde_hjson::from_str::<Config>("{\n  server_name: web\n  maxConnections: 10\n}") {
+        Err(Error::Syntax(ErrorCode::MissingField("serverName"), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_comments_in_arrays() {
+    use serde::de::impls::IgnoredAny;
+    use serde_hjson::Value;
+
+    let text = "[ # start\n  1 # one\n  // between\n  \"two\" /* after two */\n  /* before three */ 3,\n  # before end\n  /* block\n  comment */\n  // last\n]";
+    let value: Vec<Value> = serde_hjson::from_str(text).unwrap();
+    assert_eq!(value, vec![Value::F64(1.0), Value::String("two".to_owned()), Value::F64(3.0)]);
+    let value: Vec<Value> = serde_hjson::from_str("[1, /* a */ 2 /* b */, 3 // c\n]").unwrap();
+    assert_eq!(value, vec![Value::F64(1.0), Value::F64(2.0), Value::F64(3.0)]);
+    let value: Vec<Value> = serde_hjson::from_str("[ // only a comment\n]").unwrap();
+    assert!(value.is_empty());
+
+    // the same in objects and in skipped values
+    let text = "{ # start\n  a: 1 # one\n  // between\n  b: [ /* x */ true /* y */ ] // after\n  # before end\n}";
+    let value: Value = serde_hjson::from_str(text).unwrap();
+    assert_eq!(value.find_path(&["b"]), Some(&Value::Array(vec![Value::Bool(true)])));
+    serde_hjson::from_str::<IgnoredAny>(text).unwrap();
+    serde_hjson::from_str::<Vec<IgnoredAny>>("[\n  [1 # a\n  ]\n  # b\n  {x: 1 // c\n  }\n]").unwrap();
+    let value: Value = serde_hjson::from_str("# header\na: [1 # one\n]\n// trailer\n").unwrap();
+    assert_eq!(value.find("a"), Some(&Value::Array(vec![Value::F64(1.0)])));
+}
+
+#[test]
+fn test_from_str_visitor() {
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use serde::de::{MapVisitor, SeqVisitor, Visitor};
+
+    // collects the strings of a document, sharing equal ones
+    struct Interner<'a> {
+        strings: &'a mut HashSet<Rc<String>>,
+    }
+
+    impl<'a> Interner<'a> {
+        fn intern(&mut self, value: String) -> Rc<String> {
+            if let Some(existing) = self.strings.get(&value) {
+                return existing.clone();
+            }
+            let value = Rc::new(value);
+            self.strings.insert(value.clone());
+            value
+        }
+    }
+
+    impl<'a> Visitor for Interner<'a> {
+        type Value = Vec<Rc<String>>;
+
+        fn visit_seq<V: SeqVisitor>(&mut self, mut visitor: V) -> Result<Vec<Rc<String>>, V::Error> {
+            let mut values = Vec::new();
+            while let Some(value) = try!(visitor.visit::<String>()) {
+                values.push(self.intern(value));
+            }
+            try!(visitor.end());
+            Ok(values)
+        }
+
+        fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Vec<Rc<String>>, V::Error> {
+            let mut values = Vec::new();
+            while let Some((key, value)) = try!(visitor.visit::<String, String>()) {
+                values.push(self.intern(key));
+                values.push(self.intern(value));
+            }
+            try!(visitor.end());
+            Ok(values)
+        }
+    }
+
+    let mut strings = HashSet::new();
+    let values = serde_hjson::from_str_visitor("[\n  red\n  green\n  red\n]", Interner { strings: &mut strings }).unwrap();
+    assert_eq!(values.len(), 3);
+    assert!(Rc::ptr_eq(&values[0], &values[2]));
+
+    // the state carries over, also into an object without braces
+    let values = serde_hjson::from_str_visitor("first: green\nsecond: blue", Interner { strings: &mut strings }).unwrap();
+    assert_eq!(values.iter().map(|value| &value[..]).collect::<Vec<_>>(), vec!["first", "green", "second", "blue"]);
+    assert_eq!(strings.len(), 5);
+
+    // the first token decides, after comments and a quoted key
+    let values = serde_hjson::from_str_visitor("# colors\n\"first key\" : green", Interner { strings: &mut strings }).unwrap();
+    assert_eq!(values.iter().map(|value| &value[..]).collect::<Vec<_>>(), vec!["first key", "green"]);
+
+    let result = serde_hjson::from_str_visitor("[a, b] c", Interner { strings: &mut strings });
+    assert!(result.is_err());
+    // a broken object is not read again as a quoteless string
+    let result = serde_hjson::from_str_visitor("first: [green\n", Interner { strings: &mut strings });
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_strict_bare_words() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+
+    fn parse(text: &str) -> Result<Value, Error> {
+        let mut de = Deserializer::new(text.bytes()).strict_bare_words(true);
+        let value = try!(Deserialize::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    // without the option `a, b` is a single string
+    assert!(serde_hjson::from_str::<Value>("[a, b\n]").is_ok());
+    match parse("[a, b\n]") {
+        Err(Error::Syntax(ErrorCode::ReservedCharInQlString, 1, 3, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    for text in &["{\n  a: x: y\n}", "{\n  a: one # two\n}", "{\n  a: b // c\n}", "{\n  a: b}\n}", "[\n  a{\n]"] {
+        match parse(text) {
+            Err(Error::Syntax(ErrorCode::ReservedCharInQlString, _, _, _)) => {}
+            other => panic!("unexpected result for {:?}: {:?}", text, other),
+        }
+    }
+
+    // anything else still parses, including comments after other values
+    let value = parse("{\n  a: hello world\n  b: \"x, y\"\n  c: 1, d: true # yes\n  e:\n    '''\n    [ml]\n    '''\n  f: a/b\n}").unwrap();
+    assert_eq!(value.find("a").and_then(|v| v.as_str()), Some("hello world"));
+    assert_eq!(value.find("e").and_then(|v| v.as_str()), Some("[ml]"));
+    assert_eq!(value.find("f").and_then(|v| v.as_str()), Some("a/b"));
+}
+
+#[test]
+fn test_from_reader_with_trailing() {
+    use std::io::{Cursor, Read};
+    use serde_hjson::Value;
+
+    fn split(input: &str) -> (Value, String) {
+        let (value, mut rest) = serde_hjson::from_reader_with_trailing(Cursor::new(input.as_bytes().to_vec())).unwrap();
+        let mut trailing = String::new();
+        rest.read_to_string(&mut trailing).unwrap();
+        (value, trailing)
+    }
+
+    let (value, rest) = split("{\n  a: 1\n  b: [1, 2]\n}\n--\ndata");
+    assert_eq!(value.find("b").and_then(|b| b.as_array()).map(|b| b.len()), Some(2));
+    assert_eq!(rest, "\n--\ndata");
+
+    let (value, rest) = split("  [1,2]{x}");
+    assert_eq!(value.as_array().map(|a| a.len()), Some(2));
+    assert_eq!(rest, "{x}");
+
+    let (value, rest) = split("\"quoted\" more");
+    assert_eq!(value.as_str(), Some("quoted"));
+    assert_eq!(rest, " more");
+
+    // a quoteless value ends at the end of its line
+    let (value, rest) = split("42\nnext = 1");
+    assert_eq!(value.as_f64(), Some(42.0));
+    assert_eq!(rest, "\nnext = 1");
+
+    let err = serde_hjson::from_reader_with_trailing::<_, Value>(Cursor::new(b"{a: 1".to_vec())).err();
+    match err {
+        Some(Error::Syntax(ErrorCode::EOFWhileParsingObject, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|e| e.to_string())),
+    }
+}
+
+#[test]
+fn test_keys_visit_str() {
+    use serde::de::{Deserialize, Deserializer, MapVisitor, Visitor};
+    use serde::de::impls::IgnoredAny;
+
+    // records how a key reached its visitor
+    #[derive(Debug, PartialEq)]
+    enum Key {
+        Borrowed(usize),
+        Owned,
+    }
+
+    impl Deserialize for Key {
+        fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Key, D::Error> {
+            struct KeyVisitor;
+
+            impl Visitor for KeyVisitor {
+                type Value = Key;
+
+                fn visit_str<E>(&mut self, value: &str) -> Result<Key, E> {
+                    Ok(Key::Borrowed(value.len()))
+                }
+
+                fn visit_string<E>(&mut self, _value: String) -> Result<Key, E> {
+                    Ok(Key::Owned)
+                }
+            }
+
+            deserializer.deserialize_str(KeyVisitor)
+        }
+    }
+
+    struct Keys;
+
+    impl Visitor for Keys {
+        type Value = Vec<Key>;
+
+        fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Vec<Key>, V::Error> {
+            let mut keys = Vec::new();
+            while let Some(key) = try!(visitor.visit_key::<Key>()) {
+                try!(visitor.visit_value::<IgnoredAny>());
+                keys.push(key);
+            }
+            try!(visitor.end());
+            Ok(keys)
+        }
+    }
+
+    let text = "{\n  plain: 1\n  \"quoted key\": 2\n  \"esc\\u00e9\\n\": 3\n}";
+    assert_eq!(serde_hjson::from_str_visitor(text, Keys).unwrap(),
+               vec![Key::Borrowed(5), Key::Borrowed(10), Key::Borrowed(6)]);
+    assert_eq!(serde_hjson::from_str_visitor("a: 1\nbb: 2", Keys).unwrap(),
+               vec![Key::Borrowed(1), Key::Borrowed(2)]);
+}
+
+#[test]
+fn test_ml_string_tab_width() {
+    use serde::de::Deserialize;
+    use serde_hjson::Deserializer;
+
+    fn parse(text: &str, tab_width: usize) -> String {
+        let mut de = Deserializer::new(text.bytes()).tab_width(tab_width);
+        let value = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        value
+    }
+
+    // by default a tab counts like a space
+    let tabs = "\t'''\n\tfirst\n\t\tsecond\n\t'''";
+    assert_eq!(parse(tabs, 1), "first\n\tsecond");
+    assert_eq!(serde_hjson::from_str::<String>(tabs).unwrap(), "first\n\tsecond");
+    assert_eq!(parse(tabs, 4), "first\n\tsecond");
+
+    // a tab before the ''' and spaces in the lines, or the other way round
+    let mixed = "\t'''\n    first\n      second\n\t'''";
+    assert_eq!(parse(mixed, 1), "   first\n     second");
+    assert_eq!(parse(mixed, 4), "first\n  second");
+    assert_eq!(parse("    '''\n\tfirst\n\t  second\n    '''", 4), "first\n  second");
+
+    // a tab that reaches past the indentation is removed as a whole
+    assert_eq!(parse("  '''\n\tfirst\n  '''", 4), "first");
+}
+
+#[test]
+fn test_allow_single_quotes() {
+    use serde::de::Deserialize;
+    use serde::de::impls::IgnoredAny;
+    use serde_hjson::{Deserializer, Value};
+
+    fn parse(text: &str) -> Result<Value, Error> {
+        let mut de = Deserializer::new(text.bytes()).allow_single_quotes(true);
+        let value = try!(Deserialize::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    assert_eq!(parse("'hello'").unwrap(), Value::String("hello".to_owned()));
+    // without the option it is a quoteless string
+    assert_eq!(serde_hjson::from_str::<Value>("'hello'").unwrap(), Value::String("'hello'".to_owned()));
+
+    let value = parse("{\n  'a key': 'say \"hi\"'\n  b: 'it\\'s\\n\\u00e9', c: ''\n  d: '''\n    multi\n    line\n    '''\n}").unwrap();
+    assert_eq!(value.find("a key").and_then(|v| v.as_str()), Some("say \"hi\""));
+    assert_eq!(value.find("b").and_then(|v| v.as_str()), Some("it's\n\u{e9}"));
+    assert_eq!(value.find("c").and_then(|v| v.as_str()), Some(""));
+    assert_eq!(value.find("d").and_then(|v| v.as_str()), Some("multi\nline"));
+
+    // the escape of a single quote is not valid in double quotes
+    match parse("\"it\\'s\"") {
+        Err(Error::Syntax(ErrorCode::InvalidEscape, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match parse("'open") {
+        Err(Error::Syntax(ErrorCode::EOFWhileParsingString, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // also when skipping values
+    let mut de = Deserializer::new("[\n  {'x}': 'y]'}\n]".bytes()).allow_single_quotes(true);
+    let skipped: Vec<IgnoredAny> = Deserialize::deserialize(&mut de).unwrap();
+    de.end().unwrap();
+    assert_eq!(skipped.len(), 1);
+
+    // the serializer quotes strings and keys that start with a single quote
+    let mut value = serde_hjson::Map::new();
+    value.insert("'k".to_owned(), Value::Array(vec![Value::String("'q".to_owned()),
+                                                   Value::String("'''x".to_owned()),
+                                                   Value::String("it's".to_owned())]));
+    let value = Value::Object(value);
+    let hjson = serde_hjson::to_string(&value).unwrap();
+    assert_eq!(hjson, "{\n  \"'k\":\n  [\n    \"'q\"\n    \"'''x\"\n    it's\n  ]\n}");
+    assert_eq!(parse(&hjson).unwrap(), value);
+    assert_eq!(serde_hjson::from_str::<Value>(&hjson).unwrap(), value);
+}
+
+#[test]
+fn test_option_fields() {
+    use serde_hjson::Value;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: Option<String>,
+        #[serde(default)]
+        port: Option<u16>,
+        tags: Option<Vec<String>>,
+    }
+
+    // absent, null and present
+    let config: Config = serde_hjson::from_str("{\n  port: null\n  tags: [\n    a\n  ]\n}").unwrap();
+    assert_eq!(config, Config { name: None, port: None, tags: Some(vec!["a".to_owned()]) });
+    let config: Config = serde_hjson::from_str("name: web\nport: 80 # http\ntags: null // none").unwrap();
+    assert_eq!(config, Config { name: Some("web".to_owned()), port: Some(80), tags: None });
+    let config: Config = serde_hjson::from_str("{}").unwrap();
+    assert_eq!(config, Config { name: None, port: None, tags: None });
+    let config: Config = serde_hjson::from_str("{name: null, port: null}").unwrap();
+    assert_eq!(config, Config { name: None, port: None, tags: None });
+
+    // quoteless strings that start with null are strings
+    let config: Config = serde_hjson::from_str("name: nullable\nport: null\t\ntags: [\n  null pointer\n  \"null\"\n]").unwrap();
+    assert_eq!(config, Config {
+        name: Some("nullable".to_owned()),
+        port: None,
+        tags: Some(vec!["null pointer".to_owned(), "null".to_owned()]),
+    });
+    let config: Config = serde_hjson::from_str("{\n  name: nothing\n}").unwrap();
+    assert_eq!(config.name, Some("nothing".to_owned()));
+    let config: Config = serde_hjson::from_str("{\n  name: null and void\n}").unwrap();
+    assert_eq!(config.name, Some("null and void".to_owned()));
+
+    // the same through a Value
+    let value: Value = serde_hjson::from_str("{\n  port: null\n}").unwrap();
+    let config: Config = serde_hjson::from_value(value).unwrap();
+    assert_eq!(config, Config { name: None, port: None, tags: None });
+}
+
+#[test]
+fn test_unit() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Marker;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Event {
+        name: String,
+        done: (),
+        marker: Marker,
+    }
+
+    let () = serde_hjson::from_str("null").unwrap();
+    let () = serde_hjson::from_str("  null # nothing\n").unwrap();
+    assert_eq!(serde_hjson::from_str::<Marker>("null").unwrap(), Marker);
+    let event: Event = serde_hjson::from_str("name: start\ndone: null\nmarker: null").unwrap();
+    assert_eq!(event, Event { name: "start".to_owned(), done: (), marker: Marker });
+
+    // only null is accepted, and an empty array, which serde reads as the empty tuple
+    let () = serde_hjson::from_str("[]").unwrap();
+    for hjson in ["5", "false", "\"null\"", "nothing", "[\n  null\n]", "{}"].iter() {
+        assert!(serde_hjson::from_str::<()>(hjson).is_err(), "{}", hjson);
+        assert!(serde_hjson::from_str::<Marker>(hjson).is_err(), "{}", hjson);
+    }
+    match serde_hjson::from_str::<()>("5") {
+        Err(Error::Syntax(ErrorCode::InvalidType(_), 1, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(serde_hjson::from_str::<Event>("name: start\ndone: 0\nmarker: null").is_err());
+}
+
+#[test]
+fn test_adjacently_tagged_enum() {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde_hjson::Value;
+
+    // serde 0.8 has no `#[serde(tag = "t", content = "c")]`, the content is buffered as a
+    // `Value` until the tag is known
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Size {
+        w: u32,
+        h: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect(Size),
+    }
+
+    fn from_content<T: Deserialize, E: de::Error>(content: Value) -> Result<T, E> {
+        serde_hjson::from_value(content).map_err(|e| E::custom(e.to_string()))
+    }
+
+    impl Deserialize for Shape {
+        fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Shape, D::Error> {
+            let mut value = try!(Value::deserialize(deserializer));
+            let object = try!(value.as_object_mut().ok_or_else(|| de::Error::invalid_type(de::Type::Map)));
+            let tag = match object.remove("t") {
+                Some(Value::String(tag)) => tag,
+                Some(_) => return Err(de::Error::invalid_type(de::Type::Str)),
+                None => return Err(de::Error::missing_field("t")),
+            };
+            let content = object.remove("c").unwrap_or(Value::Null);
+            if let Some(key) = object.keys().next() {
+                return Err(de::Error::unknown_field(key));
+            }
+            match &tag[..] {
+                "Empty" => Ok(Shape::Empty),
+                "Circle" => from_content(content).map(Shape::Circle),
+                "Rect" => from_content(content).map(Shape::Rect),
+                _ => Err(de::Error::unknown_variant(&tag)),
+            }
+        }
+    }
+
+    let shapes: Vec<Shape> = serde_hjson::from_str("[\n  {\n    t: Circle\n    c: 1.5\n  }\n  \
+                                                    {\n    c: {\n      h: 2\n      w: 3\n    }\n    t: Rect\n  }\n  \
+                                                    {\n    t: Empty\n  }\n]").unwrap();
+    assert_eq!(shapes, vec![Shape::Circle(1.5), Shape::Rect(Size { w: 3, h: 2 }), Shape::Empty]);
+
+    // the content comes first
+    let shape: Shape = serde_hjson::from_str("c: 2\nt: Circle").unwrap();
+    assert_eq!(shape, Shape::Circle(2.0));
+
+    assert!(serde_hjson::from_str::<Shape>("c: 2").is_err());
+    assert!(serde_hjson::from_str::<Shape>("t: Square").is_err());
+    assert!(serde_hjson::from_str::<Shape>("c: {\n  w: 1\n}\nt: Rect").is_err());
+    assert!(serde_hjson::from_str::<Shape>("t: Empty\nx: 1").is_err());
+}
+
+#[test]
+fn test_duplicate_keys() {
+    use std::collections::BTreeMap;
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+    use serde_hjson::de::DuplicateKeys;
+
+    fn parse<T: Deserialize>(text: &str, policy: DuplicateKeys) -> Result<T, Error> {
+        let mut de = Deserializer::new(text.bytes()).duplicate_keys(policy);
+        let value = try!(Deserialize::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    let text = "{a: 1, b: 0, \"a\": 2}";
+    let value: Value = parse(text, DuplicateKeys::LastWins).unwrap();
+    assert_eq!(value.find("a"), Some(&Value::F64(2.0)));
+    assert_eq!(value, serde_hjson::from_str::<Value>(text).unwrap());
+
+    let value: Value = parse(text, DuplicateKeys::FirstWins).unwrap();
+    assert_eq!(value.find("a"), Some(&Value::F64(1.0)));
+    assert_eq!(value.as_object().unwrap().len(), 2);
+    // the skipped value may be anything
+    let value: Value = parse("{\n  a: 1\n  a: {\n    b: [\n      1\n    ]\n  }\n  c: x\n}", DuplicateKeys::FirstWins).unwrap();
+    assert_eq!(value, serde_hjson::from_str::<Value>("{a: 1, c: \"x\"}").unwrap());
+
+    match parse::<Value>(text, DuplicateKeys::Error) {
+        Err(Error::Syntax(ErrorCode::DuplicateKey(ref key), 1, _, _)) if key == "a" => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(parse::<Value>("{a: 1, b: 2}", DuplicateKeys::Error).is_ok());
+    // keys only collide within one object
+    assert!(parse::<Value>("{a: {a: 1}, b: {a: 2}}", DuplicateKeys::Error).is_ok());
+    assert!(parse::<BTreeMap<String, u8>>("{\n  a: 1\n  a: 2\n}", DuplicateKeys::Error).is_err());
+
+    // a sequence of pairs keeps all members
+    let pairs: Vec<(String, u8)> = parse(text, DuplicateKeys::Error).unwrap();
+    assert_eq!(pairs, vec![("a".to_owned(), 1), ("b".to_owned(), 0), ("a".to_owned(), 2)]);
+}
+
+#[test]
+fn test_integral_floats() {
+    use serde::de::Deserialize;
+    use serde_hjson::Deserializer;
+
+    fn parse<T: Deserialize>(text: &str, integral_floats: bool) -> Result<T, Error> {
+        let mut de = Deserializer::new(text.bytes()).integral_floats(integral_floats);
+        let value = try!(Deserialize::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    match parse::<u32>("1e3", false) {
+        Err(Error::Syntax(ErrorCode::InvalidType(_), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(parse::<u32>("1e3", true).unwrap(), 1000);
+    assert_eq!(parse::<i64>("-2.5e1", true).unwrap(), -25);
+    assert_eq!(parse::<Vec<u8>>("[5.0, 2e0]", true).unwrap(), vec![5, 2]);
+    assert!(parse::<u32>("1.5", false).is_err());
+    assert!(parse::<u32>("1.5", true).is_err());
+
+    // a float literal is no longer truncated into an integer field by default
+    #[derive(Debug, Deserialize)]
+    struct Port {
+        port: u16,
+    }
+    match serde_hjson::from_str::<Port>("port: 5.0") {
+        Err(Error::Syntax(ErrorCode::InvalidType(_), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(serde_hjson::from_str::<Port>("port: 5.5").is_err());
+    assert_eq!(parse::<Port>("{port: 5.0}", true).unwrap().port, 5);
+
+    // integer literals are range checked and read exactly
+    assert_eq!(parse::<i32>("-2", false).unwrap(), -2);
+    assert_eq!(parse::<u64>("18446744073709551615", false).unwrap(), u64::max_value());
+    match parse::<u64>("18446744073709551616", false) {
+        Err(Error::Syntax(ErrorCode::NumberOutOfRange(_, "u64"), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(parse::<u8>("300", true).is_err());
+    assert!(parse::<u32>("-1", false).is_err());
+    assert!(parse::<u8>("1e3", true).is_err());
+
+    // floats are unaffected
+    assert_eq!(parse::<f64>("1e3", false).unwrap(), 1000.0);
+}
+
+#[test]
+fn test_number_out_of_range() {
+    #[derive(Debug, Deserialize)]
+    struct Pixel {
+        red: u8,
+        offset: i8,
+    }
+
+    match serde_hjson::from_str::<Pixel>("{\n  red: 300\n  offset: 0\n}") {
+        Err(Error::Syntax(ErrorCode::NumberOutOfRange(ref number, "u8"), _, _, _)) if number == "300" => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let err = serde_hjson::from_str::<u8>("300").unwrap_err();
+    assert_eq!(err.to_string(), "number 300 is out of range for u8 at line 1 column 3");
+
+    // the limits of the types are fine
+    let pixel: Pixel = serde_hjson::from_str("{\n  red: 255\n  offset: -128\n}").unwrap();
+    assert_eq!((pixel.red, pixel.offset), (255, -128));
+    assert!(serde_hjson::from_str::<i8>("-129").is_err());
+    assert!(serde_hjson::from_str::<i8>("128").is_err());
+    assert!(serde_hjson::from_str::<u16>("-1").is_err());
+    assert_eq!(serde_hjson::from_str::<i64>("-9223372036854775808").unwrap(), i64::min_value());
+    match serde_hjson::from_str::<i64>("-9223372036854775809") {
+        Err(Error::Syntax(ErrorCode::NumberOutOfRange(_, "i64"), _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // floats and untyped values have no range
+    assert_eq!(serde_hjson::from_str::<f32>("300").unwrap(), 300.0);
+    assert_eq!(serde_hjson::from_str::<serde_hjson::Value>("1e30").unwrap().as_f64(), Some(1e30));
+}
+
+#[test]
+fn test_record_spans() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+
+    let text = "{\n  name: demo \n  count: 3  # a comment\n  server: {\n    \"host/name\": \"localhost\"\n    ports: [80, 443 ]\n    tls: { cert: \"a.pem\" }\n  }\n}";
+    let mut de = Deserializer::new(text.bytes()).record_spans(true);
+    let value = Value::deserialize(&mut de).unwrap();
+    de.end().unwrap();
+
+    let source = |pointer: &str| {
+        let span = de.spans()[pointer];
+        &text[span.start..span.end]
+    };
+    assert_eq!(source("/name"), "demo");
+    assert_eq!(source("/count"), "3");
+    assert_eq!(source("/server/host~1name"), "\"localhost\"");
+    assert_eq!(source("/server/ports"), "[80, 443 ]");
+    assert_eq!(source("/server/ports/1"), "443");
+    assert_eq!(source("/server/tls"), "{ cert: \"a.pem\" }");
+    assert_eq!(source("/server/tls/cert"), "\"a.pem\"");
+    assert_eq!(de.spans()["/server/ports/1"].start, text.find("443").unwrap());
+
+    // every value with a span is found by its pointer
+    assert_eq!(de.spans().len(), 9);
+    for pointer in de.spans().keys() {
+        assert!(value.pointer(pointer).is_some(), "{}", pointer);
+    }
+
+    // without braces at the root
+    let text = "a: 1\nb: [\n  true\n]\n";
+    let mut de = Deserializer::new_for_root(text.bytes()).record_spans(true);
+    Value::deserialize(&mut de).unwrap();
+    assert_eq!(de.spans()["/b"].start, 8);
+    assert_eq!(de.spans()["/b/0"].start, 12);
+    assert_eq!(de.spans()["/b/0"].end, 16);
+
+    let mut de = Deserializer::new_for_root(text.bytes());
+    Value::deserialize(&mut de).unwrap();
+    assert!(de.spans().is_empty());
+}
+
+#[test]
+fn test_ml_string_crlf() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+
+    let lf = "{\n  text:\n    '''\n    first\n\n      indented\n    last\n    '''\n  next: 1\n}\n";
+    let crlf = lf.replace('\n', "\r\n");
+    let expected = "first\n\n  indented\nlast";
+
+    let value: Value = serde_hjson::from_str(&crlf).unwrap();
+    assert_eq!(value.find("text").and_then(Value::as_str), Some(expected));
+    assert_eq!(value, serde_hjson::from_str::<Value>(lf).unwrap());
+
+    let mut de = Deserializer::new(crlf.bytes()).preserve_crlf(true);
+    let value = Value::deserialize(&mut de).unwrap();
+    assert_eq!(value.find("text").and_then(Value::as_str), Some("first\r\n\r\n  indented\r\nlast"));
+    assert_eq!(value.find("next"), Some(&Value::F64(1.0)));
+
+    // a file with LF line endings is not affected
+    let mut de = Deserializer::new(lf.bytes()).preserve_crlf(true);
+    let value = Value::deserialize(&mut de).unwrap();
+    assert_eq!(value.find("text").and_then(Value::as_str), Some(expected));
+}
+
+#[test]
+fn test_reject_tabs() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+
+    fn parse(text: &str) -> Result<Value, Error> {
+        let mut de = Deserializer::new(text.bytes()).reject_tabs(true);
+        let value = try!(Value::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    let text = "{\n\ta: 1\n}";
+    assert!(serde_hjson::from_str::<Value>(text).is_ok());
+    match parse(text) {
+        Err(Error::Syntax(ErrorCode::TabOutsideString, 2, 1, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let errors = ["[1,\t2]", "{a:\t1}", "{a\t: 1}", "{\n  a: 1\n}\t"];
+    for text in errors.iter() {
+        assert!(serde_hjson::from_str::<Value>(text).is_ok(), "{:?}", text);
+        match parse(text) {
+            Err(Error::Syntax(ErrorCode::TabOutsideString, _, _, _)) => {}
+            other => panic!("unexpected result for {:?}: {:?}", text, other),
+        }
+    }
+
+    // tabs in strings and comments are fine
+    let value = parse("{\n  a: \"x\ty\"\n  b: x\ty\n  # a\tcomment\n  c: '''\n    x\ty\n    '''\n}").unwrap();
+    assert_eq!(value.find("a").and_then(Value::as_str), Some("x\ty"));
+    assert_eq!(value.find("b").and_then(Value::as_str), Some("x\ty"));
+    assert_eq!(value.find("c").and_then(Value::as_str), Some("x\ty"));
+}
+
+#[test]
+fn test_comment_after_opening_bracket() {
+    use std::collections::BTreeMap;
+    use serde_hjson::Value;
+
+    let texts = [
+        "{ # note\n a: 1 }",
+        "{ // note\n a: 1\n}",
+        "{ /* note */ a: 1 }",
+        "{# note\n  a: 1\n}",
+    ];
+    for text in texts.iter() {
+        let value: BTreeMap<String, u32> = serde_hjson::from_str(text).unwrap();
+        assert_eq!(value.get("a"), Some(&1), "{:?}", text);
+        assert_eq!(value.len(), 1);
+    }
+    assert_eq!(serde_hjson::from_str::<Vec<u32>>("[ # note\n 1, 2 ]").unwrap(), vec![1, 2]);
+    assert_eq!(serde_hjson::from_str::<Vec<u32>>("[ /* note */ 1, 2 ]").unwrap(), vec![1, 2]);
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        name: String,
+    }
+
+    // also when the members are skipped or kept as pairs
+    let text = "{ # header\n  extra: { # skipped\n    b: [ # too\n      1\n    ]\n  }\n  name: x\n}";
+    assert_eq!(serde_hjson::from_str::<Config>(text).unwrap(), Config { name: "x".to_owned() });
+    let pairs: Vec<(String, Value)> = serde_hjson::from_str(text).unwrap();
+    assert_eq!(pairs[1], ("name".to_owned(), Value::String("x".to_owned())));
+}
+
+#[test]
+fn test_shared_pointers() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        host: String,
+        ports: Vec<u16>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        server: Arc<Server>,
+        backup: Rc<Server>,
+        name: Box<str>,
+        tags: Box<[String]>,
+    }
+
+    let text = "{\n  server: {\n    host: localhost\n    ports: [80, 443]\n  }\n  backup: {\n    host: spare\n    ports: []\n  }\n  name: demo\n  tags: [\n    a\n    b\n  ]\n}";
+    let config: Config = serde_hjson::from_str(text).unwrap();
+    assert_eq!(*config.server, Server { host: "localhost".to_owned(), ports: vec![80, 443] });
+    assert_eq!(config.backup.host, "spare");
+    assert_eq!(&*config.name, "demo");
+    assert_eq!(&*config.tags, &["a".to_owned(), "b".to_owned()][..]);
+
+    // serde has no impl for `Arc<str>`, so take the string out of a `Box<str>`
+    let name: Arc<str> = Arc::from(config.name);
+    assert_eq!(&*name, "demo");
+}
+
+#[test]
+fn test_from_str_partial() {
+    use serde_hjson::{Map, Value};
+
+    // a truncated object keeps the members read so far, nested ones included
+    let (value, err) = serde_hjson::from_str_partial("{\n  name: web\n  port: 80\n  tls: {\n    cert: a.pem\n    key: ");
+    let mut tls = Map::new();
+    tls.insert("cert".to_owned(), Value::String("a.pem".to_owned()));
+    let mut expected = Map::new();
+    expected.insert("name".to_owned(), Value::String("web".to_owned()));
+    expected.insert("port".to_owned(), Value::F64(80.0));
+    expected.insert("tls".to_owned(), Value::Object(tls));
+    assert_eq!(value, Value::Object(expected));
+    match err {
+        Some(Error::Syntax(ErrorCode::EOFWhileParsingValue, _, _, _)) => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // also without braces and for arrays
+    let (value, err) = serde_hjson::from_str_partial("a: 1\nb: [\n  x\n  y\n");
+    assert_eq!(value.find("a"), Some(&Value::F64(1.0)));
+    assert_eq!(value.find("b"), Some(&Value::Array(vec![Value::String("x".to_owned()), Value::String("y".to_owned())])));
+    assert!(err.is_some());
+    let (value, err) = serde_hjson::from_str_partial("[1, 2, {a: 3, b: }");
+    assert_eq!(value.as_array().unwrap().len(), 3);
+    assert_eq!(value.as_array().unwrap()[2].find("a"), Some(&Value::F64(3.0)));
+    assert!(err.is_some());
+
+    // complete documents decode like `from_str`
+    let (value, err) = serde_hjson::from_str_partial("{a: 1}");
+    assert_eq!(value, serde_hjson::from_str::<Value>("{a: 1}").unwrap());
+    assert!(err.is_none());
+    assert!(serde_hjson::from_str_partial("a: 1\nb: 2").1.is_none());
+    assert_eq!(serde_hjson::from_str_partial("hello").0, Value::String("hello".to_owned()));
+    let (value, err) = serde_hjson::from_str_partial("# nothing\n");
+    assert_eq!(value, Value::Null);
+    assert!(err.is_none());
+
+    // trailing characters after a complete value
+    let (value, err) = serde_hjson::from_str_partial("[1] 2");
+    assert_eq!(value, Value::Array(vec![Value::F64(1.0)]));
+    match err {
+        Some(Error::Syntax(ErrorCode::TrailingCharacters, _, _, _)) => {}
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn test_long_quoted_strings() {
+    use std::borrow::Cow;
+    use serde_hjson::Value;
+
+    #[derive(Deserialize)]
+    struct Doc<'a> {
+        body: Cow<'a, str>,
+        list: Vec<String>,
+        name: String,
+    }
+
+    // long strings are handed over instead of copied, short ones after them still decode
+    let long: String = (0..10000).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+    let text = format!("{{\n  body: \"{0}\"\n  list: [\"{0}\", \"x\\ty\", \"{0}\\n\"]\n  name: \"doc\"\n}}", long);
+    let doc: Doc = serde_hjson::from_str(&text).unwrap();
+    assert_eq!(doc.body, long);
+    assert_eq!(doc.list, vec![long.clone(), "x\ty".to_owned(), format!("{}\n", long)]);
+    assert_eq!(doc.name, "doc");
+
+    let value: Value = serde_hjson::from_str(&format!("[\"{}\"]", long)).unwrap();
+    assert_eq!(value.as_array().unwrap()[0].as_str(), Some(&long[..]));
+}
+
+#[test]
+fn test_long_quoted_keys() {
+    use serde::de::Deserialize;
+    use serde_hjson::{Deserializer, Value};
+    use serde_hjson::de::DuplicateKeys;
+
+    fn parse(text: &str, policy: DuplicateKeys) -> Result<Value, Error> {
+        let mut de = Deserializer::new(text.bytes()).duplicate_keys(policy);
+        let value = try!(Value::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    // keys as long as the strings that are handed over instead of copied
+    let a: String = ::std::iter::repeat('a').take(5000).collect();
+    let b: String = ::std::iter::repeat('b').take(5000).collect();
+    let text = format!("{{\"{}\": 1, \"{}\": 2}}", a, b);
+    for &policy in &[DuplicateKeys::FirstWins, DuplicateKeys::LastWins, DuplicateKeys::Error] {
+        let value = parse(&text, policy).unwrap();
+        assert_eq!(value.as_object().unwrap().len(), 2);
+        assert_eq!(value.find(&a), Some(&Value::F64(1.0)));
+        assert_eq!(value.find(&b), Some(&Value::F64(2.0)));
+    }
+
+    let text = format!("{{\"{0}\": 1, \"{0}\": 2}}", a);
+    assert_eq!(parse(&text, DuplicateKeys::FirstWins).unwrap().find(&a), Some(&Value::F64(1.0)));
+    assert_eq!(parse(&text, DuplicateKeys::LastWins).unwrap().find(&a), Some(&Value::F64(2.0)));
+    match parse(&text, DuplicateKeys::Error) {
+        Err(Error::Syntax(ErrorCode::DuplicateKey(ref key), _, _, _)) if *key == a => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let text = format!("{{\"{}\": 1, \"{}\": 2}}", a, b);
+    let mut de = Deserializer::new(text.bytes()).record_spans(true);
+    Value::deserialize(&mut de).unwrap();
+    let spans: Vec<String> = de.spans().keys().cloned().collect();
+    assert_eq!(spans, vec![format!("/{}", a), format!("/{}", b)]);
+}
+
+#[test]
+fn test_validate() {
+    use serde_hjson::Value;
+
+    let valid = [
+        "{\n  a: 1\n  b: [1, 2, \"x\"]\n  c: { d: null }\n}",
+        "a: 1\nb: quoteless text\nc: '''\n  multi\n  line\n  '''\n",
+        "# only a comment\n",
+        "",
+        "[]",
+        "\"text\"",
+        "{a: \"\\u00e9\"} // trailing comment",
+    ];
+    for text in valid.iter() {
+        assert!(serde_hjson::validate(text).is_ok(), "{:?}", text);
+        assert!(serde_hjson::from_str::<Value>(text).is_ok(), "{:?}", text);
+    }
+
+    // the same errors as decoding into a `Value`
+    let invalid = [
+        "{\n  a: 1\n  b: [1, 2\n}",
+        "{a: 1} extra",
+        "{\n  a: \"\\q\"\n}",
+        "a: 1\nb: [\n",
+        "{\n  a 1\n}",
+        "[\"open",
+    ];
+    for text in invalid.iter() {
+        let err = serde_hjson::validate(text).unwrap_err();
+        let expected = serde_hjson::from_str::<Value>(text).unwrap_err();
+        assert_eq!(err.to_string(), expected.to_string(), "{:?}", text);
+    }
+    match serde_hjson::validate("[1, 2") {
+        Err(Error::Syntax(ErrorCode::EOFWhileParsingList, 1, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
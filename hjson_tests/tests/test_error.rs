@@ -0,0 +1,49 @@
+
+extern crate serde;
+extern crate serde_hjson;
+
+use serde_hjson::{Error, ErrorCode, Value};
+
+#[test]
+fn test_error_offset() {
+    let text = "{\n  a: 1\n  b: ]\n}";
+    let err = serde_hjson::from_str::<Value>(text).unwrap_err();
+    match err {
+        Error::Syntax(_, line, col, offset) => {
+            assert_eq!((line, col), (3, 6));
+            assert_eq!(offset, 15);
+            assert_eq!(&text[offset - 1..offset], "]");
+        }
+        _ => panic!("expected a syntax error"),
+    }
+    assert_eq!(err.offset(), Some(15));
+}
+
+#[test]
+fn test_error_display_with_source() {
+    let text = "{\n  a: 1\n  b: ]\n}";
+    let err = serde_hjson::from_str::<Value>(text).unwrap_err();
+    assert_eq!(err.display_with_source(text),
+               format!("{}\n  |\n3 |   b: ]\n  |      ^", err));
+}
+
+#[test]
+fn test_invalid_escape_in_key() {
+    let text = "{\n  \"a\\qb\": 1\n}";
+    match serde_hjson::from_str::<Value>(text) {
+        Err(Error::Syntax(ErrorCode::InvalidEscapeInKey, 2, 6, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // also in a skipped object, but not in a value
+    use serde::de::impls::IgnoredAny;
+    match serde_hjson::from_str::<Vec<IgnoredAny>>("[\n  {\n    \"a\\qb\": 1\n  }\n]") {
+        Err(Error::Syntax(ErrorCode::InvalidEscapeInKey, 3, _, _)) => {}
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("expected an error"),
+    }
+    match serde_hjson::from_str::<Value>("{\n  a: \"a\\qb\"\n}") {
+        Err(Error::Syntax(ErrorCode::InvalidEscape, 2, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
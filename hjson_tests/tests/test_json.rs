@@ -0,0 +1,45 @@
+
+extern crate serde_hjson;
+extern crate serde_json;
+
+use std::fs::{self, File};
+use std::io::prelude::*;
+use serde_hjson::Value;
+
+// compares numbers by value, as Hjson reads all of them as floats, and objects
+// regardless of their order
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Array(ref a), &Value::Array(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same(a, b))
+        }
+        (&Value::Object(ref a), &Value::Object(ref b)) => {
+            a.len() == b.len() && a.iter().all(|(k, va)| b.get(k).map_or(false, |vb| same(va, vb)))
+        }
+        _ if a.is_number() => a.as_number() == b.as_number(),
+        _ => a == b,
+    }
+}
+
+#[test]
+fn test_json_conformance() {
+    let mut count = 0;
+
+    for entry in fs::read_dir("./assets/json/").unwrap() {
+        let path = entry.unwrap().path();
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+
+        let expected: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let expected = serde_hjson::to_value(&expected);
+        let actual: Value = match serde_hjson::from_str(&text) {
+            Ok(value) => value,
+            Err(err) => panic!("{}: {}", path.display(), err),
+        };
+
+        assert!(same(&actual, &expected), "{}: {:?} != {:?}", path.display(), actual, expected);
+        count += 1;
+    }
+
+    assert!(count > 0);
+}
@@ -0,0 +1,131 @@
+
+extern crate serde_hjson;
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde_hjson::{Number, RawNumber, Value};
+
+#[test]
+fn test_number_eq_int_float() {
+    // integers and integral floats are equal and hash the same
+    assert_eq!(Number::from(1), Number::from(1.0));
+    assert_eq!(Number::from(-3i8), Number::from(-3.0f32));
+    assert_eq!(Number::from(0), Number::from(-0.0));
+    assert!(Number::from(1) != Number::from(1.5));
+    assert!(Number::from(u64::max_value()) != Number::from(-1));
+    assert_eq!(Number::from(::std::f64::NAN), Number::from(::std::f64::NAN));
+
+    let mut set = HashSet::new();
+    set.insert(Number::from(1));
+    assert!(set.contains(&Number::from(1.0)));
+    assert!(!set.contains(&Number::from(2.0)));
+
+    assert!(Number::from(1.0).is_f64());
+    assert_eq!(Number::from(1).as_u64(), Some(1));
+    assert_eq!(Number::from(1.0).as_u64(), None);
+}
+
+#[test]
+fn test_number_from_value() {
+    let value: Value = serde_hjson::from_str("{a: 1, b: 1.0}").unwrap();
+    let a = value.find("a").and_then(|v| v.as_number());
+    let b = value.find("b").and_then(|v| v.as_number());
+    assert!(a.is_some());
+    assert_eq!(a, b);
+
+    let n: Vec<Number> = serde_hjson::from_str("[2.5]").unwrap();
+    assert_eq!(n, vec![Number::from(2.5)]);
+    assert_eq!(serde_hjson::to_string(&Number::from(42)).unwrap(), "42");
+}
+
+#[test]
+fn test_parse_number_streamed() {
+    use serde_hjson::number::ParseNumber;
+
+    let chunks: Vec<&[u8]> = vec![b"-1", b"2.", b"5e", b"+1"];
+    let bytes = chunks.into_iter().flat_map(|chunk| chunk.iter().map(|b| *b));
+    assert_eq!(ParseNumber::new(bytes).parse(false).unwrap(), -125.0);
+
+    // trailing punctuators are only accepted with `stop_at_next`
+    assert_eq!(ParseNumber::new("42, 43".bytes()).parse(true).unwrap(), 42.0);
+    assert!(ParseNumber::new("42, 43".bytes()).parse(false).is_err());
+    assert!(ParseNumber::new("4x2".bytes()).parse(true).is_err());
+}
+
+#[test]
+fn test_value_numeric_coercion() {
+    assert_eq!(Value::I64(5).as_f64(), Some(5.0));
+    assert_eq!(Value::F64(5.0).as_u64(), Some(5));
+    assert_eq!(Value::F64(-5.0).as_i64(), Some(-5));
+    assert_eq!(Value::U64(5).as_i128(), Some(5));
+    assert_eq!(Value::U64(u64::max_value()).as_i128(), Some(u64::max_value() as i128));
+
+    // lossy conversions
+    assert_eq!(Value::F64(5.5).as_i64(), None);
+    assert_eq!(Value::F64(-1.0).as_u64(), None);
+    assert_eq!(Value::I64(-1).as_u64(), None);
+    assert_eq!(Value::U64(u64::max_value()).as_i64(), None);
+    assert_eq!(Value::F64(1e20).as_i64(), None);
+    assert_eq!(Value::F64(1e20).as_u64(), None);
+    assert_eq!(Value::F64(1e20).as_i128(), Some(100000000000000000000));
+    assert_eq!(Value::I64(i64::max_value()).as_f64(), None);
+    assert_eq!(Value::U64((1 << 53) + 1).as_f64(), None);
+    assert_eq!(Value::F64(::std::f64::NAN).as_i128(), None);
+    assert_eq!(Value::F64(::std::f64::INFINITY).as_u64(), None);
+    assert_eq!(Value::String("5".to_owned()).as_f64(), None);
+
+    // parsed numbers are floats, but read back as integers
+    let value: Value = serde_hjson::from_str("{\n  a: 5\n  b: 5.0\n}").unwrap();
+    assert_eq!(value.find("a").unwrap().as_u64(), Some(5));
+    assert_eq!(value.find("b").unwrap().as_i64(), Some(5));
+}
+
+#[test]
+fn test_raw_number() {
+    let prices: BTreeMap<String, RawNumber> =
+        serde_hjson::from_str("{\n  apple: 1.50\n  pear: 2e3\n}").unwrap();
+    assert_eq!(prices["apple"].as_str(), "1.50");
+    assert_eq!(prices["apple"].as_f64(), 1.5);
+    assert_eq!(prices["pear"].as_f64(), 2000.0);
+    assert_eq!(serde_hjson::to_string(&prices).unwrap(), "{\n  apple: 1.50\n  pear: 2e3\n}");
+
+    // to_value keeps the literal unless an f64 is written the same way
+    assert_eq!(serde_hjson::to_value(&prices["apple"]), Value::Number(prices["apple"].clone()));
+    assert_eq!(serde_hjson::to_value(&"2.5".parse::<RawNumber>().unwrap()), Value::F64(2.5));
+
+    let result: Result<BTreeMap<String, RawNumber>, _> = serde_hjson::from_str("{\n  apple: cheap\n}");
+    assert!(result.is_err());
+    assert!("1.5.0".parse::<RawNumber>().is_err());
+
+    // other deserializers give a float, which is written the way the serializer would
+    let number: RawNumber = serde_hjson::from_value(Value::F64(1e300)).unwrap();
+    assert_eq!(number.as_str(), "1e+300");
+    assert_eq!(number.as_f64(), 1e300);
+    assert!(serde_hjson::from_value::<RawNumber>(Value::F64(::std::f64::NAN)).is_err());
+    assert!(serde_hjson::from_value::<RawNumber>(Value::F64(::std::f64::INFINITY)).is_err());
+    assert_eq!(RawNumber::from(u128::max_value()).as_f64(), u128::max_value() as f64);
+}
+
+#[test]
+fn test_exponent_forms() {
+    use serde_hjson::number::ParseNumber;
+
+    let cases = [("1e10", 1e10), ("1e+10", 1e10), ("1E+10", 1e10), ("1.5E-3", 1.5e-3),
+                 ("1.5e-3", 1.5e-3), ("-2E3", -2e3), ("0e+0", 0.0)];
+    for &(text, expected) in cases.iter() {
+        assert_eq!(ParseNumber::new(text.bytes()).parse(false).unwrap(), expected, "{}", text);
+        let f: f64 = serde_hjson::from_str(text).unwrap();
+        assert_eq!(f, expected, "{}", text);
+        let v: Vec<f64> = serde_hjson::from_str(&format!("[\n  {}\n  {}, 1\n]", text, text)).unwrap();
+        assert_eq!(v, vec![expected, expected, 1.0], "{}", text);
+    }
+    assert!(ParseNumber::new("1e".bytes()).parse(false).is_err());
+    assert!(ParseNumber::new("1e+".bytes()).parse(false).is_err());
+
+    // the serializer writes `e+` forms, which read back unchanged
+    for &f in [1e21, 1.5e-7, 1e300, -2.5e-300].iter() {
+        let text = serde_hjson::to_string(&f).unwrap();
+        assert_eq!(serde_hjson::from_str::<f64>(&text).unwrap(), f, "{}", text);
+    }
+    assert_eq!(serde_hjson::to_string(&1e21).unwrap(), "1e+21");
+}
@@ -0,0 +1,1877 @@
+
+extern crate serde;
+extern crate serde_hjson;
+extern crate serde_json;
+
+use std::cell::Cell;
+
+use serde::ser::Serialize;
+use serde_hjson::Serializer;
+
+#[test]
+fn test_skip_none_in_seq() {
+    let value = vec![Some(1), None, Some(3)];
+    assert_eq!(serde_hjson::to_string(&value).unwrap(), "[\n  1\n  null\n  3\n]");
+
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_seq(true);
+    value.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  1\n  3\n]");
+
+    // each element is only serialized once to find out if it is `None`
+    let calls = Cell::new(0);
+    let counted = vec![Counted(&calls, Some(vec![1])), Counted(&calls, None), Counted(&calls, Some(vec![]))];
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_seq(true);
+    counted.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  [\n    1\n  ]\n  []\n]");
+    assert_eq!(calls.get(), 3);
+
+    // an array whose elements are all skipped is empty, also as a member
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_seq(true);
+    vec![None::<i32>, None].serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[]");
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_seq(true);
+    vec![vec![None::<i32>], vec![Some(1)]].serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  []\n  [\n    1\n  ]\n]");
+    let mut map = ::std::collections::BTreeMap::new();
+    map.insert("a", vec![None::<i32>, None]);
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_seq(true);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a: []\n}");
+}
+
+// Counts how often the wrapped value is serialized.
+struct Counted<'a, T>(&'a Cell<usize>, T);
+
+impl<'a, T: Serialize> Serialize for Counted<'a, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.0.set(self.0.get() + 1);
+        self.1.serialize(serializer)
+    }
+}
+
+#[test]
+fn test_wrapped_string() {
+    use serde_hjson::ser::WrappedString;
+
+    let words = ["lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit"];
+    let mut text = String::new();
+    let mut i = 0;
+    while text.len() < 200 {
+        if i > 0 { text.push(' '); }
+        text.push_str(words[i % words.len()]);
+        i += 1;
+    }
+    text.truncate(200);
+    let text = text.trim_right().to_owned();
+
+    let hjson = serde_hjson::to_string(&WrappedString::new(&text, 60)).unwrap();
+    assert!(hjson.contains("'''"));
+
+    let wrapped: String = serde_hjson::from_str(&hjson).unwrap();
+    let lines: Vec<&str> = wrapped.split('\n').collect();
+    assert!(lines.len() > 1);
+    assert!(lines.iter().all(|line| line.len() <= 60));
+    assert_eq!(lines.join(" "), text);
+
+    // short strings are left alone
+    assert_eq!(serde_hjson::to_string(&WrappedString::new("short", 60)).unwrap(), "short");
+}
+
+#[test]
+fn test_styled_string_round_trip() {
+    use serde_hjson::ser::StringStyle;
+    use serde_hjson::value::StyledString;
+
+    #[derive(Serialize, Deserialize)]
+    struct Config {
+        plain: StyledString,
+        quoted: StyledString,
+        multi: StyledString,
+    }
+
+    let hjson = "{\n  plain: hello world\n  quoted: \"hello\"\n  multi:\n    '''\n    line 1\n    line 2\n    '''\n}";
+    let config: Config = serde_hjson::from_str(hjson).unwrap();
+    assert_eq!(config.plain, StyledString::new("hello world", Some(StringStyle::Quoteless)));
+    assert_eq!(config.quoted, StyledString::new("hello", Some(StringStyle::Quoted)));
+    assert_eq!(config.multi, StyledString::new("line 1\nline 2", Some(StringStyle::Multiline)));
+    assert_eq!(serde_hjson::to_string(&config).unwrap(), hjson);
+
+    // a style that no longer fits the value falls back to the default
+    let changed = StyledString::new("true", Some(StringStyle::Quoteless));
+    assert_eq!(serde_hjson::to_string(&changed).unwrap(), "\"true\"");
+}
+
+#[test]
+fn test_net_addrs() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Server {
+        ip: IpAddr,
+        ipv6: Ipv6Addr,
+        addr: SocketAddr,
+        addr6: SocketAddr,
+    }
+
+    let server = Server {
+        ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        ipv6: "::1".parse().unwrap(),
+        addr: "127.0.0.1:8080".parse().unwrap(),
+        addr6: "[fe80::1]:443".parse().unwrap(),
+    };
+
+    // values starting with a punctuator such as `:` or `[` must be quoted
+    let hjson = serde_hjson::to_string(&server).unwrap();
+    assert_eq!(hjson, "{\n  ip: 10.0.0.1\n  ipv6: \"::1\"\n  addr: 127.0.0.1:8080\n  addr6: \"[fe80::1]:443\"\n}");
+    assert_eq!(serde_hjson::from_str::<Server>(&hjson).unwrap(), server);
+}
+
+#[test]
+fn test_collapse_single_member() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Port {
+        port: u16,
+    }
+
+    #[derive(Serialize)]
+    struct Name {
+        name: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Listen {
+        ports: Vec<u16>,
+    }
+
+    #[derive(Serialize)]
+    struct Server {
+        name: &'static str,
+        http: Port,
+        listen: Listen,
+    }
+
+    fn collapsed<T: Serialize>(value: &T) -> String {
+        let formatter = HjsonFormatter::new().collapse_single_member(true);
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let server = Server { name: "web", http: Port { port: 80 }, listen: Listen { ports: vec![80] } };
+    assert_eq!(collapsed(&server),
+               "{\n  name: web\n  http: { port: 80 }\n  listen:\n  {\n    ports:\n    [\n      80\n    ]\n  }\n}");
+    assert_eq!(serde_hjson::to_string(&Port { port: 80 }).unwrap(), "{\n  port: 80\n}");
+
+    // strings are quoted so that they do not swallow the closing brace
+    let hjson = collapsed(&Name { name: "web" });
+    assert_eq!(hjson, "{ name: \"web\" }");
+    let value: serde_hjson::Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value.find("name").and_then(|v| v.as_str()), Some("web"));
+    let value: serde_hjson::Value = serde_hjson::from_str(&collapsed(&server)).unwrap();
+    assert_eq!(value.find_path(&["http", "port"]).and_then(|v| v.as_f64()), Some(80.0));
+
+    // the value is only serialized once to find out if it fits on the line
+    let calls = Cell::new(0);
+    let mut port = ::std::collections::BTreeMap::new();
+    port.insert("port", Counted(&calls, vec![80]));
+    assert_eq!(collapsed(&port), "{\n  port:\n  [\n    80\n  ]\n}");
+    port.insert("port", Counted(&calls, vec![]));
+    assert_eq!(collapsed(&port), "{\n  port: []\n}");
+    let mut name = ::std::collections::BTreeMap::new();
+    name.insert("name", Counted(&calls, "web"));
+    assert_eq!(collapsed(&name), "{ name: \"web\" }");
+    assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn test_align_keys() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Limits {
+        cpu: u32,
+        memory_mb: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Service {
+        name: &'static str,
+        port: u16,
+        description: &'static str,
+        limits: Limits,
+        tags: Vec<&'static str>,
+    }
+
+    fn aligned<T: Serialize>(value: &T) -> String {
+        let formatter = HjsonFormatter::new().align_keys(true);
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let service = Service {
+        name: "web",
+        port: 80,
+        description: "front end",
+        limits: Limits { cpu: 2, memory_mb: 512 },
+        tags: vec!["a"],
+    };
+    let hjson = aligned(&service);
+    assert_eq!(hjson, "{\n  name:        web\n  port:        80\n  description: front end\n  \
+                       limits:\n  {\n    cpu:       2\n    memory_mb: 512\n  }\n  tags:\n  [\n    a\n  ]\n}");
+    let value: serde_hjson::Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value, serde_hjson::from_str::<serde_hjson::Value>(&serde_hjson::to_string(&service).unwrap()).unwrap());
+
+    // quoted keys are measured as written
+    let mut map = BTreeMap::new();
+    map.insert("a b", 1);
+    map.insert("c", 2);
+    assert_eq!(aligned(&map), "{\n  \"a b\": 1\n  c:     2\n}");
+    assert_eq!(aligned(&BTreeMap::<String, u32>::new()), "{}");
+}
+
+#[test]
+fn test_blank_line_between_members() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Limits {
+        cpu: u32,
+        memory_mb: u32,
+    }
+
+    #[derive(Serialize)]
+    struct Service {
+        name: &'static str,
+        limits: Limits,
+        tags: Vec<&'static str>,
+    }
+
+    let service = Service {
+        name: "web",
+        limits: Limits { cpu: 2, memory_mb: 512 },
+        tags: vec!["a", "b"],
+    };
+    let formatter = HjsonFormatter::new().blank_line_between_members(true);
+    let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+    service.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+    assert_eq!(hjson, "{\n  name: web\n\n  limits:\n  {\n    cpu: 2\n    memory_mb: 512\n  }\n\n  \
+                       tags:\n  [\n    a\n    b\n  ]\n}");
+    let value: serde_hjson::Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value, serde_hjson::from_str::<serde_hjson::Value>(&serde_hjson::to_string(&service).unwrap()).unwrap());
+
+    // a root array is not spread out
+    let formatter = HjsonFormatter::new().blank_line_between_members(true);
+    let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+    vec![1, 2].serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  1\n  2\n]");
+}
+
+#[test]
+fn test_compact_arrays_of_primitives() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::HjsonFormatter;
+    use serde_hjson::Value;
+
+    fn compact<T: Serialize>(value: &T) -> String {
+        let formatter = HjsonFormatter::new().compact_arrays_of_primitives(true);
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    assert_eq!(compact(&vec![1, 2, 3]), "[1, 2, 3]");
+    assert_eq!(compact(&(true, (), "a b", 'c', 1.5)), "[true, null, \"a b\", \"c\", 1.5]");
+    assert_eq!(compact(&Vec::<u8>::new()), "[]");
+
+    let mut a = BTreeMap::new();
+    a.insert("a", 1);
+    assert_eq!(compact(&vec![a]), "[\n  {\n    a: 1\n  }\n]");
+
+    // the elements before a collection or a long string are not lost
+    let value: Value = serde_hjson::from_str("[\n  1\n  x\n  [2, 3]\n  {}\n]").unwrap();
+    let hjson = compact(&value);
+    assert_eq!(hjson, "[\n  1\n  x\n  [2, 3]\n  {}\n]");
+    assert_eq!(serde_hjson::from_str::<Value>(&hjson).unwrap(), value);
+    assert_eq!(compact(&vec!["short", "a string of more than twenty characters"]),
+               "[\n  short\n  a string of more than twenty characters\n]");
+    let hjson = compact(&vec!["two\nlines"]);
+    assert!(hjson.contains("'''"));
+    assert_eq!(serde_hjson::from_str::<Vec<String>>(&hjson).unwrap(), vec!["two\nlines"]);
+
+    #[derive(Serialize)]
+    struct Service {
+        name: &'static str,
+        ports: Vec<u16>,
+        hosts: Vec<Vec<&'static str>>,
+    }
+
+    let service = Service { name: "web", ports: vec![80, 443], hosts: vec![vec!["a", "b"]] };
+    let hjson = compact(&service);
+    assert_eq!(hjson, "{\n  name: web\n  ports: [80, 443]\n  hosts:\n  [\n    [\"a\", \"b\"]\n  ]\n}");
+    let value: Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value, serde_hjson::from_str::<Value>(&serde_hjson::to_string(&service).unwrap()).unwrap());
+
+    // each element is only serialized once, also if the array does not fit on one line
+    let calls = Cell::new(0);
+    let counted = |text: &str| -> String {
+        let value: Value = serde_hjson::from_str(text).unwrap();
+        compact(&value.as_array().unwrap().iter().map(|v| Counted(&calls, v)).collect::<Vec<_>>())
+    };
+    assert_eq!(counted("[1, \"a b\"]"), "[1, \"a b\"]");
+    assert_eq!(counted("[1, \"a b\", [2]]"), "[\n  1\n  a b\n  [2]\n]");
+    assert_eq!(calls.get(), 5);
+}
+
+#[test]
+fn test_flush_after_members() {
+    use std::io;
+    use serde_hjson::ser::HjsonFormatter;
+
+    // records how much had been written at each flush
+    #[derive(Default)]
+    struct Counting {
+        written: usize,
+        writes: usize,
+        flushes: Vec<usize>,
+    }
+
+    impl io::Write for Counting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written += buf.len();
+            self.writes += 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.written);
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        id: u32,
+        name: String,
+    }
+
+    let rows: Vec<Row> = (0..1000).map(|id| Row { id: id, name: format!("row {}", id) }).collect();
+
+    let formatter = HjsonFormatter::new().flush_after_members(true);
+    let mut ser = Serializer::with_formatter(Counting::default(), formatter);
+    rows.serialize(&mut ser).unwrap();
+    let counting = ser.into_inner();
+    // two members and the row itself
+    assert_eq!(counting.flushes.len(), 3 * rows.len());
+    assert!(counting.flushes[0] > 0 && counting.flushes[0] < 30, "{:?}", &counting.flushes[..3]);
+    assert!(counting.flushes.windows(2).all(|w| w[0] <= w[1]));
+    assert!(*counting.flushes.last().unwrap() < counting.written);
+
+    // without the option the output is still written as it goes, but never flushed
+    let mut ser = Serializer::new(Counting::default());
+    rows.serialize(&mut ser).unwrap();
+    assert!(ser.into_inner().flushes.is_empty());
+    let mut ser = Serializer::new(Counting::default());
+    rows.serialize(&mut ser).unwrap();
+    ser.flush().unwrap();
+    let counting = ser.into_inner();
+    assert!(counting.writes > rows.len());
+    assert_eq!(counting.flushes, vec![counting.written]);
+
+    // nothing is flushed while the members of an object are held back
+    let formatter = HjsonFormatter::new().flush_after_members(true).align_keys(true);
+    let mut ser = Serializer::with_formatter(Counting::default(), formatter);
+    rows[0].serialize(&mut ser).unwrap();
+    assert!(ser.into_inner().flushes.is_empty());
+}
+
+#[test]
+fn test_equals_separator() {
+    use std::collections::BTreeMap;
+    use serde::de::Deserialize;
+    use serde_hjson::ser::HjsonFormatter;
+    use serde_hjson::{Deserializer, Value};
+
+    #[derive(Serialize)]
+    struct Server {
+        host: &'static str,
+        port: u16,
+        tags: Vec<&'static str>,
+        limits: BTreeMap<&'static str, u32>,
+    }
+
+    fn to_string<T: Serialize>(value: &T, formatter: HjsonFormatter) -> String {
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    fn parse(text: &str) -> Value {
+        let mut de = Deserializer::new(text.bytes()).allow_equals_separator(true);
+        let value = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        value
+    }
+
+    let mut limits = BTreeMap::new();
+    limits.insert("cpu", 2);
+    let server = Server { host: "a=b", port: 80, tags: vec!["x"], limits: limits };
+    let expected: Value = serde_hjson::from_str(&serde_hjson::to_string(&server).unwrap()).unwrap();
+
+    let hjson = to_string(&server, HjsonFormatter::new().equals_separator(true));
+    assert_eq!(hjson, "{\n  host = a=b\n  port = 80\n  tags =\n  [\n    x\n  ]\n  limits =\n  {\n    cpu = 2\n  }\n}");
+    assert_eq!(parse(&hjson), expected);
+
+    let hjson = to_string(&server, HjsonFormatter::new().equals_separator(false));
+    assert_eq!(hjson, "{\n  host=a=b\n  port=80\n  tags=\n  [\n    x\n  ]\n  limits=\n  {\n    cpu=2\n  }\n}");
+    assert_eq!(parse(&hjson), expected);
+
+    // with the other options
+    let formatter = HjsonFormatter::new().equals_separator(true).align_keys(true).collapse_single_member(true);
+    let hjson = to_string(&server, formatter);
+    assert_eq!(hjson, "{\n  host =   a=b\n  port =   80\n  tags =\n  [\n    x\n  ]\n  limits = { cpu = 2 }\n}");
+    assert_eq!(parse(&hjson), expected);
+
+    // quoted keys, and both separators in one document
+    assert_eq!(parse("{\n  \"a b\" = 1\n  c: 2\n}"), serde_hjson::from_str::<Value>("{\n  \"a b\": 1\n  c: 2\n}").unwrap());
+    // without the option `=` is part of the key
+    assert!(serde_hjson::from_str::<Value>("{\n  a = 1\n}").is_err());
+    assert_eq!(serde_hjson::from_str::<Value>("{\n  a=b: 1\n}").unwrap().find("a=b"), Some(&Value::F64(1.0)));
+}
+
+#[test]
+fn test_formatter_escape_byte() {
+    use std::io;
+    use serde_hjson::ser::{Formatter, HjsonFormatter};
+
+    struct SlashFormatter<'a>(HjsonFormatter<'a>);
+
+    impl<'a> Formatter for SlashFormatter<'a> {
+        fn open<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.0.open(writer, ch)
+        }
+
+        fn comma<W: io::Write>(&mut self, writer: &mut W, first: bool) -> serde_hjson::Result<()> {
+            self.0.comma(writer, first)
+        }
+
+        fn colon<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            self.0.colon(writer)
+        }
+
+        fn close<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.0.close(writer, ch)
+        }
+
+        fn newline<W: io::Write>(&mut self, writer: &mut W, add_indent: i32) -> serde_hjson::Result<()> {
+            self.0.newline(writer, add_indent)
+        }
+
+        fn start_value<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            self.0.start_value(writer)
+        }
+
+        fn escape_byte(&self, byte: u8) -> Option<&'static [u8]> {
+            if byte == b'/' { Some(b"\\/") } else { None }
+        }
+    }
+
+    let value = vec!["[a/b]", "a/b"];
+    let mut ser = Serializer::with_formatter(Vec::new(), SlashFormatter(HjsonFormatter::new()));
+    value.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+
+    // only quoted strings are escaped
+    assert_eq!(hjson, "[\n  \"[a\\/b]\"\n  a/b\n]");
+    assert_eq!(serde_hjson::from_str::<Vec<String>>(&hjson).unwrap(), value);
+    assert_eq!(serde_hjson::to_string(&value).unwrap(), "[\n  \"[a/b]\"\n  a/b\n]");
+}
+
+#[test]
+fn test_raw_str() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::RawStr;
+
+    let mut raw = BTreeMap::new();
+    let mut plain = BTreeMap::new();
+    for key in &["alpha", "beta", "gamma"] {
+        raw.insert(RawStr::new(key), vec![RawStr::new(key)]);
+        plain.insert(*key, vec![*key]);
+    }
+
+    // safe strings come out exactly as they would without the fast path
+    assert_eq!(serde_hjson::to_string(&raw).unwrap(), serde_hjson::to_string(&plain).unwrap());
+
+    // other serializers see a plain string
+    assert_eq!(serde_json::to_string(&RawStr::new("alpha")).unwrap(), "\"alpha\"");
+}
+
+#[test]
+fn test_skip_none_in_map() {
+    use std::collections::BTreeMap;
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_owned(), Some(1));
+    map.insert("b".to_owned(), None);
+    map.insert("c".to_owned(), Some(3));
+    assert_eq!(serde_hjson::to_string(&map).unwrap(), "{\n  a: 1\n  b: null\n  c: 3\n}");
+
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a: 1\n  c: 3\n}");
+
+    // each value is only serialized once, also if the keys are aligned
+    let calls = Cell::new(0);
+    let mut counted = BTreeMap::new();
+    counted.insert("a", Counted(&calls, Some(vec![1])));
+    counted.insert("bb", Counted(&calls, None));
+    counted.insert("ccc", Counted(&calls, Some(vec![])));
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true);
+    counted.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a:\n  [\n    1\n  ]\n  ccc: []\n}");
+    let formatter = serde_hjson::ser::HjsonFormatter::new().align_keys(true);
+    let mut ser = Serializer::with_formatter(Vec::new(), formatter).skip_none_in_map(true);
+    counted.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a:\n  [\n    1\n  ]\n  ccc: []\n}");
+    assert_eq!(calls.get(), 6);
+
+    // a map whose members are all skipped is empty, whatever the layout
+    let mut nones = BTreeMap::new();
+    nones.insert("a", None::<i32>);
+    nones.insert("b", None);
+    let mut outer = BTreeMap::new();
+    outer.insert("x", nones.clone());
+    let formatters = vec![
+        serde_hjson::ser::HjsonFormatter::new(),
+        serde_hjson::ser::HjsonFormatter::new().align_keys(true),
+        serde_hjson::ser::HjsonFormatter::new().collapse_single_member(true),
+    ];
+    for formatter in formatters {
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter).skip_none_in_map(true);
+        (&nones, &outer).serialize(&mut ser).unwrap();
+        let text = String::from_utf8(ser.into_inner()).unwrap();
+        assert!(text == "[\n  {}\n  {\n    x: {}\n  }\n]" || text == "[\n  {}\n  { x: {} }\n]", "{}", text);
+    }
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true).sort_keys_by(|a, b| b.cmp(a));
+    outer.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  x: {}\n}");
+
+    // struct fields are not affected
+    #[derive(Serialize)]
+    struct Entry {
+        value: Option<i32>,
+    }
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true);
+    Entry { value: None }.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  value: null\n}");
+}
+
+// Data with `None` in arrays and maps, for `test_layout_combinations`. A map is given the
+// length in `Map.0` up front, which decides whether it may be collapsed.
+enum Data {
+    Num(i32),
+    Str(&'static str),
+    List(Vec<Option<Data>>),
+    Map(Option<usize>, Vec<(&'static str, Option<Data>)>),
+}
+
+thread_local!(static DATA_CALLS: Cell<usize> = Cell::new(0));
+
+impl Serialize for Data {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        DATA_CALLS.with(|calls| calls.set(calls.get() + 1));
+        match *self {
+            Data::Num(n) => serializer.serialize_i32(n),
+            Data::Str(s) => serializer.serialize_str(s),
+            Data::List(ref elements) => elements.serialize(serializer),
+            Data::Map(len, ref members) => {
+                let mut state = try!(serializer.serialize_map(len));
+                for &(key, ref value) in members {
+                    try!(serializer.serialize_map_key(&mut state, key));
+                    try!(serializer.serialize_map_value(&mut state, value));
+                }
+                serializer.serialize_map_end(state)
+            }
+        }
+    }
+}
+
+impl Data {
+    fn map(members: Vec<(&'static str, Option<Data>)>) -> Data {
+        Data::Map(Some(members.len()), members)
+    }
+
+    // The data as it should be written: without the `None` entries if `skip` is set and with
+    // the members in reverse order if `sort` is set. Maps keep the length they announce.
+    fn expected(&self, skip: bool, sort: bool) -> Data {
+        let entry = |value: &Option<Data>| value.as_ref().map(|v| v.expected(skip, sort));
+        match *self {
+            Data::Num(n) => Data::Num(n),
+            Data::Str(s) => Data::Str(s),
+            Data::List(ref elements) => Data::List(elements.iter()
+                .filter(|v| !skip || v.is_some()).map(&entry).collect()),
+            Data::Map(len, ref members) => {
+                let mut members: Vec<_> = members.iter()
+                    .filter(|&&(_, ref v)| !skip || v.is_some()).map(|&(k, ref v)| (k, entry(v))).collect();
+                if sort {
+                    members.sort_by(|a, b| b.0.cmp(a.0));
+                }
+                Data::Map(len, members)
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        let entry = |value: &Option<Data>| value.as_ref().map_or(0, Data::count);
+        1 + match *self {
+            Data::Num(_) | Data::Str(_) => 0,
+            Data::List(ref elements) => elements.iter().map(&entry).sum(),
+            Data::Map(_, ref members) => members.iter().map(|&(_, ref v)| entry(v)).sum(),
+        }
+    }
+}
+
+#[test]
+fn test_layout_combinations() {
+    use regex::Regex;
+    use serde_hjson::Value;
+    use serde_hjson::ser::HjsonFormatter;
+
+    fn to_string(data: &Data, options: [bool; 5]) -> String {
+        let [skip_none, compact, collapse, align, sort] = options;
+        let formatter = HjsonFormatter::new()
+            .compact_arrays_of_primitives(compact)
+            .collapse_single_member(collapse)
+            .align_keys(align);
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter)
+            .skip_none_in_seq(skip_none)
+            .skip_none_in_map(skip_none);
+        if sort {
+            ser = ser.sort_keys_by(|a, b| b.cmp(a));
+        }
+        data.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let samples = vec![
+        Data::map(vec![
+            ("a", Some(Data::Num(1))),
+            ("bb", None),
+            ("c", Some(Data::List(vec![Some(Data::Num(1)), None, Some(Data::Str("x"))]))),
+            ("d", Some(Data::map(vec![("e", None)]))),
+            ("f", Some(Data::List(vec![None]))),
+            ("g", Some(Data::map(vec![("h", Some(Data::Str("two\nlines")))]))),
+            ("i", Some(Data::map(vec![("j", None), ("k", Some(Data::Num(2)))]))),
+        ]),
+        Data::List(vec![
+            None,
+            Some(Data::map(vec![("x", Some(Data::Num(1)))])),
+            Some(Data::List(vec![Some(Data::Num(1)), Some(Data::Num(2)), None])),
+            Some(Data::List(vec![Some(Data::Num(1)), None, Some(Data::Str("a string too long for one line"))])),
+            Some(Data::List(vec![None, Some(Data::List(vec![]))])),
+            None,
+        ]),
+        Data::map(vec![("only", Some(Data::map(vec![("inner", None)])))]),
+        Data::Map(None, vec![("a", None), ("b", None)]),
+        Data::List(vec![None, None]),
+    ];
+
+    // nothing is left of an array or object but its brackets if none of its entries is written
+    let empty = Regex::new(r"[\[{]\s*\n\s*[\]}]").unwrap();
+
+    for data in &samples {
+        for bits in 0..32 {
+            let options = [bits & 1 != 0, bits & 2 != 0, bits & 4 != 0, bits & 8 != 0, bits & 16 != 0];
+            let (skip_none, sort) = (options[0], options[4]);
+
+            DATA_CALLS.with(|calls| calls.set(0));
+            let text = to_string(data, options);
+            assert_eq!(DATA_CALLS.with(|calls| calls.get()), data.count(), "{:?}\n{}", options, text);
+
+            // skipping and sorting only leave out and reorder what is otherwise written
+            let expected = data.expected(skip_none, sort);
+            assert_eq!(text, to_string(&expected, [false, options[1], options[2], options[3], false]), "{:?}", options);
+            assert!(!empty.is_match(&text), "{:?}\n{}", options, text);
+
+            let value: Value = serde_hjson::from_str(&text).unwrap();
+            assert!(value.deep_eq(&serde_hjson::to_value(&expected)), "{:?}\n{}", options, text);
+        }
+    }
+
+    // all of them at once
+    assert_eq!(to_string(&samples[0], [true; 5]),
+               "{\n  i:\n  {\n    k: 2\n  }\n  g:\n  {\n    h:\n      '''\n      two\n      lines\n      '''\n  }\n  \
+                f: []\n  d: {}\n  c: [1, \"x\"]\n  a: 1\n}");
+}
+
+#[test]
+fn test_pretty_print() {
+    let messy = "\n   name:   test\n# a comment\n      list: [1,\n 2,    3]\n  nested:{ a:\"x\",\n\t\tb  :   true  }\n";
+    assert_eq!(serde_hjson::pretty_print(messy).unwrap(),
+               "{\n  name: test\n  list:\n  [\n    1\n    2\n    3\n  ]\n  nested:\n  {\n    a: x\n    b: true\n  }\n}");
+
+    assert!(serde_hjson::pretty_print("{a: [}").is_err());
+}
+
+#[test]
+fn test_reformat_preserving_order() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    let input = "zebra: 1\nmango: {\n  y: true\n  b: [3, 1]\n}\napple: x\n";
+    let output = serde_hjson::reformat_preserving_order(input, HjsonFormatter::new()).unwrap();
+    assert_eq!(output, "{\n  zebra: 1\n  mango:\n  {\n    y: true\n    b:\n    [\n      3\n      1\n    ]\n  }\n  apple: x\n}");
+
+    let keys: Vec<&str> = output.lines().filter_map(|line| line.find(':').map(|i| line[..i].trim())).collect();
+    assert_eq!(keys, vec!["zebra", "mango", "y", "b", "apple"]);
+
+    let formatter = HjsonFormatter::with_indent(b"\t").align_keys(true);
+    assert_eq!(serde_hjson::reformat_preserving_order(input, formatter).unwrap(),
+               "{\n\tzebra: 1\n\tmango:\n\t{\n\t\ty: true\n\t\tb:\n\t\t[\n\t\t\t3\n\t\t\t1\n\t\t]\n\t}\n\tapple: x\n}");
+
+    assert!(serde_hjson::reformat_preserving_order("{a: [}", HjsonFormatter::new()).is_err());
+}
+
+#[test]
+fn test_raw_value() {
+    use serde_hjson::Value;
+    use serde_hjson::value::RawValue;
+
+    #[derive(Serialize)]
+    struct Inner {
+        name: &'static str,
+        server: RawValue,
+    }
+
+    #[derive(Serialize)]
+    struct Outer {
+        inner: Inner,
+        list: Vec<RawValue>,
+    }
+
+    let template = "{\n  # from the template\n  port: 8080\n  motd:\n    '''\n    hello\n    world\n    '''\n}\n";
+    let value = Outer {
+        inner: Inner { name: "test", server: RawValue::from_string(template.to_owned()) },
+        list: vec![RawValue::from_string("1".to_owned()), RawValue::from_string("[2, 3]".to_owned())],
+    };
+
+    let hjson = serde_hjson::to_string(&value).unwrap();
+    assert_eq!(hjson, "{\n  inner:\n  {\n    name: test\n    server: {\n      # from the template\n      port: 8080\n      motd:\n        '''\n        hello\n        world\n        '''\n    }\n  }\n  list:\n  [\n    1\n    [2, 3]\n  ]\n}");
+
+    let parsed: Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(parsed.find_path(&["inner", "server", "motd"]).unwrap().as_str(), Some("hello\nworld"));
+    assert_eq!(parsed.find("list").unwrap().as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_raw_value_written_back() {
+    use serde_hjson::Value;
+    use serde_hjson::value::RawValue;
+
+    #[derive(Deserialize)]
+    struct Server {
+        plugin: RawValue,
+    }
+
+    #[derive(Deserialize)]
+    struct Config {
+        server: Server,
+    }
+
+    #[derive(Serialize)]
+    struct Flat {
+        plugin: RawValue,
+    }
+
+    // a value captured two levels deep reads as if it started in column 0, so writing it
+    // back one level up keeps its multiline string and comments as they were
+    let text = "{\n  server: {\n    plugin: {\n      # greeting\n      motd:\n        '''\n        hello\n          \
+                world\n        '''\n      ports: [\n        80 # http\n        443\n      ]\n    }\n  }\n}";
+    let config: Config = serde_hjson::from_str(text).unwrap();
+    let plugin = "{\n  # greeting\n  motd:\n    '''\n    hello\n      world\n    '''\n  ports: [\n    80 # http\n    \
+                  443\n  ]\n}";
+    assert_eq!(config.server.plugin.get(), plugin);
+
+    let flat = serde_hjson::to_string(&Flat { plugin: config.server.plugin }).unwrap();
+    assert_eq!(flat, format!("{{\n  plugin: {}\n}}", plugin.replace("\n", "\n  ")));
+    let parsed: Value = serde_hjson::from_str(&flat).unwrap();
+    assert_eq!(parsed.find_path(&["plugin", "motd"]).unwrap().as_str(), Some("hello\n  world"));
+}
+
+#[test]
+fn test_key_filter() {
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+    use serde::ser::Error;
+
+    fn filter(key: &str) -> serde_hjson::Result<Cow<str>> {
+        if key == "__proto__" {
+            Err(serde_hjson::Error::custom("reserved key"))
+        } else if key.starts_with('_') {
+            Ok(Cow::Owned(key.trim_left_matches('_').to_owned()))
+        } else {
+            Ok(Cow::Borrowed(key))
+        }
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert("_a", 1);
+    map.insert("b", 2);
+    let mut ser = Serializer::new(Vec::new()).key_filter(filter);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a: 1\n  b: 2\n}");
+
+    map.insert("__proto__", 3);
+    let mut ser = Serializer::new(Vec::new()).key_filter(filter);
+    match map.serialize(&mut ser) {
+        Err(serde_hjson::Error::Syntax(serde_hjson::ErrorCode::Custom(ref msg), _, _, _)) => {
+            assert_eq!(msg, "reserved key");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // struct fields are not filtered
+    #[derive(Serialize)]
+    struct Entry {
+        _a: i32,
+    }
+    let mut ser = Serializer::new(Vec::new()).key_filter(filter);
+    Entry { _a: 1 }.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  _a: 1\n}");
+}
+
+#[test]
+fn test_quote_profile() {
+    use serde_hjson::ser::QuoteProfile;
+
+    fn to_string(value: &[&str], profile: QuoteProfile) -> String {
+        let mut ser = Serializer::new(Vec::new()).quote_profile(profile);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let values = ["yes", "on", "1e3", "Off", "~", "0o17", ".5", "1_000", "NaN", "null value", "hello", "yesterday", "1.2.3"];
+    assert_eq!(to_string(&values, QuoteProfile::Minimal),
+               "[\n  yes\n  on\n  \"1e3\"\n  Off\n  ~\n  0o17\n  .5\n  \"1_000\"\n  NaN\n  null value\n  hello\n  yesterday\n  1.2.3\n]");
+    assert_eq!(to_string(&values, QuoteProfile::Safe),
+               "[\n  \"yes\"\n  \"on\"\n  \"1e3\"\n  \"Off\"\n  \"~\"\n  \"0o17\"\n  \".5\"\n  \"1_000\"\n  \"NaN\"\n  \"null value\"\n  hello\n  yesterday\n  1.2.3\n]");
+}
+
+#[test]
+fn test_object_serializer() {
+    let mut ser = Serializer::new(Vec::new());
+    {
+        let mut object = ser.serialize_object().unwrap();
+        object.field("name", &"test").unwrap();
+        object.field("count", &5).unwrap();
+        object.field("enabled", &true).unwrap();
+        object.field("tags", &vec!["a", "b"]).unwrap();
+        object.field("nested key", &Some("x")).unwrap();
+        object.finish().unwrap();
+    }
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+               "{\n  name: test\n  count: 5\n  enabled: true\n  tags:\n  [\n    a\n    b\n  ]\n  \"nested key\": x\n}");
+
+    // an object without members
+    let mut ser = Serializer::new(Vec::new());
+    ser.serialize_object().unwrap().finish().unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{}");
+}
+
+#[test]
+fn test_serialize_entries() {
+    let pairs = vec![("one", 1), ("two", 2), ("three", 3), ("four", 4)];
+
+    let mut ser = Serializer::new(Vec::new());
+    ser.serialize_entries(pairs.iter().cloned().filter(|&(_, n)| n % 2 == 0)).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  two: 2\n  four: 4\n}");
+
+    // computed keys and nested values
+    let mut ser = Serializer::new(Vec::new());
+    ser.serialize_entries((1..3).map(|n| (format!("item {}", n), vec![n; n]))).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+               "{\n  \"item 1\":\n  [\n    1\n  ]\n  \"item 2\":\n  [\n    2\n    2\n  ]\n}");
+
+    let mut ser = Serializer::new(Vec::new());
+    ser.serialize_entries(pairs.into_iter().filter(|_| false)).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{}");
+}
+
+#[test]
+fn test_bytes_base64() {
+    use serde::bytes::ByteBuf;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Blob {
+        data: ByteBuf,
+    }
+
+    for len in 0..8 {
+        let blob = Blob { data: ByteBuf::from((0..len).map(|i| (i * 71 + 250) as u8).collect::<Vec<u8>>()) };
+        let hjson = serde_hjson::to_string(&blob).unwrap();
+        assert_eq!(serde_hjson::from_str::<Blob>(&hjson).unwrap(), blob);
+    }
+
+    let blob = Blob { data: ByteBuf::from(b"hjson\xff".to_vec()) };
+    assert_eq!(serde_hjson::to_string(&blob).unwrap(), "{\n  data: aGpzb27/\n}");
+
+    // invalid base64, and the array form
+    assert!(serde_hjson::from_str::<Blob>("data: aGpzb2\n").is_err());
+    assert_eq!(serde_hjson::from_str::<Blob>("data: [104, 106]\n").unwrap().data, ByteBuf::from(b"hj".to_vec()));
+}
+
+#[test]
+fn test_keep_float_point() {
+    fn to_string<T: Serialize>(value: &T, keep: bool) -> String {
+        let mut ser = Serializer::new(Vec::new()).keep_float_point(keep);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    assert_eq!(to_string(&3.0f64, false), "3");
+    assert_eq!(to_string(&3.0f64, true), "3.0");
+    assert_eq!(to_string(&vec![-0.0f64, 2.5, 1e3, 1e20, 3.0f32 as f64], true),
+               "[\n  0.0\n  2.5\n  1000.0\n  1e+20\n  3.0\n]");
+    assert_eq!(to_string(&3.0f32, true), "3.0");
+
+    // integers are not affected
+    assert_eq!(to_string(&3, true), "3");
+}
+
+#[test]
+fn test_force_quote_strings() {
+    use serde_hjson::Value;
+    use serde_hjson::value::StyledString;
+    use serde_hjson::ser::StringStyle;
+
+    fn reformat(input: &str) -> String {
+        let value: Value = serde_hjson::from_str(input).unwrap();
+        let mut ser = Serializer::new(Vec::new()).force_quote_strings(true);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let input = "name: web server\npath: /srv/www\nmotd:\n  '''\n  hello\n  world\n  '''\nport: 80\ncolor: \"red\"\n";
+    assert_eq!(reformat(input),
+               "{\n  name: \"web server\"\n  path: \"/srv/www\"\n  motd: \"hello\\nworld\"\n  port: 80\n  color: \"red\"\n}");
+    assert_eq!(reformat("[\n  a\n  true\n]"), "[\n  \"a\"\n  true\n]");
+
+    // also over an explicit style, while keys are left alone
+    let mut ser = Serializer::new(Vec::new()).force_quote_strings(true);
+    vec![StyledString::new("x", Some(StringStyle::Quoteless))].serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[\n  \"x\"\n]");
+}
+
+#[test]
+fn test_non_finite_token() {
+    use std::f64;
+    use serde::de::Deserialize;
+    use serde_hjson::Deserializer;
+    use serde_hjson::ser::NonFiniteMode;
+
+    fn to_string<T: Serialize>(value: &T, mode: NonFiniteMode) -> String {
+        let mut ser = Serializer::new(Vec::new()).non_finite(mode);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    fn parse<T: Deserialize>(text: &str) -> T {
+        let mut de = Deserializer::new(text.bytes()).allow_non_finite(true);
+        let value = Deserialize::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        value
+    }
+
+    // null by default
+    assert_eq!(serde_hjson::to_string(&f64::INFINITY).unwrap(), "null");
+    assert_eq!(to_string(&f64::NAN, NonFiniteMode::Null), "null");
+
+    let values = vec![f64::INFINITY, f64::NEG_INFINITY, 1.5];
+    let hjson = to_string(&values, NonFiniteMode::Token);
+    assert_eq!(hjson, "[\n  Infinity\n  -Infinity\n  1.5\n]");
+    assert_eq!(parse::<Vec<f64>>(&hjson), values);
+    assert_eq!(to_string(&::std::f32::NEG_INFINITY, NonFiniteMode::Token), "-Infinity");
+    assert_eq!(parse::<f64>(&to_string(&f64::INFINITY, NonFiniteMode::Token)), f64::INFINITY);
+    assert!(parse::<f64>(&to_string(&f64::NAN, NonFiniteMode::Token)).is_nan());
+    assert_eq!(parse::<Vec<f64>>("[Infinity, -Infinity]"), vec![f64::INFINITY, f64::NEG_INFINITY]);
+
+    // strings that look like the tokens are quoted, and without the flag the tokens are strings
+    let hjson = to_string(&vec!["NaN", "Infinity", "infinity"], NonFiniteMode::Token);
+    assert_eq!(hjson, "[\n  \"NaN\"\n  \"Infinity\"\n  infinity\n]");
+    assert_eq!(parse::<Vec<String>>(&hjson), vec!["NaN", "Infinity", "infinity"]);
+    assert_eq!(serde_hjson::from_str::<String>("Infinity").unwrap(), "Infinity");
+    assert_eq!(parse::<String>("Infinity and beyond"), "Infinity and beyond");
+}
+
+#[test]
+fn test_lazy() {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::Lazy;
+
+    let calls = Cell::new(0);
+    let compute = || { calls.set(calls.get() + 1); vec![1, 2] };
+
+    let mut map = BTreeMap::new();
+    map.insert("a", Lazy::new(&compute));
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  a:\n  [\n    1\n    2\n  ]\n}");
+    assert_eq!(calls.get(), 1);
+
+    // not called unless serialized
+    let calls = Cell::new(0);
+    let _unused = Lazy::new(|| calls.set(calls.get() + 1));
+    assert_eq!(calls.get(), 0);
+}
+
+#[test]
+fn test_to_buf() {
+    #[derive(Serialize)]
+    struct Event {
+        id: u32,
+        name: String,
+    }
+
+    let mut buf = Vec::new();
+    serde_hjson::to_buf(&Event { id: 0, name: "start".to_owned() }, &mut buf).unwrap();
+    let capacity = buf.capacity();
+    let ptr = buf.as_ptr();
+
+    // the buffer is cleared and reused, as long as the output fits
+    for id in 1..100 {
+        let event = Event { id: id, name: "tick".to_owned() };
+        serde_hjson::to_buf(&event, &mut buf).unwrap();
+        assert_eq!(buf, serde_hjson::to_vec(&event).unwrap());
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(buf.as_ptr(), ptr);
+    }
+    assert_eq!(String::from_utf8(buf.clone()).unwrap(), "{\n  id: 99\n  name: tick\n}");
+
+    // and grows if needed
+    let long = Event { id: 0, name: "x".repeat(capacity) };
+    serde_hjson::to_buf(&long, &mut buf).unwrap();
+    assert_eq!(buf, serde_hjson::to_vec(&long).unwrap());
+}
+
+#[test]
+fn test_formatted_seq() {
+    use serde_hjson::Value;
+    use serde_hjson::ser::FormattedSeq;
+
+    let ids = [7, 42, 12345];
+    let padded = FormattedSeq::new(&ids, |id| format!("{:05}", id));
+    assert_eq!(serde_hjson::to_string(&padded).unwrap(), "[\n  00007\n  00042\n  12345\n]");
+
+    // as a member, and read back
+    #[derive(Serialize)]
+    struct Reading<'a> {
+        sensor: &'a str,
+        values: FormattedSeq<'a, f64, fn(&f64) -> String>,
+    }
+
+    fn fixed(value: &f64) -> String {
+        format!("{:.3}", value)
+    }
+
+    let values = [0.5, -12.25, 3.0];
+    let reading = Reading { sensor: "t1", values: FormattedSeq::new(&values, fixed) };
+    let hjson = serde_hjson::to_string(&reading).unwrap();
+    assert_eq!(hjson, "{\n  sensor: t1\n  values:\n  [\n    0.500\n    -12.250\n    3.000\n  ]\n}");
+    let back: Vec<f64> = serde_hjson::from_str::<Value>(&hjson).unwrap().find("values").map(|values| {
+        values.as_array().unwrap().iter().map(|value| value.as_f64().unwrap()).collect()
+    }).unwrap();
+    assert_eq!(back, values);
+
+    // other serializers see strings
+    assert_eq!(serde_hjson::to_value(&padded), Value::Array(vec![
+        Value::String("00007".to_owned()), Value::String("00042".to_owned()), Value::String("12345".to_owned())]));
+    let empty: [u8; 0] = [];
+    assert_eq!(serde_hjson::to_string(&FormattedSeq::new(&empty, |n| n.to_string())).unwrap(), "[]");
+}
+
+#[test]
+fn test_to_string_escaped_ascii() {
+    use std::collections::BTreeMap;
+    use serde_hjson::Value;
+    use serde_hjson::value::RawValue;
+
+    let mut map = BTreeMap::new();
+    map.insert("name".to_owned(), Value::String("Zoë".to_owned()));
+    map.insert("größe".to_owned(), Value::String("plain".to_owned()));
+    map.insert("emoji".to_owned(), Value::String("smile 😀".to_owned()));
+    map.insert("text".to_owned(), Value::String("naïve\nlines".to_owned()));
+
+    let hjson = serde_hjson::to_string_escaped_ascii(&map).unwrap();
+    assert!(hjson.bytes().all(|byte| byte <= 0x7f));
+    assert_eq!(hjson, "{\n  emoji: \"smile \\ud83d\\ude00\"\n  \"gr\\u00f6\\u00dfe\": \"plain\"\n  \
+                       name: \"Zo\\u00eb\"\n  text: \"na\\u00efve\\nlines\"\n}");
+    assert_eq!(serde_hjson::from_str::<BTreeMap<String, Value>>(&hjson).unwrap(), map);
+
+    // chars and raw values are escaped too
+    assert_eq!(serde_hjson::to_string_escaped_ascii(&'é').unwrap(), "\"\\u00e9\"");
+    let raw = RawValue::from_string("{\n  city: München\n}".to_owned());
+    assert_eq!(serde_hjson::to_string_escaped_ascii(&vec![raw]).unwrap(),
+               "[\n  {\n    city: \"M\\u00fcnchen\"\n  }\n]");
+}
+
+#[test]
+fn test_stringify_keys() {
+    use std::collections::{BTreeMap, HashMap};
+    use serde_hjson::{Error, ErrorCode};
+
+    fn stringified<T: Serialize>(value: &T) -> String {
+        let mut ser = Serializer::new(Vec::new()).stringify_keys(true);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let mut chars = HashMap::new();
+    chars.insert('x', 1);
+    match serde_hjson::to_string(&chars) {
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(stringified(&chars), "{\n  x: 1\n}");
+
+    // keys that need quotes as strings are quoted
+    let mut chars = BTreeMap::new();
+    chars.insert(' ', 1);
+    chars.insert(':', 2);
+    chars.insert('a', 3);
+    assert_eq!(stringified(&chars), "{\n  \" \": 1\n  \":\": 2\n  a: 3\n}");
+
+    let mut numbers = BTreeMap::new();
+    numbers.insert(-1, "minus one");
+    numbers.insert(10, "ten");
+    assert_eq!(stringified(&numbers), "{\n  -1: minus one\n  10: ten\n}");
+    let back: BTreeMap<String, String> = serde_hjson::from_str(&stringified(&numbers)).unwrap();
+    assert_eq!(back["10"], "ten");
+
+    let mut flags = BTreeMap::new();
+    flags.insert(true, 1.5);
+    assert_eq!(stringified(&flags), "{\n  true: 1.5\n}");
+}
+
+#[test]
+fn test_stringify_128_bit_keys() {
+    use std::collections::HashMap;
+    use serde_hjson::{Error, ErrorCode, RawNumber};
+
+    // serde 0.8 cannot serialize a `u128`, it is carried by a `RawNumber`
+    let mut ids = HashMap::new();
+    ids.insert(RawNumber::from(u128::max_value()), "max");
+    match serde_hjson::to_string(&ids) {
+        Err(Error::Syntax(ErrorCode::KeyMustBeAString, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let mut ser = Serializer::new(Vec::new()).stringify_keys(true);
+    ids.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+    assert_eq!(hjson, "{\n  340282366920938463463374607431768211455: max\n}");
+
+    let back: HashMap<RawNumber, String> = serde_hjson::from_str(&hjson).unwrap();
+    let (key, value) = back.into_iter().next().unwrap();
+    assert_eq!(key.as_u128(), Some(u128::max_value()));
+    assert_eq!(key.as_i128(), None);
+    assert_eq!(value, "max");
+
+    let mut ser = Serializer::new(Vec::new()).stringify_keys(true);
+    let mut ids = HashMap::new();
+    ids.insert(RawNumber::from(i128::min_value()), 1);
+    ids.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  -170141183460469231731687303715884105728: 1\n}");
+}
+
+#[test]
+fn test_field_comment() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    /// A web server.
+    #[derive(Serialize)]
+    struct Server {
+        /// The host name.
+        host: &'static str,
+        /// The port to listen on,
+        /// 0 picks a free one.
+        port: u16,
+        tls: Tls,
+    }
+
+    #[derive(Serialize)]
+    struct Tls {
+        /// Path to the certificate.
+        cert: &'static str,
+    }
+
+    #[derive(Serialize)]
+    enum Listen {
+        Tcp {
+            /// The backlog size.
+            backlog: u32,
+        },
+    }
+
+    fn commented<T: Serialize>(value: &T, formatter: HjsonFormatter) -> String {
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter)
+            .field_comment("Server", "host", "The host name.")
+            .field_comment("Server", "port", "The port to listen on,\n0 picks a free one.")
+            .field_comment("Tls", "cert", "Path to the certificate.")
+            .field_comment("Tcp", "backlog", "The backlog size.");
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    let server = Server { host: "localhost", port: 8080, tls: Tls { cert: "a.pem" } };
+    let hjson = commented(&server, HjsonFormatter::new());
+    assert_eq!(hjson, "{\n  # The host name.\n  host: localhost\n  # The port to listen on,\n  # 0 picks a free one.\n  \
+                       port: 8080\n  tls:\n  {\n    # Path to the certificate.\n    cert: a.pem\n  }\n}");
+    let value: serde_hjson::Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value.find_path(&["tls", "cert"]).and_then(|v| v.as_str()), Some("a.pem"));
+
+    // commented members are neither collapsed nor counted for alignment
+    let hjson = commented(&server, HjsonFormatter::new().collapse_single_member(true).align_keys(true));
+    assert_eq!(hjson, "{\n  # The host name.\n  host: localhost\n  # The port to listen on,\n  # 0 picks a free one.\n  \
+                       port: 8080\n  tls:\n  {\n    # Path to the certificate.\n    cert: a.pem\n  }\n}");
+    assert_eq!(commented(&Listen::Tcp { backlog: 5 }, HjsonFormatter::new()),
+               "{\n  Tcp:\n  {\n    # The backlog size.\n    backlog: 5\n  }\n}");
+}
+
+#[test]
+fn test_tuples_as_objects() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Point(i32, i32);
+
+    fn as_objects<T: Serialize>(value: &T, formatter: HjsonFormatter) -> String {
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter).tuples_as_objects(true);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    assert_eq!(as_objects(&(1, "a"), HjsonFormatter::new()), "{\n  _0: 1\n  _1: a\n}");
+    assert_eq!(serde_hjson::to_string(&(1, "a")).unwrap(), "[\n  1\n  a\n]");
+    assert_eq!(as_objects(&vec![Point(1, 2)], HjsonFormatter::new()), "[\n  {\n    _0: 1\n    _1: 2\n  }\n]");
+
+    // nested tuples count their own positions
+    assert_eq!(as_objects(&((1,), 2), HjsonFormatter::new().collapse_single_member(true)),
+               "{\n  _0: { _0: 1 }\n  _1: 2\n}");
+
+    let value: serde_hjson::Value = serde_hjson::from_str(&as_objects(&(1, "a"), HjsonFormatter::new())).unwrap();
+    assert_eq!(value.find("_1").and_then(|v| v.as_str()), Some("a"));
+}
+
+#[test]
+fn test_formatter_write_indent() {
+    use std::io;
+    use serde_hjson::ser::Formatter;
+
+    // keeps opening brackets on the line of their key
+    struct SameLine {
+        level: usize,
+    }
+
+    impl Formatter for SameLine {
+        fn open<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.level += 1;
+            writer.write_all(&[ch]).map_err(From::from)
+        }
+
+        fn comma<W: io::Write>(&mut self, writer: &mut W, _first: bool) -> serde_hjson::Result<()> {
+            try!(writer.write_all(b"\n"));
+            self.write_indent(writer, self.level)
+        }
+
+        fn colon<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            writer.write_all(b": ").map_err(From::from)
+        }
+
+        fn close<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.level -= 1;
+            try!(writer.write_all(b"\n"));
+            try!(self.write_indent(writer, self.level));
+            writer.write_all(&[ch]).map_err(From::from)
+        }
+
+        fn newline<W: io::Write>(&mut self, writer: &mut W, add_indent: i32) -> serde_hjson::Result<()> {
+            try!(writer.write_all(b"\n"));
+            self.write_indent(writer, (self.level as i32 + add_indent) as usize)
+        }
+
+        fn start_value<W: io::Write>(&mut self, _writer: &mut W) -> serde_hjson::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Server {
+        name: &'static str,
+        ports: Vec<u16>,
+    }
+
+    let server = Server { name: "web", ports: vec![80, 443] };
+    let mut ser = Serializer::with_formatter(Vec::new(), SameLine { level: 0 });
+    server.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+    assert_eq!(hjson, "{\n  name: web\n  ports: [\n    80\n    443\n  ]\n}");
+
+    let value: serde_hjson::Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(value.find_path(&["ports"]).and_then(|v| v.as_array()).map(|a| a.len()), Some(2));
+}
+
+#[test]
+fn test_struct_field_order() {
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Config {
+        zone: &'static str,
+        name: &'static str,
+        port: u16,
+        b: bool,
+        address: &'static str,
+    }
+
+    let config = Config { zone: "eu", name: "web", port: 80, b: true, address: "10.0.0.1" };
+    assert_eq!(serde_hjson::to_string(&config).unwrap(),
+               "{\n  zone: eu\n  name: web\n  port: 80\n  b: true\n  address: 10.0.0.1\n}");
+
+    // also when the members are buffered to line up the values
+    let mut ser = Serializer::with_formatter(Vec::new(), HjsonFormatter::new().align_keys(true));
+    config.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+               "{\n  zone:    eu\n  name:    web\n  port:    80\n  b:       true\n  address: 10.0.0.1\n}");
+}
+
+#[test]
+fn test_paths() {
+    use std::path::{Path, PathBuf};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Dirs {
+        windows: PathBuf,
+        unix: PathBuf,
+        spaced: PathBuf,
+    }
+
+    let dirs = Dirs {
+        windows: PathBuf::from(r"C:\Program Files\App\{config}.hjson"),
+        unix: PathBuf::from("/var/lib/app data/#1"),
+        spaced: PathBuf::from(" padded\\ "),
+    };
+    let hjson = serde_hjson::to_string(&dirs).unwrap();
+    // backslashes are written as they are, unless the path has to be quoted
+    assert_eq!(hjson, "{\n  windows: C:\\Program Files\\App\\{config}.hjson\n  \
+                       unix: /var/lib/app data/#1\n  spaced: \" padded\\\\ \"\n}");
+    assert_eq!(serde_hjson::from_str::<Dirs>(&hjson).unwrap(), dirs);
+    assert_eq!(serde_hjson::to_string(&vec![Path::new(r"\\server\share"), Path::new(r"[old]\logs")]).unwrap(),
+               "[\n  \\\\server\\share\n  '''[old]\\logs'''\n]");
+    assert_eq!(serde_hjson::to_string(&Path::new("/tmp")).unwrap(), "/tmp");
+}
+
+#[test]
+fn test_array_serializer_separators() {
+    use std::io;
+    use serde_hjson::ser::Formatter;
+
+    // writes `[ a, b ]`
+    struct Commas;
+
+    impl Formatter for Commas {
+        fn open<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            writer.write_all(&[ch]).map_err(From::from)
+        }
+
+        fn comma<W: io::Write>(&mut self, writer: &mut W, first: bool) -> serde_hjson::Result<()> {
+            writer.write_all(if first { b" " } else { b", " }).map_err(From::from)
+        }
+
+        fn colon<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            writer.write_all(b": ").map_err(From::from)
+        }
+
+        fn close<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            writer.write_all(&[b' ', ch]).map_err(From::from)
+        }
+
+        fn newline<W: io::Write>(&mut self, _writer: &mut W, _add_indent: i32) -> serde_hjson::Result<()> {
+            Ok(())
+        }
+
+        fn start_value<W: io::Write>(&mut self, _writer: &mut W) -> serde_hjson::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut ser = Serializer::with_formatter(Vec::new(), Commas).skip_none_in_seq(true);
+    {
+        let mut array = ser.serialize_array().unwrap();
+        assert!(array.is_first());
+        array.element(&1).unwrap();
+        // a skipped element leaves the state alone
+        array.element(&None::<u8>).unwrap();
+        assert!(!array.is_first());
+        array.element(&2).unwrap();
+        array.reset_first();
+        assert!(array.is_first());
+        array.element(&3).unwrap();
+        array.element(&vec![4, 5]).unwrap();
+        array.finish().unwrap();
+    }
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "[ 1, 2 3, [ 4, 5 ] ]");
+}
+
+#[test]
+fn test_escape_html() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::HjsonFormatter;
+
+    let mut map = BTreeMap::new();
+    map.insert("html", "</script><script>alert(1)</script>");
+    map.insert("plain", "text");
+    map.insert("a&b", "x > y\nand more");
+
+    let mut ser = Serializer::with_formatter(Vec::new(), HjsonFormatter::new().escape_html(true));
+    map.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+    assert!(!hjson.contains('<') && !hjson.contains('>') && !hjson.contains('&'));
+    assert_eq!(hjson, "{\n  \"a\\u0026b\": \"x \\u003e y\\nand more\"\n  \
+                       html: \"\\u003c/script\\u003e\\u003cscript\\u003ealert(1)\\u003c/script\\u003e\"\n  \
+                       plain: text\n}");
+    let parsed: BTreeMap<String, String> = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(parsed["html"], map["html"]);
+    assert_eq!(parsed["a&b"], map["a&b"]);
+
+    // off by default
+    assert_eq!(serde_hjson::to_string(&"</script>").unwrap(), "</script>");
+}
+
+#[test]
+fn test_escape_control_chars() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::HjsonFormatter;
+
+    fn to_string<T: Serialize>(value: &T) -> String {
+        let mut ser = Serializer::with_formatter(Vec::new(), HjsonFormatter::new().escape_control_chars(true));
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    assert_eq!(to_string(&"del\x7f"), "\"del\\u007f\"");
+    assert_eq!(to_string(&"c1 \u{80}\u{9f} é\u{a0}"), "\"c1 \\u0080\\u009f é\u{a0}\"");
+    assert_eq!(to_string(&"bell\x07\ttab\nline"), "\"bell\\u0007\\ttab\\nline\"");
+    assert_eq!(to_string(&"plain"), "plain");
+
+    // keys too, and the escapes read back
+    let mut map = BTreeMap::new();
+    map.insert("k\x7f", "v\u{85}");
+    let hjson = to_string(&map);
+    assert_eq!(hjson, "{\n  \"k\\u007f\": \"v\\u0085\"\n}");
+    let parsed: BTreeMap<String, String> = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(parsed["k\x7f"], "v\u{85}");
+
+    // passed through raw by default
+    assert_eq!(serde_hjson::to_string(&"del\x7f").unwrap(), "\"del\x7f\"");
+}
+
+#[test]
+fn test_reject_multiline_keys() {
+    use std::collections::BTreeMap;
+    use serde_hjson::{Error, ErrorCode};
+
+    let mut map = BTreeMap::new();
+    map.insert("two\nlines", 1);
+    map.insert("one line", 2);
+
+    // quoted with the line break escaped by default
+    let hjson = serde_hjson::to_string(&map).unwrap();
+    assert_eq!(hjson, "{\n  \"one line\": 2\n  \"two\\nlines\": 1\n}");
+    assert_eq!(serde_hjson::from_str::<BTreeMap<String, i32>>(&hjson).unwrap()["two\nlines"], 1);
+
+    let mut ser = Serializer::new(Vec::new()).reject_multiline_keys(true);
+    match map.serialize(&mut ser) {
+        Err(Error::Syntax(ErrorCode::MultilineKey, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert("cr\r", 1);
+    let mut ser = Serializer::new(Vec::new()).reject_multiline_keys(true).skip_none_in_map(true);
+    assert!(map.serialize(&mut ser).is_err());
+
+    // other keys are fine
+    let mut map = BTreeMap::new();
+    map.insert("a b", 1);
+    let mut ser = Serializer::new(Vec::new()).reject_multiline_keys(true);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  \"a b\": 1\n}");
+}
+
+#[test]
+fn test_begin_end_object() {
+    use std::io;
+    use serde_hjson::Value;
+    use serde_hjson::ser::{Formatter, HjsonFormatter};
+
+    // marks where each object ends, leaving arrays to the default `open` and `close`
+    struct MarkObjects<'a>(HjsonFormatter<'a>);
+
+    impl<'a> Formatter for MarkObjects<'a> {
+        fn open<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.0.open(writer, ch)
+        }
+
+        fn comma<W: io::Write>(&mut self, writer: &mut W, first: bool) -> serde_hjson::Result<()> {
+            self.0.comma(writer, first)
+        }
+
+        fn colon<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            self.0.colon(writer)
+        }
+
+        fn close<W: io::Write>(&mut self, writer: &mut W, ch: u8) -> serde_hjson::Result<()> {
+            self.0.close(writer, ch)
+        }
+
+        fn newline<W: io::Write>(&mut self, writer: &mut W, add_indent: i32) -> serde_hjson::Result<()> {
+            self.0.newline(writer, add_indent)
+        }
+
+        fn start_value<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            self.0.start_value(writer)
+        }
+
+        fn end_object<W: io::Write>(&mut self, writer: &mut W) -> serde_hjson::Result<()> {
+            try!(self.0.close(writer, b'}'));
+            writer.write_all(b" # end").map_err(From::from)
+        }
+    }
+
+    let value: Value = serde_hjson::from_str("{a: [1, 2], b: {c: 1}}").unwrap();
+    let mut ser = Serializer::with_formatter(Vec::new(), MarkObjects(HjsonFormatter::new()));
+    value.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+
+    assert_eq!(hjson, "{\n  a:\n  [\n    1\n    2\n  ]\n  b:\n  {\n    c: 1\n  } # end\n} # end");
+    assert_eq!(serde_hjson::from_str::<Value>(&hjson).unwrap(), value);
+}
+
+#[test]
+fn test_bool_style() {
+    use std::collections::BTreeMap;
+    use serde::de::Deserialize;
+    use serde_hjson::Deserializer;
+    use serde_hjson::ser::BoolStyle;
+
+    fn to_string<T: Serialize>(value: &T, style: BoolStyle) -> String {
+        let mut ser = Serializer::new(Vec::new()).bool_style(style);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    fn parse<T: Deserialize>(text: &str) -> serde_hjson::Result<T> {
+        let mut de = Deserializer::new(text.bytes()).allow_bool_words(true);
+        let value = try!(Deserialize::deserialize(&mut de));
+        try!(de.end());
+        Ok(value)
+    }
+
+    let mut value = BTreeMap::new();
+    value.insert("debug", true);
+    value.insert("verbose", false);
+    let hjson = to_string(&value, BoolStyle::YesNo);
+    assert_eq!(hjson, "{\n  debug: yes\n  verbose: no\n}");
+    assert_eq!(parse::<BTreeMap<String, bool>>(&hjson).unwrap(), {
+        let mut map = BTreeMap::new();
+        map.insert("debug".to_owned(), true);
+        map.insert("verbose".to_owned(), false);
+        map
+    });
+    assert_eq!(to_string(&vec![true, false], BoolStyle::OnOff), "[\n  on\n  off\n]");
+    assert_eq!(to_string(&true, BoolStyle::TrueFalse), "true");
+
+    // strings that would read back as booleans are quoted
+    let words = vec!["yes", "on", "Yes"];
+    let hjson = to_string(&words, BoolStyle::YesNo);
+    assert_eq!(hjson, "[\n  \"yes\"\n  on\n  Yes\n]");
+    assert_eq!(serde_hjson::from_str::<Vec<String>>(&hjson).unwrap(), words);
+
+    // without the flag the words are strings
+    assert_eq!(serde_hjson::from_str::<String>("yes").unwrap(), "yes");
+    assert!(serde_hjson::from_str::<bool>("yes").is_err());
+    assert_eq!(parse::<Vec<bool>>("[\n  on\n  off\n  true\n]").unwrap(), vec![true, false, true]);
+    assert_eq!(parse::<String>("\"yes\"").unwrap(), "yes");
+}
+
+#[test]
+fn test_key_needs_quotes() {
+    use std::collections::BTreeMap;
+    use serde_hjson::ser::{escape_key, escape_key_quoted, key_needs_quotes};
+
+    let keys = [
+        ("name", false),
+        ("dotted.name", false),
+        ("über", false),
+        ("it's", false),
+        ("/path", false),
+        ("", true),
+        ("two words", true),
+        ("tab\there", true),
+        ("a:b", true),
+        ("a,b", true),
+        ("[x]", true),
+        ("{x}", true),
+        ("#tag", true),
+        ("//x", true),
+        ("/*x", true),
+        ("say\"hi\"", true),
+        ("'''", true),
+    ];
+    for &(key, quoted) in keys.iter() {
+        assert_eq!(key_needs_quotes(key), quoted, "{:?}", key);
+
+        // the predicate matches what the serializer writes
+        let mut map = BTreeMap::new();
+        map.insert(key, 1);
+        let hjson = serde_hjson::to_string(&map).unwrap();
+        assert_eq!(hjson.starts_with("{\n  \""), quoted, "{}", hjson);
+
+        let mut out = Vec::new();
+        escape_key(&mut out, key).unwrap();
+        assert_eq!(out.starts_with(b"\""), quoted);
+    }
+
+    let mut out = Vec::new();
+    escape_key_quoted(&mut out, "name").unwrap();
+    assert_eq!(out, b"\"name\"");
+    let mut out = Vec::new();
+    escape_key_quoted(&mut out, "say\"hi\"").unwrap();
+    assert_eq!(out, b"\"say\\\"hi\\\"\"");
+}
+
+#[test]
+fn test_serialize_result() {
+    use std::fmt;
+    use serde_hjson::Value;
+    use serde_hjson::ser::SerializeResult;
+
+    struct Failure(u32);
+
+    impl fmt::Display for Failure {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "failed with code {}", self.0)
+        }
+    }
+
+    let ok: Result<Vec<u32>, Failure> = Ok(vec![1, 2]);
+    let hjson = serde_hjson::to_string(&SerializeResult(&ok)).unwrap();
+    assert_eq!(hjson, "{\n  Ok:\n  [\n    1\n    2\n  ]\n}");
+
+    let err: Result<Vec<u32>, Failure> = Err(Failure(3));
+    let hjson = serde_hjson::to_string(&SerializeResult(&err)).unwrap();
+    assert_eq!(hjson, "{\n  Err: failed with code 3\n}");
+
+    // the layout of a serializable `Result`
+    let plain: Result<Vec<u32>, String> = Err("failed with code 3".to_owned());
+    assert_eq!(serde_hjson::to_string(&plain).unwrap(), hjson);
+
+    let value = serde_hjson::to_value(&vec![SerializeResult(&ok), SerializeResult(&err)]);
+    assert_eq!(value.pointer("/0/Ok/1"), Some(&Value::U64(2)));
+    assert_eq!(value.pointer("/1/Err").and_then(Value::as_str), Some("failed with code 3"));
+}
+
+#[test]
+fn test_number_format() {
+    use std::collections::BTreeMap;
+    use serde_hjson::Value;
+
+    fn group(digits: &str) -> String {
+        let mut grouped = String::new();
+        for (i, ch) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(ch);
+        }
+        grouped
+    }
+
+    // integers from 1000 up are written with thousands separators, which must be quoted
+    let mut ser = Serializer::new(Vec::new()).number_format(|number| {
+        match number.as_u64() {
+            Some(n) if n >= 1000 => Some(format!("\"{}\"", group(&n.to_string()))),
+            _ => None,
+        }
+    });
+    let mut value = BTreeMap::new();
+    value.insert(1234567, vec![999.0, 1500.0]);
+    value.insert(12, vec![]);
+    let mut doc = BTreeMap::new();
+    doc.insert("population", Value::U64(1234567));
+    doc.insert("rank", Value::U64(999));
+    doc.insert("area", Value::F64(2500.5));
+    doc.insert("values", serde_hjson::to_value(&(1000u16, -5000i32, 1000000u64)));
+    doc.serialize(&mut ser).unwrap();
+    let hjson = String::from_utf8(ser.into_inner()).unwrap();
+
+    // floats and negative integers are left to the default notation
+    assert_eq!(hjson, "{\n  area: 2500.5\n  population: \"1,234,567\"\n  rank: 999\n  values:\n  [\n    \"1,000\"\n    -5000\n    \"1,000,000\"\n  ]\n}");
+    let parsed: Value = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(parsed.find("population").and_then(Value::as_str), Some("1,234,567"));
+
+    // keys are not affected
+    let mut ser = Serializer::new(Vec::new()).stringify_keys(true).number_format(|_| Some("0".to_owned()));
+    value.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  12: []\n  1234567:\n  [\n    0\n    0\n  ]\n}");
+}
+
+#[test]
+fn test_sort_keys_by() {
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+    use serde_hjson::Value;
+    use serde_hjson::ser::HjsonFormatter;
+
+    // `name` and `version` first, then the others alphabetically
+    fn rank(key: &str) -> usize {
+        match key {
+            "name" => 0,
+            "version" => 1,
+            _ => 2,
+        }
+    }
+    fn compare(a: &str, b: &str) -> Ordering {
+        rank(a).cmp(&rank(b)).then_with(|| a.cmp(b))
+    }
+
+    let value: Value = serde_hjson::from_str("{\n  authors: [\"x\"]\n  version: 1.0\n  deps: {\n    zlib: 1\n    name: inner\n    bzip: 2\n  }\n  name: demo\n}").unwrap();
+
+    let mut ser = Serializer::new(Vec::new()).sort_keys_by(compare);
+    value.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+               "{\n  name: demo\n  version: 1.0\n  authors:\n  [\n    x\n  ]\n  deps:\n  {\n    name: inner\n    bzip: 2\n    zlib: 1\n  }\n}");
+
+    // together with aligned keys
+    let mut map = BTreeMap::new();
+    map.insert("description", "d");
+    map.insert("name", "n");
+    map.insert("author", "a");
+    let formatter = HjsonFormatter::new().align_keys(true);
+    let mut ser = Serializer::with_formatter(Vec::new(), formatter).sort_keys_by(compare);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(),
+               "{\n  name:        n\n  author:      a\n  description: d\n}");
+
+    // with skipped members
+    let mut map = BTreeMap::new();
+    map.insert("a", None);
+    map.insert("name", Some(1));
+    map.insert("b", Some(2));
+    let mut ser = Serializer::new(Vec::new()).skip_none_in_map(true).sort_keys_by(compare);
+    map.serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  name: 1\n  b: 2\n}");
+
+    // each key is serialized once, and sorted as it was before the key filter
+    struct Key<'a>(&'a str, &'a Cell<usize>);
+    impl<'a> Serialize for Key<'a> {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            self.1.set(self.1.get() + 1);
+            serializer.serialize_str(self.0)
+        }
+    }
+    struct Keys<'a>(&'a Cell<usize>);
+    impl<'a> Serialize for Keys<'a> {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            let mut state = try!(serializer.serialize_map(Some(3)));
+            for &key in &["b", "version", "a"] {
+                try!(serializer.serialize_map_key(&mut state, Key(key, self.0)));
+                try!(serializer.serialize_map_value(&mut state, 1));
+            }
+            serializer.serialize_map_end(state)
+        }
+    }
+    let count = Cell::new(0);
+    let mut ser = Serializer::new(Vec::new()).sort_keys_by(compare)
+        .key_filter(|key| Ok(key.to_uppercase().into()));
+    Keys(&count).serialize(&mut ser).unwrap();
+    assert_eq!(String::from_utf8(ser.into_inner()).unwrap(), "{\n  VERSION: 1\n  A: 1\n  B: 1\n}");
+    assert_eq!(count.get(), 3);
+}
+
+#[test]
+fn test_max_depth() {
+    use serde_hjson::{Error, ErrorCode, Value};
+
+    fn nested(depth: usize) -> Value {
+        let mut value = Value::Null;
+        for _ in 0..depth {
+            value = Value::Array(vec![value]);
+        }
+        value
+    }
+
+    let mut ser = Serializer::new(Vec::new()).max_depth(128);
+    match nested(5000).serialize(&mut ser) {
+        Err(Error::Syntax(ErrorCode::DepthLimitExceeded, _, _, _)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // the limit itself is fine, as are siblings at the same depth
+    let mut ser = Serializer::new(Vec::new()).max_depth(3);
+    assert!(Value::Array(vec![nested(2), nested(2), Value::Array(vec![])]).serialize(&mut ser).is_ok());
+    let mut ser = Serializer::new(Vec::new()).max_depth(3);
+    assert!(nested(4).serialize(&mut ser).is_err());
+
+    // objects, structs and enum variants count as well
+    #[derive(Serialize)]
+    enum Tree { Leaf, Node(Box<Tree>) }
+    let tree = Tree::Node(Box::new(Tree::Node(Box::new(Tree::Leaf))));
+    let mut ser = Serializer::new(Vec::new()).max_depth(2);
+    assert!(tree.serialize(&mut ser).is_ok());
+    let mut ser = Serializer::new(Vec::new()).max_depth(1);
+    assert!(tree.serialize(&mut ser).is_err());
+
+    // the object around a tuple or struct variant is a level of its own
+    #[derive(Serialize)]
+    enum Shape { Pair(u8, u8), Rect { size: (u8, u8) }, Nested(Vec<Shape>) }
+    let check = |shape: &Shape, depth: usize| {
+        let mut ser = Serializer::new(Vec::new()).max_depth(depth);
+        assert!(shape.serialize(&mut ser).is_ok(), "fits in {}", depth);
+        let mut ser = Serializer::new(Vec::new()).max_depth(depth - 1);
+        assert!(shape.serialize(&mut ser).is_err(), "deeper than {}", depth - 1);
+    };
+    check(&Shape::Pair(1, 2), 2);
+    check(&Shape::Rect { size: (1, 2) }, 3);
+    check(&Shape::Nested(vec![Shape::Pair(1, 2)]), 4);
+    check(&Shape::Nested(vec![Shape::Nested(vec![Shape::Rect { size: (1, 2) }])]), 7);
+
+    let mut map = serde_hjson::Map::new();
+    map.insert("a".to_owned(), nested(1));
+    let mut ser = Serializer::new(Vec::new()).max_depth(1);
+    assert!(Value::Object(map).serialize(&mut ser).is_err());
+}
+
+#[test]
+fn test_sets_as_arrays() {
+    use std::collections::{BTreeSet, HashSet};
+
+    let set: BTreeSet<i32> = vec![3, -1, 2, 3].into_iter().collect();
+    assert_eq!(serde_hjson::to_string(&set).unwrap(), "[\n  -1\n  2\n  3\n]");
+    let parsed: BTreeSet<i32> = serde_hjson::from_str("[\n  -1\n  2\n  3\n]").unwrap();
+    assert_eq!(parsed, set);
+    assert_eq!(serde_hjson::to_string(&BTreeSet::<i32>::new()).unwrap(), "[]");
+
+    // a HashSet is written in its iteration order, collecting it into a BTreeSet sorts it
+    let set: HashSet<&str> = vec!["b", "c", "a"].into_iter().collect();
+    let hjson = serde_hjson::to_string(&set).unwrap();
+    let parsed: HashSet<String> = serde_hjson::from_str(&hjson).unwrap();
+    assert_eq!(parsed.len(), 3);
+    assert!(parsed.contains("a") && parsed.contains("b") && parsed.contains("c"));
+    let sorted: BTreeSet<&str> = set.into_iter().collect();
+    assert_eq!(serde_hjson::to_string(&sorted).unwrap(), "[\n  a\n  b\n  c\n]");
+}
@@ -0,0 +1,419 @@
+
+extern crate serde;
+extern crate serde_hjson;
+
+use serde_hjson::{Map, Value};
+
+#[test]
+fn test_type_name() {
+    assert_eq!(Value::Null.type_name(), "null");
+    assert_eq!(Value::Bool(true).type_name(), "bool");
+    assert_eq!(Value::I64(-1).type_name(), "number");
+    assert_eq!(Value::U64(1).type_name(), "number");
+    assert_eq!(Value::F64(1.5).type_name(), "number");
+    assert_eq!(Value::String("a".to_owned()).type_name(), "string");
+    assert_eq!(Value::Array(vec![]).type_name(), "array");
+    assert_eq!(Value::Object(Map::new()).type_name(), "object");
+}
+
+#[test]
+fn test_partial_eq_native() {
+    let value: Value = serde_hjson::from_str("{\n  count: 5\n  ratio: 0.5\n  name: text\n  enabled: true\n}").unwrap();
+    let get = |key| value.find(key).unwrap();
+
+    assert!(*get("count") == 5);
+    assert!(*get("count") == 5u64);
+    assert!(*get("count") == 5.0);
+    assert!(*get("count") != 6);
+    assert!(*get("ratio") == 0.5f32);
+    assert!(*get("ratio") != 0);
+    assert!(*get("name") == "text");
+    assert!(*get("name") == "text".to_owned());
+    assert!(*get("name") != "other");
+    assert!(*get("enabled") == true);
+    assert!(*get("enabled") != false);
+
+    // no conversions between types
+    assert!(Value::String("5".to_owned()) != 5);
+    assert!(Value::I64(1) != true);
+    assert!(Value::Null != false);
+
+    // and the other way around
+    assert!(5 == *get("count"));
+    assert!("text" == *get("name"));
+    assert!(true == *get("enabled"));
+    assert!(Value::I64(-3) == -3.0);
+}
+
+#[test]
+fn test_visit() {
+    use serde_hjson::value::{PathSegment, ValueVisitor};
+
+    #[derive(Default)]
+    struct Collect {
+        leaves: Vec<(String, Value)>,
+        keys: Vec<String>,
+        events: Vec<String>,
+    }
+
+    impl ValueVisitor for Collect {
+        fn visit_value(&mut self, path: &[PathSegment], value: &Value) {
+            let text: Vec<String> = path.iter().map(|segment| segment.to_string()).collect();
+            if let Some(&PathSegment::Key(key)) = path.last() {
+                self.keys.push(key.to_owned());
+            }
+            match *value {
+                Value::Array(_) | Value::Object(_) => self.events.push(format!("enter /{}", text.join("/"))),
+                _ => self.leaves.push((text.join("/"), value.clone())),
+            }
+        }
+
+        fn leave_value(&mut self, path: &[PathSegment], _value: &Value) {
+            self.events.push(format!("leave {}", path.len()));
+        }
+    }
+
+    let value: Value = serde_hjson::from_str("{\n  name: web\n  ports: [80, 443]\n  tls: {\n    cert: a.pem\n    opts: [[], {}]\n  }\n}").unwrap();
+    let mut collect = Collect::default();
+    value.visit(&mut collect);
+
+    let paths: Vec<&str> = collect.leaves.iter().map(|&(ref path, _)| &path[..]).collect();
+    assert_eq!(paths, vec!["name", "ports/0", "ports/1", "tls/cert"]);
+    assert_eq!(collect.leaves[2].1, 443);
+    assert_eq!(collect.keys, vec!["name", "ports", "tls", "cert", "opts"]);
+    assert_eq!(collect.events, vec![
+        "enter /", "enter /ports", "leave 1", "enter /tls", "enter /tls/opts",
+        "enter /tls/opts/0", "leave 3", "enter /tls/opts/1", "leave 3", "leave 2", "leave 1", "leave 0",
+    ]);
+
+    // a scalar is a leaf with an empty path
+    let mut collect = Collect::default();
+    Value::Bool(true).visit(&mut collect);
+    assert_eq!(collect.leaves, vec![(String::new(), Value::Bool(true))]);
+}
+
+#[test]
+fn test_is_empty() {
+    assert!(Value::String(String::new()).is_empty());
+    assert!(Value::Array(vec![]).is_empty());
+    assert!(Value::Object(Map::new()).is_empty());
+
+    let value: Value = serde_hjson::from_str("{\n  s: \"\"\n  a: []\n  o: {}\n  t: x\n  n: 0\n}").unwrap();
+    assert!(!value.is_empty());
+    for key in ["s", "a", "o"].iter() {
+        assert!(value.find(key).unwrap().is_empty(), "{}", key);
+    }
+    assert!(!value.find("t").unwrap().is_empty());
+    assert!(!Value::Array(vec![Value::Null]).is_empty());
+
+    // scalars are never empty
+    assert!(!Value::Null.is_empty());
+    assert!(!Value::Bool(false).is_empty());
+    assert!(!Value::I64(0).is_empty());
+    assert!(!Value::U64(0).is_empty());
+    assert!(!Value::F64(0.0).is_empty());
+}
+
+#[test]
+fn test_entries_and_values() {
+    let value: Value = serde_hjson::from_str("{\n  name: web\n  port: 80\n  tags: [\n    a\n  ]\n}").unwrap();
+    let entries: Vec<(&str, &Value)> = value.entries().collect();
+    assert_eq!(entries, vec![
+        ("name", &Value::String("web".to_owned())),
+        ("port", &Value::F64(80.0)),
+        ("tags", &Value::Array(vec![Value::String("a".to_owned())]))]);
+    assert_eq!(value.entries().size_hint(), (3, Some(3)));
+
+    let values: Vec<&Value> = value.values().collect();
+    assert_eq!(values, entries.iter().map(|&(_, value)| value).collect::<Vec<_>>());
+
+    // other values have no entries, not even arrays
+    let array = value.find("tags").unwrap();
+    assert_eq!(array.entries().count(), 0);
+    assert_eq!(array.values().count(), 0);
+    assert_eq!(Value::Null.values().next(), None);
+    assert_eq!(Value::Object(Map::new()).entries().count(), 0);
+}
+
+#[test]
+fn test_to_value_data_model() {
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Unit;
+
+    #[derive(Serialize)]
+    struct Meters(f32);
+
+    #[derive(Serialize)]
+    struct Pair(u8, &'static str);
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle(Meters),
+        Rect(u32, u32),
+        Polygon { sides: u8, regular: bool },
+    }
+
+    #[derive(Serialize)]
+    struct Drawing {
+        title: String,
+        layer: i8,
+        scale: Option<f64>,
+        author: Option<&'static str>,
+        unit: Unit,
+        origin: (i64, u64),
+        pair: Pair,
+        shapes: Vec<Shape>,
+        labels: BTreeMap<String, Vec<&'static str>>,
+    }
+
+    let mut labels = BTreeMap::new();
+    labels.insert("a".to_owned(), vec!["x", "y"]);
+    labels.insert("b".to_owned(), vec![]);
+    let drawing = Drawing {
+        title: "plan".to_owned(),
+        layer: -2,
+        scale: Some(0.5),
+        author: None,
+        unit: Unit,
+        origin: (-1, 1),
+        pair: Pair(7, "seven"),
+        shapes: vec![Shape::Empty, Shape::Circle(Meters(1.5)), Shape::Rect(2, 3),
+                     Shape::Polygon { sides: 6, regular: true }],
+        labels: labels,
+    };
+
+    fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(members.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+    fn string(text: &str) -> Value {
+        Value::String(text.to_owned())
+    }
+
+    let expected = object(vec![
+        ("title", string("plan")),
+        ("layer", Value::I64(-2)),
+        ("scale", Value::F64(0.5)),
+        ("author", Value::Null),
+        ("unit", Value::Null),
+        ("origin", Value::Array(vec![Value::I64(-1), Value::U64(1)])),
+        ("pair", Value::Array(vec![Value::U64(7), string("seven")])),
+        ("shapes", Value::Array(vec![
+            string("Empty"),
+            object(vec![("Circle", Value::F64(1.5))]),
+            object(vec![("Rect", Value::Array(vec![Value::U64(2), Value::U64(3)]))]),
+            object(vec![("Polygon", object(vec![("sides", Value::U64(6)), ("regular", Value::Bool(true))]))]),
+        ])),
+        ("labels", object(vec![
+            ("a", Value::Array(vec![string("x"), string("y")])),
+            ("b", Value::Array(vec![])),
+        ])),
+    ]);
+    assert_eq!(serde_hjson::to_value(&drawing), expected);
+
+    // and written the same way
+    assert_eq!(serde_hjson::to_string(&expected).unwrap(), serde_hjson::to_string(&drawing).unwrap());
+}
+
+#[test]
+fn test_pretty_and_compact() {
+    let hjson = "{\n  name: web server\n  ports: [80, 443]\n  tls: {\n    enabled: true\n    cert: null\n  }\n  \
+                 motd:\n    '''\n    hello\n    world\n    '''\n  punct: \"a, b: {c}\"\n  empty: []\n}";
+    let value: Value = serde_hjson::from_str(hjson).unwrap();
+
+    let pretty = value.pretty();
+    assert_eq!(pretty, serde_hjson::to_string(&value).unwrap());
+    assert_eq!(serde_hjson::from_str::<Value>(&pretty).unwrap(), value);
+
+    let compact = value.compact();
+    assert_eq!(compact, "{name:\"web server\",ports:[80,443],tls:{enabled:true,cert:null},\
+                         motd:\"hello\\nworld\",punct:\"a, b: {c}\",empty:[]}");
+    assert_eq!(serde_hjson::from_str::<Value>(&compact).unwrap(), value);
+
+    assert_eq!(Value::Array(vec![]).compact(), "[]");
+    assert_eq!(Value::String("x".to_owned()).compact(), "\"x\"");
+
+    // neither panics for keys and strings that need quotes or escapes, or for deep nesting
+    let mut awkward = Map::new();
+    awkward.insert("line\nbreak".to_owned(), Value::String("tab\there \u{7f} \u{e9}".to_owned()));
+    awkward.insert("".to_owned(), Value::F64(::std::f64::NAN));
+    let mut nested = Value::Object(awkward);
+    for _ in 0..200 {
+        nested = Value::Array(vec![nested]);
+    }
+    assert!(nested.pretty().starts_with("[\n  [\n"));
+    assert!(nested.compact().starts_with("[[[["));
+}
+
+#[test]
+fn test_deep_eq() {
+    let value: Value = serde_hjson::from_str("{\n  a: 1\n  b: [\n    -2\n    {\n      c: 3.5\n      d: x\n    }\n  ]\n  e: null\n}").unwrap();
+
+    let mut inner = Map::new();
+    inner.insert("d".to_owned(), Value::String("x".to_owned()));
+    inner.insert("c".to_owned(), Value::F64(3.5));
+    let mut other = Map::new();
+    other.insert("e".to_owned(), Value::Null);
+    other.insert("b".to_owned(), Value::Array(vec![Value::I64(-2), Value::Object(inner)]));
+    other.insert("a".to_owned(), Value::U64(1));
+    let other = Value::Object(other);
+
+    assert!(value != other);
+    assert!(value.deep_eq(&other));
+    assert!(other.deep_eq(&value));
+
+    // anything else still has to match
+    let differs = |hjson: &str| !value.deep_eq(&serde_hjson::from_str(hjson).unwrap());
+    assert!(differs("{\n  a: 1\n  b: [\n    -2\n    {\n      c: 3.5\n      d: x\n    }\n  ]\n}"));
+    assert!(differs("{\n  a: 1\n  b: [\n    {\n      c: 3.5\n      d: x\n    }\n    -2\n  ]\n  e: null\n}"));
+    assert!(differs("{\n  a: \"1\"\n  b: [\n    -2\n    {\n      c: 3.5\n      d: x\n    }\n  ]\n  e: null\n}"));
+    assert!(differs("{\n  a: 1\n  b: [\n    -2\n    {\n      c: 3.5\n      d: x\n    }\n  ]\n  f: null\n}"));
+    assert!(!Value::I64(-1).deep_eq(&Value::U64(1)));
+    assert!(!Value::Null.deep_eq(&Value::Bool(false)));
+    assert!(Value::U64(u64::max_value()).deep_eq(&Value::U64(u64::max_value())));
+}
+
+#[test]
+fn test_retain() {
+    let hjson = "{\n  name: web\n  _comment: internal\n  server: {\n    port: 80\n    _secret: x\n    tls: {\n      _key: y\n    }\n  }\n  \
+                 users: [\n    {\n      _id: 1\n      login: a\n    }\n  ]\n}";
+    let mut value: Value = serde_hjson::from_str(hjson).unwrap();
+
+    // only the members of the object itself
+    let mut shallow = value.clone();
+    shallow.retain(|key, _| !key.starts_with('_'));
+    assert_eq!(shallow.find("_comment"), None);
+    assert!(shallow.find_path(&["server", "_secret"]).is_some());
+
+    let mut seen = Vec::new();
+    value.retain_recursive(|key, _| {
+        seen.push(key.to_owned());
+        !key.starts_with('_')
+    });
+    assert_eq!(value, serde_hjson::from_str::<Value>("{\n  name: web\n  server: {\n    port: 80\n    tls: {}\n  }\n  \
+                                                      users: [\n    {\n      login: a\n    }\n  ]\n}").unwrap());
+    // each member is checked once
+    assert_eq!(seen.len(), 10);
+
+    // by value
+    let mut value: Value = serde_hjson::from_str("{a: 1, b: \"x\", c: 2}").unwrap();
+    value.retain(|_, value| value.is_number());
+    assert_eq!(value.entries().map(|(key, _)| key).collect::<Vec<_>>(), vec!["a", "c"]);
+
+    // other values are left alone
+    let mut value = Value::Array(vec![Value::I64(1)]);
+    value.retain(|_, _| false);
+    assert_eq!(value, Value::Array(vec![Value::I64(1)]));
+}
+
+#[test]
+fn test_flatten_keys() {
+    let hjson = "{\n  server: {\n    host: localhost\n    ports: [\n      80\n      443\n    ]\n    tls: {}\n  }\n  debug: true\n}";
+    let value: Value = serde_hjson::from_str(hjson).unwrap();
+
+    let flat: Vec<(String, Value)> = value.flatten_keys("_");
+    assert_eq!(flat, vec![
+        ("server_host".to_owned(), Value::String("localhost".to_owned())),
+        ("server_ports_0".to_owned(), Value::F64(80.0)),
+        ("server_ports_1".to_owned(), Value::F64(443.0)),
+        ("server_tls".to_owned(), Value::Object(Map::new())),
+        ("debug".to_owned(), Value::Bool(true)),
+    ]);
+    assert_eq!(value.flatten_keys(".")[0].0, "server.host");
+
+    // values inside arrays keep their keys
+    let value: Value = serde_hjson::from_str("[{a: 1}, []]").unwrap();
+    let keys: Vec<String> = value.flatten_keys(".").into_iter().map(|(key, _)| key).collect();
+    assert_eq!(keys, vec!["0.a", "1"]);
+
+    assert_eq!(Value::U64(1).flatten_keys("."), vec![(String::new(), Value::U64(1))]);
+}
+
+#[test]
+fn test_into_array() {
+    let value: Value = serde_hjson::from_str("{\n  hosts: [\n    a\n    b\n  ]\n  port: 80\n}").unwrap();
+    let mut map = value.into_object().unwrap();
+
+    let hosts: Vec<String> = map.remove("hosts").unwrap().into_iter()
+        .map(|host| match host {
+            Value::String(s) => s,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(hosts, vec!["a", "b"]);
+
+    let port = map.remove("port").unwrap();
+    assert_eq!(port.clone().into_array(), None);
+    assert_eq!(port.clone().into_object(), None);
+    assert_eq!(port.into_iter().count(), 0);
+
+    let nested: Value = serde_hjson::from_str("[[1], {}]").unwrap();
+    let items = nested.into_array().unwrap();
+    assert_eq!(items[0].clone().into_array(), Some(vec![Value::F64(1.0)]));
+    assert_eq!(items[1].clone().into_object(), Some(Map::new()));
+}
+
+#[test]
+fn test_serialize_like_typed_data() {
+    use serde::ser::Serialize;
+    use serde_hjson::Serializer;
+    use serde_hjson::ser::HjsonFormatter;
+
+    #[derive(Serialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        aliases: Vec<&'static str>,
+        limits: Option<Vec<u32>>,
+    }
+
+    fn to_string<T: Serialize>(value: &T, formatter: HjsonFormatter) -> String {
+        let mut ser = Serializer::with_formatter(Vec::new(), formatter);
+        value.serialize(&mut ser).unwrap();
+        String::from_utf8(ser.into_inner()).unwrap()
+    }
+
+    // a `Value` goes through the same serializer methods as the data it was built from, so
+    // the formatter's options apply to it in the same way
+    let servers = vec![
+        Server { host: "example.com".to_owned(), port: 80, aliases: vec!["www", "web"], limits: None },
+        Server { host: "localhost".to_owned(), port: 8080, aliases: vec![], limits: Some(vec![1, 2]) },
+    ];
+    let value = serde_hjson::to_value(&servers);
+    assert_eq!(serde_hjson::to_string(&value).unwrap(), serde_hjson::to_string(&servers).unwrap());
+    let formatters = || vec![
+        HjsonFormatter::new().align_keys(true),
+        HjsonFormatter::new().compact_arrays_of_primitives(true),
+        HjsonFormatter::new().collapse_single_member(true).blank_line_between_members(true),
+    ];
+    for (a, b) in formatters().into_iter().zip(formatters()) {
+        assert_eq!(to_string(&value, a), to_string(&servers, b));
+    }
+}
+
+#[test]
+fn test_number_literal() {
+    // the literal of a number is kept if writing its value would change it
+    let value: Value = serde_hjson::from_str("1.50").unwrap();
+    assert!(value.is_number());
+    assert_eq!(value.as_f64(), Some(1.5));
+    assert_eq!(serde_hjson::to_string(&value).unwrap(), "1.50");
+
+    let text = "{\n  a:\n  [\n    1.50\n    2e3\n    1.5\n    -0\n  ]\n  b: 10\n}";
+    let value: Value = serde_hjson::from_str(text).unwrap();
+    assert_eq!(serde_hjson::to_string(&value).unwrap(), text);
+    assert_eq!(value.pointer("/a/2"), Some(&Value::F64(1.5)));
+    assert_eq!(value.pointer("/b").and_then(Value::as_u64), Some(10));
+    assert_eq!(value.pointer("/a/1").and_then(Value::as_u64), Some(2000));
+
+    // and survives a trip through to_value and from_value
+    let copy: Value = serde_hjson::from_value(serde_hjson::to_value(&value)).unwrap();
+    assert_eq!(copy, value);
+    assert_eq!(serde_hjson::to_string(&copy).unwrap(), text);
+
+    // typed data still gets the value
+    let numbers: Vec<f64> = serde_hjson::from_value(value.find("a").unwrap().clone()).unwrap();
+    assert_eq!(numbers, vec![1.5, 2000.0, 1.5, -0.0]);
+}